@@ -0,0 +1,305 @@
+//! Strategies for spreading requests over instances
+//!
+//! Each request (and each retry of one) goes to the available instance
+//! picked by the cluster's `Balancer`. The built in strategies send each
+//! instance traffic in proportion to its weight.
+
+use std::ascii::AsciiExt;
+use std::iter::AdditiveIterator;
+use std::num::Float;
+use std::rand;
+use std::sync::Mutex;
+use std::u64;
+use crypto::digest::Digest;
+use crypto::md5::Md5;
+use transport::{Instance, Request};
+
+/// Picks the instance each request is sent to
+pub trait Balancer: Send + Sync {
+    /// The index of the instance to use, out of `instances` (never empty)
+    fn pick(&self, instances: &[Instance]) -> usize;
+
+    /// The index of the instance to send `request` to - `pick` unless the
+    /// strategy routes by what is requested
+    fn pick_for(&self, instances: &[Instance], _request: &Request) -> usize {
+        self.pick(instances)
+    }
+
+    /// A request to `instance` has been sent
+    fn started(&self, _instance: &Instance) {}
+
+    /// A request to `instance` has been answered, or has failed
+    fn finished(&self, _instance: &Instance) {}
+}
+
+/// An instance's weight, treating 0 as 1
+fn weight(instance: &Instance) -> usize {
+    if instance.weight == 0 { 1 } else { instance.weight as usize }
+}
+
+/// The index of the instance covering `slot`, out of the sum of the weights
+fn weighted_index(instances: &[Instance], mut slot: usize) -> usize {
+    for (index, instance) in instances.iter().enumerate() {
+        if slot < weight(instance) {
+            return index;
+        }
+        slot -= weight(instance);
+    }
+    instances.len() - 1
+}
+
+/// Use each instance in turn, as many times in a row as its weight
+#[derive(Show)]
+pub struct RoundRobin {
+    next: Mutex<usize>
+}
+
+impl RoundRobin {
+    pub fn new() -> RoundRobin {
+        RoundRobin { next: Mutex::new(0) }
+    }
+}
+
+impl Balancer for RoundRobin {
+    fn pick(&self, instances: &[Instance]) -> usize {
+        let total = instances.iter().map(weight).sum();
+        let mut next = self.next.lock().unwrap();
+        let slot = *next % total;
+        *next = slot + 1;
+        weighted_index(instances, slot)
+    }
+}
+
+/// Use an instance chosen at random
+#[derive(Show, Clone, Copy)]
+pub struct Random;
+
+impl Balancer for Random {
+    fn pick(&self, instances: &[Instance]) -> usize {
+        let total = instances.iter().map(weight).sum();
+        weighted_index(instances, rand::random::<usize>() % total)
+    }
+}
+
+/// Use the instance with the fewest requests in flight for its weight, the
+/// first such in a tie
+#[derive(Show)]
+pub struct LeastPending {
+    pending: Mutex<Vec<(Instance, usize)>>
+}
+
+impl LeastPending {
+    pub fn new() -> LeastPending {
+        LeastPending { pending: Mutex::new(vec!()) }
+    }
+}
+
+impl Balancer for LeastPending {
+    fn pick(&self, instances: &[Instance]) -> usize {
+        let pending = self.pending.lock().unwrap();
+        let count = |instance: &Instance| {
+            pending.iter().find(|&&(ref i, _)| i == instance).map_or(0, |&(_, n)| n)
+        };
+        let mut best = 0;
+        for (index, instance) in instances.iter().enumerate() {
+            let best_instance = &instances[best];
+            if count(instance) * weight(best_instance) <
+                count(best_instance) * weight(instance) {
+                best = index;
+            }
+        }
+        best
+    }
+
+    fn started(&self, instance: &Instance) {
+        let mut pending = self.pending.lock().unwrap();
+        for &mut (ref i, ref mut n) in pending.iter_mut() {
+            if i == instance {
+                *n += 1;
+                return;
+            }
+        }
+        pending.push((instance.clone(), 1));
+    }
+
+    fn finished(&self, instance: &Instance) {
+        let mut pending = self.pending.lock().unwrap();
+        pending.retain(|&(ref i, n)| !(i == instance && n <= 1));
+        for &mut (ref i, ref mut n) in pending.iter_mut() {
+            if i == instance {
+                *n -= 1;
+            }
+        }
+    }
+}
+
+/// What `ConsistentHash` routes by
+#[derive(Show, Clone, Copy, PartialEq)]
+pub enum HashKey {
+    /// The database, so each database lives on one instance
+    Database,
+    /// The database and measurement, so each measurement lives on one
+    /// instance
+    Measurement
+}
+
+/// Send every request for the same key to the same instance, for manually
+/// sharded deployments
+///
+/// Uses rendezvous hashing, so adding or removing an instance only moves
+/// the keys of that instance, and clients agree on where each key lives. The
+/// measurement of a write is that of its first line, so writes should hold
+/// one measurement each; that of a query is the first after `FROM`.
+#[derive(Show, Clone, Copy)]
+pub struct ConsistentHash {
+    pub key: HashKey
+}
+
+impl ConsistentHash {
+    pub fn new(key: HashKey) -> ConsistentHash {
+        ConsistentHash { key: key }
+    }
+
+    /// The key `request` is routed by
+    fn key(&self, request: &Request) -> String {
+        let param = |name: &str| {
+            request.query.iter().find(|&&(ref n, _)| n.as_slice() == name)
+                .map(|&(_, ref value)| value.as_slice())
+        };
+        // writes to 2.x name a bucket rather than a database
+        let database = param("db").or_else(|| param("bucket")).unwrap_or("");
+        if self.key == HashKey::Database {
+            return String::from_str(database);
+        }
+        let measurement = match param("q") {
+            Some(query) => query_measurement(query),
+            None => request.body.as_ref().map_or("", |body| line_measurement(body.as_slice()))
+        };
+        format!("{}\n{}", database, measurement)
+    }
+}
+
+impl Balancer for ConsistentHash {
+    fn pick(&self, instances: &[Instance]) -> usize {
+        rendezvous(instances, "")
+    }
+
+    fn pick_for(&self, instances: &[Instance], request: &Request) -> usize {
+        rendezvous(instances, self.key(request).as_slice())
+    }
+}
+
+/// The measurement of the first line of line protocol
+fn line_measurement(body: &str) -> &str {
+    let line = body.trim_left().lines().next().unwrap_or("");
+    let mut escaped = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            '\\' if !escaped => escaped = true,
+            ',' | ' ' if !escaped => return &line[..i],
+            _ => escaped = false
+        }
+    }
+    line
+}
+
+/// The first measurement a query selects from, without quotes or its
+/// database and retention policy
+fn query_measurement(query: &str) -> &str {
+    let upper = query.to_ascii_uppercase();
+    let start = match upper.as_slice().find("FROM ") {
+        Some(i) => i + 5,
+        None => return ""
+    };
+    let rest = query[start..].trim_left();
+    let end = rest.find(|c: char| c.is_whitespace() || c == ';' || c == ',')
+        .unwrap_or(rest.len());
+    let name = &rest[..end];
+    name.split('.').last().unwrap_or(name).trim_matches('"')
+}
+
+/// The instance `key` lives on: the one scoring highest for it, with
+/// scores scaled by weight
+fn rendezvous(instances: &[Instance], key: &str) -> usize {
+    // every score is positive
+    let mut best = (0, 0.0);
+    for (index, instance) in instances.iter().enumerate() {
+        let mut md5 = Md5::new();
+        md5.input_str(format!("{}\n{}://{}:{}", key, instance.scheme, instance.host,
+                              instance.port).as_slice());
+        let mut digest = [0u8; 16];
+        md5.result(&mut digest);
+        let hash = digest[..8].iter().fold(0u64, |hash, &byte| (hash << 8) | byte as u64);
+        // uniform in (0, 1), so the log is negative
+        let unit = (hash as f64 + 1.0) / (u64::MAX as f64 + 2.0);
+        let score = -(weight(instance) as f64) / unit.ln();
+        if score > best.1 {
+            best = (index, score);
+        }
+    }
+    best.0
+}
+
+#[test]
+fn balances_over_every_instance() {
+    use std::default::Default;
+    let first: Instance = Default::default();
+    let second = Instance { port: 8087, ..Default::default() };
+    let instances = [first.clone(), second.clone()];
+
+    let round_robin = RoundRobin::new();
+    let picks: Vec<usize> = (0..4).map(|_| round_robin.pick(&instances)).collect();
+    assert_eq!(picks, vec!(0, 1, 0, 1));
+
+    let least_pending = LeastPending::new();
+    least_pending.started(&first);
+    assert_eq!(least_pending.pick(&instances), 1);
+    least_pending.finished(&first);
+    assert_eq!(least_pending.pick(&instances), 0);
+}
+
+#[test]
+fn balances_by_weight() {
+    use std::default::Default;
+    let heavy = Instance { weight: 3, ..Default::default() };
+    let light = Instance { port: 8087, ..Default::default() };
+    let instances = [heavy.clone(), light.clone()];
+
+    let round_robin = RoundRobin::new();
+    let picks: Vec<usize> = (0..8).map(|_| round_robin.pick(&instances)).collect();
+    assert_eq!(picks, vec!(0, 0, 0, 1, 0, 0, 0, 1));
+
+    let least_pending = LeastPending::new();
+    least_pending.started(&heavy);
+    least_pending.started(&heavy);
+    least_pending.started(&light);
+    assert_eq!(least_pending.pick(&instances), 0);
+}
+
+#[test]
+fn hashes_series_to_the_same_instance() {
+    use std::default::Default;
+    use hyper::method::Method;
+    let instances: Vec<Instance> = (0..4u16)
+        .map(|i| Instance { port: 8086 + i, ..Default::default() })
+        .collect();
+    let balancer = ConsistentHash::new(HashKey::Measurement);
+    let write = |body: &str| {
+        Request::new(Method::Post, vec!(String::from_str("write")))
+            .param("db", String::from_str("metrics"))
+            .body(String::from_str(body))
+    };
+    let query = Request::new(Method::Get, vec!(String::from_str("query")))
+        .param("q", String::from_str("SELECT * FROM \"autogen\".\"cpu\" WHERE time > now() - 1h"))
+        .param("db", String::from_str("metrics"));
+    let cpu = balancer.pick_for(instances.as_slice(), &write("cpu,host=a value=1 1"));
+    assert_eq!(balancer.pick_for(instances.as_slice(), &write("cpu,host=b value=2 2")), cpu);
+    assert_eq!(balancer.pick_for(instances.as_slice(), &query), cpu);
+    // only the keys of a removed instance move
+    let remaining: Vec<Instance> = instances.iter().enumerate()
+        .filter(|&(i, _)| i != (cpu + 1) % 4)
+        .map(|(_, instance)| instance.clone())
+        .collect();
+    let moved = balancer.pick_for(remaining.as_slice(), &write("cpu value=3 3"));
+    assert_eq!(remaining[moved], instances[cpu]);
+}
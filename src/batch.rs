@@ -0,0 +1,464 @@
+//! Buffered writing of points in batches
+//!
+//! A `BatchWriter` hands points to a background thread, which serializes
+//! them and writes them to the database once enough have accumulated, or
+//! once the flush interval has passed. Optionally, batches are journalled to
+//! disk while no instances are available and replayed once one is.
+//!
+//! Only one batch is in flight at a time, points wait in a bounded queue in
+//! the meantime.
+
+use time;
+use std::default::Default;
+use std::io;
+use std::mem;
+use std::sync::{Arc, Condvar, Mutex, RwLock};
+use std::thread::Thread;
+use std::time::duration::Duration;
+use futures::Future;
+use client::{Influx, Database, DataPoint, FieldValue, UnsignedFallback, WriteOptions};
+use error::InfluxError;
+use lineproto;
+use point;
+use queue::{BoundedQueue, OverflowPolicy};
+use spool::Spool;
+
+/// Controls when a `BatchWriter` flushes its buffer, and how batches are
+/// written
+#[derive(Show, Clone)]
+pub struct BatchConfig {
+    /// Flush once this many points are buffered
+    pub max_points: usize,
+    /// Flush once the serialized buffer reaches this many bytes
+    pub max_bytes: usize,
+    /// Flush buffered points at least this often
+    pub interval: Duration,
+    /// Maximum number of points waiting to be batched, at least 1
+    pub queue_size: usize,
+    /// What to do with new points when the queue is full
+    pub overflow: OverflowPolicy,
+    /// Options each batch is written with
+    pub options: WriteOptions,
+    /// File to journal batches to while no instances are available, or when
+    /// writing them fails in a way a retry may fix - default None (batches
+    /// are attempted, and fail, regardless)
+    ///
+    /// Batches are replayed with `options`, so a spool should always be
+    /// reused with the same precision
+    pub spool: Option<Path>,
+    /// How long dropping the writer waits for buffered points to be written
+    pub shutdown_timeout: Duration
+}
+
+impl Default for BatchConfig {
+    fn default() -> BatchConfig {
+        BatchConfig {
+            max_points: 5000,
+            max_bytes: 1024 * 1024,
+            interval: Duration::seconds(1),
+            queue_size: 50000,
+            overflow: OverflowPolicy::Block,
+            options: Default::default(),
+            spool: None,
+            shutdown_timeout: Duration::seconds(30)
+        }
+    }
+}
+
+/// Messages from the writer handle (and ticker) to the background thread
+enum Message {
+    Point(String, DataPoint),
+    Tick,
+    Flush,
+    Stop
+}
+
+/// Points serialized so far for the next batch
+struct Batch {
+    lines: Vec<String>,
+    /// The points of `lines`, only kept when deduplicating, to merge those
+    /// of the same series and time
+    points: Vec<(String, DataPoint)>,
+    bytes: usize
+}
+
+impl Batch {
+    fn new() -> Batch {
+        Batch {
+            lines: vec!(),
+            points: vec!(),
+            bytes: 0
+        }
+    }
+
+    /// Serialize a point into the batch
+    fn push(&mut self, series: &str, point: &DataPoint, options: &WriteOptions) {
+        let line = lineproto::serialize_point(series, point, options.precision);
+        if options.dedup {
+            self.points.push((String::from_str(series), point.clone()));
+        }
+        self.bytes += line.len() + 1;
+        self.lines.push(line);
+    }
+
+    /// Take the batch as a line protocol body, leaving it empty
+    fn take(&mut self, options: &WriteOptions) -> String {
+        let mut lines = mem::replace(&mut self.lines, vec!());
+        let points = mem::replace(&mut self.points, vec!());
+        self.bytes = 0;
+        if !points.is_empty() {
+            let points = point::dedup(points, |&(ref series, ref p)| {
+                point::series_key(series.as_slice(), p)
+            }, |&mut (_, ref mut p), (_, later)| point::merge_fields(p, later));
+            lines = points.iter().map(|&(ref series, ref p)| {
+                lineproto::serialize_point(series.as_slice(), p, options.precision)
+            }).collect();
+        }
+        let mut body = String::new();
+        for line in lines.iter() {
+            body.push_str(line.as_slice());
+            body.push('\n');
+        }
+        body
+    }
+}
+
+/// The queue of a writer, and whether its background thread has finished
+struct Shared {
+    queue: BoundedQueue<Message>,
+    stopped: Mutex<bool>,
+    /// Signalled once the background thread has finished
+    stopped_signal: Condvar,
+    /// Called with each failed write, and each point dropped
+    error_handlers: RwLock<Vec<Box<Fn(&InfluxError) + Send + Sync>>>
+}
+
+impl Shared {
+    /// Tell the error handlers about a failure
+    fn report(&self, error: &InfluxError) {
+        for handler in self.error_handlers.read().unwrap().iter() {
+            handler(error);
+        }
+    }
+
+    /// Ask the background thread to write what is buffered and finish, and
+    /// wait up to `timeout` for it to - true if it did
+    fn stop(&self, timeout: Duration) -> bool {
+        let _ = self.queue.push_unbounded(Message::Stop);
+        let deadline = time::precise_time_ns() + timeout.num_nanoseconds().unwrap_or(0) as u64;
+        let mut stopped = self.stopped.lock().unwrap();
+        while !*stopped {
+            let now = time::precise_time_ns();
+            if now >= deadline {
+                return false;
+            }
+            let wait = Duration::nanoseconds((deadline - now) as i64);
+            stopped = self.stopped_signal.wait_timeout(stopped, wait).unwrap().0;
+        }
+        true
+    }
+}
+
+/// Buffers points and writes them in batches from a background thread
+///
+/// Any points still buffered are written when the writer is dropped, or when
+/// the client is shut down, after which the writer refuses new points
+pub struct BatchWriter {
+    shared: Arc<Shared>,
+    shutdown_timeout: Duration,
+    /// The client, to remove the shutdown hook from on drop
+    influx: Influx,
+    hook: usize
+}
+
+impl BatchWriter {
+    /// Start the background threads for a writer on the given database
+    pub fn new(influx: Influx, database: &str, config: BatchConfig) -> BatchWriter {
+        let shared = Arc::new(Shared {
+            queue: BoundedQueue::new(config.queue_size, config.overflow),
+            stopped: Mutex::new(false),
+            stopped_signal: Condvar::new(),
+            error_handlers: RwLock::new(vec!())
+        });
+        let ticker = shared.clone();
+        let receiver = shared.clone();
+        let interval = config.interval;
+        let shutdown_timeout = config.shutdown_timeout;
+        let database = String::from_str(database);
+        let writer = shared.downgrade();
+        let hook = influx.on_shutdown(Box::new(move |timeout| match writer.upgrade() {
+            Some(writer) => writer.stop(timeout),
+            None => true
+        }));
+        let client = influx.clone();
+        Thread::spawn(move || {
+            loop {
+                io::timer::sleep(interval);
+                if ticker.queue.push_unbounded(Message::Tick).is_err() {
+                    break;
+                }
+            }
+        });
+        Thread::spawn(move || {
+            BatchWriter::run(influx, database, config, &*receiver);
+            *receiver.stopped.lock().unwrap() = true;
+            receiver.stopped_signal.notify_all();
+        });
+        BatchWriter {
+            shared: shared,
+            shutdown_timeout: shutdown_timeout,
+            influx: client,
+            hook: hook
+        }
+    }
+
+    /// Queue a point for the given series
+    ///
+    /// Fails if the queue is full and the overflow policy is
+    /// `OverflowPolicy::Error`, or the client has been shut down
+    pub fn write_point(&self, series: &str, point: DataPoint) -> Result<(), InfluxError> {
+        self.shared.queue.push(Message::Point(String::from_str(series), point)).map_err(|_| {
+            if self.shared.queue.is_closed() {
+                InfluxError::Request(String::from_str("Client shut down"))
+            } else {
+                InfluxError::Request(String::from_str("Write queue is full"))
+            }
+        })
+    }
+
+    /// Queue a batch of points for the given series
+    pub fn write_points(&self, series: &str,
+                        points: Vec<DataPoint>) -> Result<(), InfluxError> {
+        for point in points.into_iter() {
+            try!(self.write_point(series, point));
+        }
+        Ok(())
+    }
+
+    /// Call `handler` with the error of each batch that fails to be written,
+    /// and of each point dropped because the server can't take it, e.g. an
+    /// unsigned field too large for the fallback
+    pub fn on_error(&self, handler: Box<Fn(&InfluxError) + Send + Sync>) {
+        self.shared.error_handlers.write().unwrap().push(handler);
+    }
+
+    /// Number of points waiting to be batched
+    pub fn queue_len(&self) -> usize {
+        self.shared.queue.len()
+    }
+
+    /// Ask the background thread to write whatever is buffered now
+    pub fn flush(&self) {
+        let _ = self.shared.queue.push_unbounded(Message::Flush);
+    }
+
+    /// Write whatever is buffered and stop, waiting up to `timeout` for the
+    /// writes to finish - true if they did
+    pub fn close(self, timeout: Duration) -> bool {
+        self.shared.stop(timeout)
+    }
+
+    /// Body of the background thread
+    fn run(influx: Influx, database: String, config: BatchConfig, shared: &Shared) {
+        let db = influx.database(database.as_slice());
+        let spool = config.spool.clone().map(Spool::new);
+        let mut batch = Batch::new();
+        let mut last_flush = time::get_time();
+        let mut conversion = None;
+
+        while let Some(message) = shared.queue.pop() {
+            let flush = match message {
+                Message::Point(series, mut point) => {
+                    match BatchWriter::prepare(&influx, &mut conversion, &mut point) {
+                        Ok(()) => batch.push(series.as_slice(), &point, &config.options),
+                        // the server can't take the point
+                        Err(e) => shared.report(&e)
+                    }
+                    batch.lines.len() >= config.max_points || batch.bytes >= config.max_bytes
+                },
+                Message::Tick => time::get_time() - last_flush >= config.interval,
+                Message::Flush => true,
+                Message::Stop => {
+                    shared.queue.close();
+                    break;
+                }
+            };
+            if flush {
+                BatchWriter::send(&influx, &db, &config, &spool, shared,
+                                  batch.take(&config.options));
+                last_flush = time::get_time();
+            }
+        }
+        BatchWriter::send(&influx, &db, &config, &spool, shared, batch.take(&config.options));
+    }
+
+    /// Convert the unsigned fields of a point if the server doesn't support
+    /// them
+    ///
+    /// Whether it does is found on the first point with unsigned fields, and
+    /// kept in `conversion` once the server's version is known.
+    fn prepare(influx: &Influx, conversion: &mut Option<Option<UnsignedFallback>>,
+               point: &mut DataPoint) -> Result<(), InfluxError> {
+        let unsigned = point.fields.iter().any(|&(_, ref value)| match *value {
+            FieldValue::UInteger(_) => true,
+            _ => false
+        });
+        if !unsigned {
+            return Ok(());
+        }
+        let fallback = match *conversion {
+            Some(fallback) => fallback,
+            None => {
+                let supported = influx.supports_unsigned().wait().unwrap_or(false);
+                let fallback = if supported { None } else { Some(influx.unsigned_fallback()) };
+                if influx.server_version().is_some() {
+                    *conversion = Some(fallback);
+                }
+                fallback
+            }
+        };
+        match fallback {
+            Some(fallback) => fallback.convert_point(point),
+            None => Ok(())
+        }
+    }
+
+    /// Send a batch, going via the spool if one is configured
+    ///
+    /// Waits for the write to finish, so only one batch is in flight at a
+    /// time and a slow server backs points up into the (bounded) queue.
+    ///
+    /// With a spool, batches are journalled while no instances are available,
+    /// or if writing them fails in a way a retry may fix, and anything
+    /// journalled is sent ahead of new batches once an instance is available.
+    /// Journalled lines are only removed once written, or once writing them
+    /// fails in a way a retry won't fix (e.g. a malformed line), when the
+    /// failure is reported and the chunk dropped so it can't hold up the rest.
+    fn send(influx: &Influx, db: &Database, config: &BatchConfig,
+            spool: &Option<Spool>, shared: &Shared, batch: String) {
+        if let Some(ref spool) = *spool {
+            if influx.get_instances_available().is_empty() {
+                if !batch.is_empty() && spool.append(batch.as_slice()).is_ok() {
+                    return;
+                }
+            } else if !spool.is_empty() {
+                let replayed = spool.replay(config.max_bytes, |chunk| {
+                    match db.write_lines(chunk, config.options.clone()).wait() {
+                        Err(ref e) if !e.is_retriable() => {
+                            shared.report(e);
+                            Ok(())
+                        },
+                        written => written
+                    }
+                });
+                if let Err(e) = replayed {
+                    shared.report(&e);
+                    // behind what is still journalled, to keep the order
+                    if !batch.is_empty() && spool.append(batch.as_slice()).is_ok() {
+                        return;
+                    }
+                }
+            }
+        }
+        if batch.is_empty() {
+            return;
+        }
+        if let Err(e) = db.write_lines(batch.clone(), config.options.clone()).wait() {
+            shared.report(&e);
+            // e.g. the instance went away mid-write, so keep it for later
+            if let Some(ref spool) = *spool {
+                if e.is_retriable() {
+                    let _ = spool.append(batch.as_slice());
+                }
+            }
+        }
+    }
+}
+
+impl Drop for BatchWriter {
+    fn drop(&mut self) {
+        self.influx.remove_shutdown_hook(self.hook);
+        self.shared.stop(self.shutdown_timeout);
+    }
+}
+
+#[test]
+fn reports_dropped_points() {
+    use std::u64;
+    use client::Auth;
+    use transport::{Canned, reply_as, stub_client};
+    let influx = stub_client(Auth::None, Canned::new(|_| reply_as("1.3.0", 204, "")));
+    let errors = Arc::new(Mutex::new(vec!()));
+    let seen = errors.clone();
+    let writer = BatchWriter::new(influx, "telegraf", Default::default());
+    writer.on_error(Box::new(move |error: &InfluxError| seen.lock().unwrap().push(error.clone())));
+    let mut point = DataPoint::new(time::Timespec::new(1, 0));
+    point.fields.push((String::from_str("free"), FieldValue::UInteger(u64::MAX)));
+    writer.write_point("mem", point).unwrap();
+    assert!(writer.close(Duration::seconds(5)));
+    assert_eq!(errors.lock().unwrap().len(), 1);
+}
+
+#[test]
+fn flushes_on_shutdown_and_drop() {
+    use client::Auth;
+    use transport::{Canned, stub_client};
+    let transport = Canned::status(204);
+    let sent = transport.sent();
+    let written = move || {
+        sent.lock().unwrap().iter().filter(|sent| sent.request.body.is_some()).count()
+    };
+    let influx = stub_client(Auth::None, transport);
+    let config = BatchConfig { interval: Duration::minutes(1), ..Default::default() };
+    let point = || {
+        let mut point = DataPoint::new(time::Timespec::new(1, 0));
+        point.fields.push((String::from_str("free"), FieldValue::Integer(1)));
+        point
+    };
+
+    let writer = BatchWriter::new(influx.clone(), "telegraf", config.clone());
+    writer.write_point("mem", point()).unwrap();
+    drop(writer);
+    assert_eq!(written(), 1);
+
+    let writer = BatchWriter::new(influx.clone(), "telegraf", config);
+    writer.write_point("mem", point()).unwrap();
+    assert!(influx.shutdown(Duration::seconds(5)));
+    assert_eq!(written(), 2);
+    assert_eq!(writer.write_point("mem", point()),
+               Err(InfluxError::Request(String::from_str("Client shut down"))));
+}
+
+#[test]
+fn drops_journalled_lines_the_server_rejects() {
+    use std::io::TempDir;
+    use client::Auth;
+    use transport::{Canned, reply, stub_client};
+    let dir = TempDir::new("influx-batch").unwrap();
+    let path = dir.path().join("spool.lp");
+    // journalled while the cluster was down
+    Spool::new(path.clone()).append("cpu value= 1").unwrap();
+    let transport = Canned::new(|sent| match sent.request.body {
+        Some(ref body) if body.as_slice().contains("value= ") => {
+            reply(400, r#"{"error":"unable to parse 'cpu value= 1': missing field value"}"#)
+        }
+        _ => reply(204, "")
+    });
+    let sent = transport.sent();
+    let influx = stub_client(Auth::None, transport);
+    let errors = Arc::new(Mutex::new(0));
+    let seen = errors.clone();
+    let config = BatchConfig { spool: Some(path.clone()), ..Default::default() };
+    let writer = BatchWriter::new(influx, "telegraf", config);
+    writer.on_error(Box::new(move |_: &InfluxError| *seen.lock().unwrap() += 1));
+    let mut point = DataPoint::new(time::Timespec::new(1, 0));
+    point.fields.push((String::from_str("free"), FieldValue::Integer(1)));
+    writer.write_point("mem", point).unwrap();
+    assert!(writer.close(Duration::seconds(5)));
+    assert_eq!(*errors.lock().unwrap(), 1);
+    let written = sent.lock().unwrap().iter().filter(|sent| {
+        sent.request.body.as_ref().map_or(false, |body| !body.as_slice().contains("value= "))
+    }).count();
+    assert_eq!(written, 1);
+    assert!(Spool::new(path).is_empty());
+}
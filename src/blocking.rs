@@ -0,0 +1,414 @@
+//! A synchronous facade over the client (feature `blocking`)
+//!
+//! Every method sends the same request as its namesake on `client::Influx`
+//! or `client::Database`, then waits for the result, so tools and tests can
+//! use `try!` directly on what comes back.
+
+use futures::Future;
+use url::Host;
+use client;
+use client::{WriteOptions, DataPoint, ShardSpace};
+use continuous::{ContinuousQuery, Resample};
+use explain::{QueryPlan, QueryAnalysis};
+use flux::FluxTable;
+use metrics::Metrics;
+use point::Point;
+use error::InfluxError;
+use query::{QueryResult, QueryOptions, RunningQuery, StatementResult, Value};
+use rustc_serialize::Decodable;
+use retention::RetentionPolicy;
+use schema::FieldKey;
+use shards::{Shard, ShardGroup};
+use stats::{Diagnostics, Statistics};
+use subscriptions::Subscription;
+use transport::Scheme;
+use users::{User, Privilege};
+
+/// A blocking influx client
+///
+/// Cloning is cheap, clones share the same cluster
+#[derive(Clone)]
+pub struct Influx {
+    client: client::Influx
+}
+
+impl Influx {
+    pub fn new(scheme: Scheme, host: Host, port: u16,
+               username: &str, password: &str) -> Influx {
+        Influx::from_client(client::Influx::new(scheme, host, port, username, password))
+    }
+
+    /// Wrap an existing client, e.g. one already configured
+    pub fn from_client(client: client::Influx) -> Influx {
+        Influx { client: client }
+    }
+
+    /// The underlying client, for configuration or non-blocking requests
+    pub fn client(&mut self) -> &mut client::Influx {
+        &mut self.client
+    }
+
+    /// List the queries running on the server - requires admin privileges
+    pub fn show_queries(&self) -> Result<Vec<RunningQuery>, InfluxError> {
+        self.client.show_queries().wait()
+    }
+
+    /// Kill a running query - requires admin privileges
+    pub fn kill_query(&self, id: u64, host: Option<&str>) -> Result<(), InfluxError> {
+        self.client.kill_query(id, host).wait()
+    }
+
+    /// List the users, with their privileges - requires admin privileges
+    pub fn get_users(&self) -> Result<Vec<User>, InfluxError> {
+        self.client.get_users().wait()
+    }
+
+    /// Get a user by name, with its privileges - requires admin privileges
+    pub fn get_user(&self, name: &str) -> Result<Option<User>, InfluxError> {
+        self.client.get_user(name).wait()
+    }
+
+    /// Create a user - requires admin privileges
+    pub fn create_user(&self, name: &str, password: &str, admin: bool) -> Result<(), InfluxError> {
+        self.client.create_user(name, password, admin).wait()
+    }
+
+    /// Grant or revoke a user's admin privileges - requires admin privileges
+    pub fn update_user(&self, name: &str, admin: bool) -> Result<(), InfluxError> {
+        self.client.update_user(name, admin).wait()
+    }
+
+    /// Drop a user - requires admin privileges
+    pub fn delete_user(&self, name: &str) -> Result<(), InfluxError> {
+        self.client.delete_user(name).wait()
+    }
+
+    /// Change a user's password
+    pub fn set_password(&self, name: &str, password: &str) -> Result<(), InfluxError> {
+        self.client.set_password(name, password).wait()
+    }
+
+    /// Grant a user a privilege on a database - requires admin privileges
+    pub fn grant(&self, privilege: Privilege, db: &str, user: &str) -> Result<(), InfluxError> {
+        self.client.grant(privilege, db, user).wait()
+    }
+
+    /// Revoke a privilege on a database from a user - requires admin privileges
+    pub fn revoke(&self, privilege: Privilege, db: &str, user: &str) -> Result<(), InfluxError> {
+        self.client.revoke(privilege, db, user).wait()
+    }
+
+    /// Create a new database - requires admin privileges
+    pub fn create_database(&self, name: &str) -> Result<(), InfluxError> {
+        self.client.create_database(name).wait()
+    }
+
+    /// Create a new database whose default retention policy is `policy` -
+    /// requires admin privileges
+    pub fn create_database_with_rp(&self, name: &str,
+                                   policy: &RetentionPolicy) -> Result<(), InfluxError> {
+        self.client.create_database_with_rp(name, policy).wait()
+    }
+
+    /// Create a database unless it already exists, returning whether it was
+    /// created - requires admin privileges
+    pub fn ensure_database(&self, name: &str) -> Result<bool, InfluxError> {
+        self.client.ensure_database(name).wait()
+    }
+
+    /// Drop a database, and all its data - requires admin privileges
+    pub fn delete_database(&self, name: &str) -> Result<(), InfluxError> {
+        self.client.delete_database(name).wait()
+    }
+
+    /// List the databases the user can see
+    pub fn get_database_names(&self) -> Result<Vec<String>, InfluxError> {
+        self.client.get_database_names().wait()
+    }
+
+    /// Show the server's build, runtime, network and system details -
+    /// requires admin privileges
+    pub fn diagnostics(&self) -> Result<Diagnostics, InfluxError> {
+        self.client.diagnostics().wait()
+    }
+
+    /// Show the internal statistics of each module of the server - requires
+    /// admin privileges
+    pub fn stats(&self) -> Result<Vec<Statistics>, InfluxError> {
+        self.client.stats().wait()
+    }
+
+    /// List the subscriptions of every database - requires admin privileges
+    pub fn show_subscriptions(&self) -> Result<Vec<Subscription>, InfluxError> {
+        self.client.show_subscriptions().wait()
+    }
+
+    /// Create a subscription, e.g. for Kapacitor - requires admin privileges
+    pub fn create_subscription(&self, subscription: &Subscription) -> Result<(), InfluxError> {
+        self.client.create_subscription(subscription).wait()
+    }
+
+    /// Drop a subscription to a retention policy - requires admin privileges
+    pub fn drop_subscription(&self, name: &str, db: &str,
+                             retention_policy: &str) -> Result<(), InfluxError> {
+        self.client.drop_subscription(name, db, retention_policy).wait()
+    }
+
+    /// List the shards of every database - requires admin privileges
+    pub fn show_shards(&self) -> Result<Vec<Shard>, InfluxError> {
+        self.client.show_shards().wait()
+    }
+
+    /// List the shard groups of every database - requires admin privileges
+    pub fn show_shard_groups(&self) -> Result<Vec<ShardGroup>, InfluxError> {
+        self.client.show_shard_groups().wait()
+    }
+
+    /// Drop a shard, and the data in it - requires admin privileges
+    pub fn drop_shard(&self, id: u64) -> Result<(), InfluxError> {
+        self.client.drop_shard(id).wait()
+    }
+
+    /// Get database
+    pub fn database(&self, name: &str) -> Database {
+        Database { database: self.client.database(name) }
+    }
+
+    /// A snapshot of the counters and gauges of the requests sent
+    pub fn metrics(&self) -> Metrics {
+        self.client.metrics()
+    }
+
+    /// The server version, as reported by the most recent response
+    pub fn server_version(&self) -> Option<String> {
+        self.client.server_version()
+    }
+}
+
+/// A database of a blocking client
+#[derive(Clone)]
+pub struct Database<'a> {
+    database: client::Database<'a>
+}
+
+impl<'a> Database<'a> {
+
+    /// Get a handle on this database whose writes go to the given retention
+    /// policy
+    pub fn retention_policy(&self, name: &str) -> Database<'a> {
+        Database { database: self.database.retention_policy(name) }
+    }
+
+    /// List the measurements in the database
+    pub fn show_measurements(&self) -> Result<Vec<String>, InfluxError> {
+        self.database.show_measurements().wait()
+    }
+
+    /// List the keys of the series in the database
+    pub fn get_series_names(&self) -> Result<Vec<String>, InfluxError> {
+        self.database.get_series_names().wait()
+    }
+
+    /// Drop every series of a measurement, and the data in them
+    pub fn drop_series(&self, measurement: &str) -> Result<(), InfluxError> {
+        self.database.drop_series(measurement).wait()
+    }
+
+    /// List the tag keys of each measurement, or only of `measurement`
+    pub fn show_tag_keys(&self, measurement: Option<&str>)
+                         -> Result<Vec<(String, Vec<String>)>, InfluxError> {
+        self.database.show_tag_keys(measurement).wait()
+    }
+
+    /// List the values of the tag `key` of each measurement, or only of
+    /// `measurement`
+    pub fn show_tag_values(&self, measurement: Option<&str>, key: &str)
+                           -> Result<Vec<(String, Vec<String>)>, InfluxError> {
+        self.database.show_tag_values(measurement, key).wait()
+    }
+
+    /// List the fields, with their types, of each measurement, or only of
+    /// `measurement`
+    pub fn show_field_keys(&self, measurement: Option<&str>)
+                           -> Result<Vec<(String, Vec<FieldKey>)>, InfluxError> {
+        self.database.show_field_keys(measurement).wait()
+    }
+
+    /// Show how the server would run a query, without running it
+    pub fn explain(&self, query: &str) -> Result<QueryPlan, InfluxError> {
+        self.database.explain(query).wait()
+    }
+
+    /// Run a query, returning how the server ran it and what it cost
+    pub fn explain_analyze(&self, query: &str) -> Result<QueryAnalysis, InfluxError> {
+        self.database.explain_analyze(query).wait()
+    }
+
+    /// Write a single point to the given series
+    pub fn write_point(&self, series: &str, point: DataPoint,
+                       options: WriteOptions) -> Result<(), InfluxError> {
+        self.database.write_point(series, point, options).wait()
+    }
+
+    /// Write a batch of points to the given series
+    pub fn write_points(&self, series: &str, points: Vec<DataPoint>,
+                        options: WriteOptions) -> Result<(), InfluxError> {
+        self.database.write_points(series, points, options).wait()
+    }
+
+    /// Write batches of points to several series in a single request
+    pub fn write_series(&self, series: Vec<(String, Vec<DataPoint>)>,
+                        options: WriteOptions) -> Result<(), InfluxError> {
+        self.database.write_series(series, options).wait()
+    }
+
+    /// Write points of any measurements in a single request
+    pub fn write(&self, points: &[Point], options: WriteOptions) -> Result<(), InfluxError> {
+        self.database.write(points, options).wait()
+    }
+
+    /// Post a body of already serialized line protocol to the write endpoint
+    pub fn write_lines(&self, body: String, options: WriteOptions) -> Result<(), InfluxError> {
+        self.database.write_lines(body, options).wait()
+    }
+
+    /// Query the database
+    pub fn query(&self, query: &str) -> Result<QueryResult, InfluxError> {
+        self.database.query(query).wait()
+    }
+
+    /// Query the database with the given options
+    pub fn query_opts(&self, query: &str,
+                      options: QueryOptions) -> Result<QueryResult, InfluxError> {
+        self.database.query_opts(query, options).wait()
+    }
+
+    /// Run several statements in one request
+    pub fn query_multi(&self, statements: &[&str])
+                       -> Result<Vec<StatementResult>, InfluxError> {
+        self.database.query_multi(statements).wait()
+    }
+
+    /// Query the database with `params` bound on the server
+    pub fn query_params(&self, query: &str,
+                        params: Vec<(String, Value)>) -> Result<QueryResult, InfluxError> {
+        self.database.query_params(query, params).wait()
+    }
+
+    /// Run a read query against every available instance, merging the
+    /// results
+    pub fn query_federated(&self, query: &str) -> Result<QueryResult, InfluxError> {
+        self.database.query_federated(query).wait()
+    }
+
+    /// Query the database, decoding each row of the results into a `T`
+    pub fn query_as<T>(&self, query: &str) -> Result<Vec<T>, InfluxError>
+        where T: Decodable + Send + 'static
+    {
+        self.database.query_as(query).wait()
+    }
+
+    /// Run a Flux script against the `/api/v2/query` endpoint
+    pub fn flux_query(&self, script: &str) -> Result<Vec<FluxTable>, InfluxError> {
+        self.database.flux_query(script).wait()
+    }
+
+    /// List the database's continuous queries - requires db admin privileges
+    pub fn get_continuous_queries(&self) -> Result<Vec<ContinuousQuery>, InfluxError> {
+        self.database.get_continuous_queries().wait()
+    }
+
+    /// Create a continuous query on the database - requires db admin
+    /// privileges
+    pub fn create_continuous_query(&self, name: &str, resample: Option<Resample>,
+                                   query: &str) -> Result<(), InfluxError> {
+        self.database.create_continuous_query(name, resample, query).wait()
+    }
+
+    /// Drop one of the database's continuous queries - requires db admin
+    /// privileges
+    pub fn drop_continuous_query(&self, name: &str) -> Result<(), InfluxError> {
+        self.database.drop_continuous_query(name).wait()
+    }
+
+    /// List the database's retention policies
+    pub fn show_retention_policies(&self) -> Result<Vec<RetentionPolicy>, InfluxError> {
+        self.database.show_retention_policies().wait()
+    }
+
+    /// Create a retention policy on the database - requires admin privileges
+    pub fn create_retention_policy(&self, policy: &RetentionPolicy) -> Result<(), InfluxError> {
+        self.database.create_retention_policy(policy).wait()
+    }
+
+    /// Change one of the database's retention policies to match `policy` -
+    /// requires admin privileges
+    pub fn alter_retention_policy(&self, policy: &RetentionPolicy) -> Result<(), InfluxError> {
+        self.database.alter_retention_policy(policy).wait()
+    }
+
+    /// Drop one of the database's retention policies, and the data in it -
+    /// requires admin privileges
+    pub fn drop_retention_policy(&self, name: &str) -> Result<(), InfluxError> {
+        self.database.drop_retention_policy(name).wait()
+    }
+
+    /// Create shard space for db - requires cluster admin privileges
+    pub fn create_shard_space(&self, shard_space: &ShardSpace) -> Result<(), InfluxError> {
+        self.database.create_shard_space(shard_space).wait()
+    }
+
+    /// Change the shard space of the same name to match `shard_space` -
+    /// requires cluster admin privileges
+    pub fn update_shard_space(&self, shard_space: &ShardSpace) -> Result<(), InfluxError> {
+        self.database.update_shard_space(shard_space).wait()
+    }
+
+    /// Drop a shard space, and the data in it - requires cluster admin
+    /// privileges
+    pub fn delete_shard_space(&self, name: &str) -> Result<(), InfluxError> {
+        self.database.delete_shard_space(name).wait()
+    }
+
+    /// The underlying database, for the requests not wrapped here (already
+    /// blocking or iterator based)
+    pub fn database(&self) -> &client::Database<'a> {
+        &self.database
+    }
+}
+
+#[test]
+fn waits_for_each_request() {
+    use client::{ApiVersion, Auth};
+    use transport::{Canned, Request, reply, stub_client};
+    /// The path of a request, and its query if any
+    fn describe(request: &Request) -> String {
+        let q = request.query.iter().find(|&&(ref name, _)| name.as_slice() == "q")
+                             .map(|&(_, ref q)| q.clone()).unwrap_or(String::new());
+        format!("{} {}", request.path.connect("/"), q).trim().to_string()
+    }
+    let transport = Canned::new(|sent| match describe(&sent.request).as_slice() {
+        "write" => reply(204, ""),
+        "query SHOW DATABASES" => reply(200, concat!(
+            r#"{"results":[{"statement_id":0,"series":[{"name":"databases","#,
+            r#""columns":["name"],"values":[["telegraf"],["_internal"]]}]}]}"#)),
+        query if query.starts_with("query") => {
+            reply(400, r#"{"error":"database name required"}"#)
+        }
+        _ => reply(404, "")
+    });
+    let sent = transport.sent();
+    let mut client = stub_client(Auth::None, transport);
+    client.set_api_version(ApiVersion::V1);
+    let influx = Influx::from_client(client);
+    assert_eq!(influx.get_database_names(),
+               Ok(vec!(String::from_str("telegraf"), String::from_str("_internal"))));
+    let db = influx.database("telegraf");
+    assert_eq!(db.write_lines(String::from_str("cpu value=1"), Default::default()), Ok(()));
+    assert!(influx.create_database("").is_err());
+    let sent: Vec<String> = sent.lock().unwrap().iter().map(|sent| describe(&sent.request))
+                                                       .collect();
+    assert_eq!(sent,
+               vec!(String::from_str("query SHOW DATABASES"), String::from_str("write"),
+                    String::from_str("query CREATE DATABASE \"\"")));
+}
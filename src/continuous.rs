@@ -0,0 +1,131 @@
+//! Continuous queries, as created by `CREATE CONTINUOUS QUERY` and listed by
+//! `SHOW CONTINUOUS QUERIES`
+
+use std::ascii::AsciiExt;
+use std::time::duration::Duration;
+use influxql::{self, quote_ident};
+use query::{QueryResult, Value};
+
+/// When a continuous query runs, and over what range of time
+#[derive(Show, Clone, Copy, PartialEq)]
+pub struct Resample {
+    /// How often to run - default (None) the `GROUP BY time` interval
+    pub every: Option<Duration>,
+    /// How far back each run covers - default (None) the `GROUP BY time`
+    /// interval
+    pub range: Option<Duration>
+}
+
+/// A continuous query
+#[derive(Show, Clone, PartialEq)]
+pub struct ContinuousQuery {
+    pub name: String,
+    pub database: String,
+    /// None runs the query with the server's default schedule
+    pub resample: Option<Resample>,
+    /// The `SELECT ... INTO ... GROUP BY time(...)` statement to run
+    pub query: String
+}
+
+impl ContinuousQuery {
+    /// The statement creating this continuous query
+    pub fn create_statement(&self) -> String {
+        let mut statement = format!("CREATE CONTINUOUS QUERY {} ON {}",
+                                    quote_ident(self.name.as_slice()),
+                                    quote_ident(self.database.as_slice()));
+        if let Some(resample) = self.resample {
+            statement.push_str(" RESAMPLE");
+            if let Some(every) = resample.every {
+                statement.push_str(format!(" EVERY {}", influxql::duration_literal(every))
+                                   .as_slice());
+            }
+            if let Some(range) = resample.range {
+                statement.push_str(format!(" FOR {}", influxql::duration_literal(range))
+                                   .as_slice());
+            }
+        }
+        statement.push_str(format!(" BEGIN {} END", self.query).as_slice());
+        statement
+    }
+
+    /// Read the definition of a continuous query as shown by
+    /// `SHOW CONTINUOUS QUERIES`
+    fn parse(name: String, database: String,
+             definition: &str) -> Result<ContinuousQuery, String> {
+        let invalid = || format!("Invalid continuous query: {}", definition);
+        // uppercasing ASCII keeps byte offsets the same
+        let upper = definition.to_ascii_uppercase();
+        let begin = try!(upper.find_str(" BEGIN ").ok_or_else(|| invalid()));
+        let end = try!(upper.rfind_str(" END").ok_or_else(|| invalid()));
+        if end < begin {
+            return Err(invalid());
+        }
+
+        let header = upper.slice_to(begin);
+        let resample = match header.find_str(" RESAMPLE") {
+            Some(start) => {
+                let mut resample = Resample { every: None, range: None };
+                let mut words = header.slice_from(start).words().skip(1);
+                loop {
+                    let target = match words.next() {
+                        Some("EVERY") => &mut resample.every,
+                        Some("FOR") => &mut resample.range,
+                        Some(_) => return Err(invalid()),
+                        None => break
+                    };
+                    let literal = try!(words.next().ok_or_else(|| invalid())).to_ascii_lowercase();
+                    *target = Some(try!(influxql::parse_duration_literal(literal.as_slice())
+                                        .ok_or_else(|| invalid())));
+                }
+                Some(resample)
+            },
+            None => None
+        };
+
+        Ok(ContinuousQuery {
+            name: name,
+            database: database,
+            resample: resample,
+            query: String::from_str(definition.slice(begin + 7, end).trim())
+        })
+    }
+
+    /// Read the result of `SHOW CONTINUOUS QUERIES`, which lists the
+    /// continuous queries of each database as a series
+    pub fn from_result(result: &QueryResult) -> Result<Vec<ContinuousQuery>, String> {
+        let mut queries = vec!();
+        for series in result.series.iter() {
+            let name = try!(series.column_index("name")
+                            .ok_or(String::from_str("Result has no name column")));
+            let query = try!(series.column_index("query")
+                             .ok_or(String::from_str("Result has no query column")));
+            for row in series.values.iter() {
+                match (&row[name], &row[query]) {
+                    (&Value::Text(ref name), &Value::Text(ref query)) => {
+                        queries.push(try!(ContinuousQuery::parse(name.clone(),
+                                                                 series.name.clone(),
+                                                                 query.as_slice())));
+                    },
+                    _ => return Err(String::from_str("Invalid continuous query row"))
+                }
+            }
+        }
+        Ok(queries)
+    }
+}
+
+#[test]
+fn parses_definition() {
+    let cq = ContinuousQuery::parse(
+        String::from_str("cq_hourly"), String::from_str("db"),
+        "CREATE CONTINUOUS QUERY cq_hourly ON db RESAMPLE EVERY 30m FOR 2h \
+         BEGIN SELECT mean(value) INTO hourly FROM cpu GROUP BY time(1h) END").unwrap();
+    assert_eq!(cq.resample, Some(Resample {
+        every: Some(Duration::minutes(30)),
+        range: Some(Duration::hours(2))
+    }));
+    assert_eq!(cq.query, "SELECT mean(value) INTO hourly FROM cpu GROUP BY time(1h)");
+    assert_eq!(cq.create_statement(),
+               "CREATE CONTINUOUS QUERY \"cq_hourly\" ON \"db\" RESAMPLE EVERY 30m FOR 2h \
+                BEGIN SELECT mean(value) INTO hourly FROM cpu GROUP BY time(1h) END");
+}
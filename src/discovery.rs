@@ -0,0 +1,81 @@
+//! Finding instances from DNS SRV records (feature `srv`)
+//!
+//! For clusters whose membership is published in DNS, such as Consul
+//! services or Kubernetes headless services. The record is resolved again
+//! on an interval and the cluster's instances brought in line with it.
+
+use std::default::Default;
+use std::io;
+use std::sync::Arc;
+use std::thread::Thread;
+use std::time::duration::Duration;
+use trust_dns_resolver::Resolver;
+use url::Host;
+use transport::{Cluster, Instance, Scheme};
+
+/// Discovers instances from the SRV record `name`, e.g.
+/// `_http._tcp.influxdb.default.svc.cluster.local`
+#[derive(Show, Clone)]
+pub struct SrvDiscovery {
+    name: String,
+    scheme: Scheme,
+    interval: Duration
+}
+
+impl SrvDiscovery {
+    pub fn new(name: &str, scheme: Scheme) -> SrvDiscovery {
+        SrvDiscovery {
+            name: String::from_str(name),
+            scheme: scheme,
+            interval: Duration::seconds(30)
+        }
+    }
+
+    /// How often the record is resolved - default 30s
+    pub fn interval(mut self, interval: Duration) -> SrvDiscovery {
+        self.interval = interval;
+        self
+    }
+
+    /// The instances the record currently lists, weighted by the record's
+    /// weights
+    pub fn lookup(&self) -> Result<Vec<Instance>, String> {
+        let resolver = try!(Resolver::from_system_conf().map_err(|e| format!("{}", e)));
+        let records = try!(resolver.lookup_srv(self.name.as_slice())
+                           .map_err(|e| format!("Can't resolve {}: {}", self.name, e)));
+        Ok(records.iter().map(|record| {
+            let target = record.target().to_string();
+            Instance {
+                scheme: self.scheme,
+                host: Host::Domain(String::from_str(target.as_slice().trim_right_matches('.'))),
+                port: record.port(),
+                role: Default::default(),
+                weight: record.weight() as u32
+            }
+        }).collect())
+    }
+
+    /// Keep `cluster`'s instances in line with the record, until the cluster
+    /// is dropped
+    ///
+    /// A failed lookup, or one listing no instances, leaves the instances as
+    /// they were
+    pub fn start(self, cluster: &Arc<Cluster>) {
+        let cluster = cluster.downgrade();
+        Thread::spawn(move || {
+            loop {
+                let cluster = match cluster.upgrade() {
+                    Some(cluster) => cluster,
+                    None => return
+                };
+                match self.lookup() {
+                    Ok(ref instances) if instances.is_empty() => {},
+                    Ok(instances) => cluster.set_hosts(instances),
+                    Err(_) => {}
+                }
+                drop(cluster);
+                io::timer::sleep(self.interval);
+            }
+        });
+    }
+}
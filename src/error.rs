@@ -0,0 +1,120 @@
+//! The error requests fail with
+
+use std::error::FromError;
+use std::fmt;
+use std::io::IoError;
+use hyper::status::StatusCode;
+use partial::PartialWrite;
+use transport::{self, RequestError, Timeout};
+
+/// Why a request failed
+#[derive(Show, Clone, PartialEq)]
+pub enum InfluxError {
+    /// No instance could be reached, or the connection failed part way
+    Connection(String),
+    /// The request exceeded one of the cluster's time limits
+    Timeout(Timeout),
+    /// The server answered with a non-2xx status, with the response body
+    Status(u16, String),
+    /// The server refused the credentials (401) or what they allow (403),
+    /// with the response body
+    Unauthorized(u16, String),
+    /// The response, or the rows in it, couldn't be read
+    Parse(String),
+    /// The server reported an error for the query as a whole
+    Query(String),
+    /// The server reported an error for the statement with the given id
+    Statement(usize, String),
+    /// The request couldn't be made as asked, e.g. it needs a feature the
+    /// server doesn't have
+    Request(String),
+    /// The server rejected some lines of a write, saying which and why
+    PartialWrite(PartialWrite)
+}
+
+impl InfluxError {
+    /// Whether the request may succeed if retried - e.g. after a connection
+    /// reset, a timeout or a 503 - rather than failing the same way again,
+    /// as after a 400 or an authentication failure
+    pub fn is_retriable(&self) -> bool {
+        match *self {
+            InfluxError::Connection(_) | InfluxError::Timeout(_) => true,
+            InfluxError::Status(status, _) => transport::retriable_status(status),
+            InfluxError::Unauthorized(..) | InfluxError::Parse(_) | InfluxError::Query(_) |
+            InfluxError::Statement(..) | InfluxError::Request(_) |
+            InfluxError::PartialWrite(_) => false
+        }
+    }
+}
+
+impl fmt::String for InfluxError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            InfluxError::Connection(ref e) => write!(f, "{}", e),
+            InfluxError::Timeout(ref timeout) => write!(f, "{}", timeout),
+            InfluxError::Status(status, ref body) |
+            InfluxError::Unauthorized(status, ref body) => {
+                write!(f, "{}: {}", StatusCode::from_u16(status), body)
+            },
+            InfluxError::Parse(ref e) => write!(f, "Invalid response: {}", e),
+            InfluxError::Query(ref e) => write!(f, "{}", e),
+            InfluxError::Statement(id, ref e) => write!(f, "statement {}: {}", id, e),
+            InfluxError::Request(ref e) => write!(f, "{}", e),
+            InfluxError::PartialWrite(ref partial) => {
+                try!(write!(f, "{} lines rejected", partial.rejected.len()));
+                match partial.rejected.first() {
+                    Some(line) => write!(f, ", first: {}", line.reason),
+                    None => Ok(())
+                }
+            }
+        }
+    }
+}
+
+impl FromError<String> for InfluxError {
+    fn from_error(error: String) -> InfluxError {
+        InfluxError::Request(error)
+    }
+}
+
+impl FromError<IoError> for InfluxError {
+    fn from_error(error: IoError) -> InfluxError {
+        InfluxError::Connection(format!("{}", error))
+    }
+}
+
+impl RequestError for InfluxError {
+    fn timeout(timeout: Timeout) -> InfluxError {
+        InfluxError::Timeout(timeout)
+    }
+
+    fn connection(error: String) -> InfluxError {
+        InfluxError::Connection(error)
+    }
+
+    fn status(status: u16, body: String) -> InfluxError {
+        match status {
+            401 | 403 => InfluxError::Unauthorized(status, body),
+            _ => InfluxError::Status(status, body)
+        }
+    }
+}
+
+#[test]
+fn classifies_statuses() {
+    use transport::Failure;
+    let error: InfluxError = Failure::Status(401, String::from_str("unauthorized")).into_error();
+    assert_eq!(error, InfluxError::Unauthorized(401, String::from_str("unauthorized")));
+    let error: InfluxError = Failure::Status(404, String::from_str("not found")).into_error();
+    assert_eq!(error, InfluxError::Status(404, String::from_str("not found")));
+    let error: String = Failure::Status(404, String::from_str("not found")).into_error();
+    assert_eq!(error, "404 Not Found: not found");
+}
+
+#[test]
+fn classifies_retriable_errors() {
+    assert!(InfluxError::Status(503, String::new()).is_retriable());
+    assert!(InfluxError::Connection(String::from_str("connection reset")).is_retriable());
+    assert!(!InfluxError::Status(400, String::new()).is_retriable());
+    assert!(!InfluxError::Unauthorized(401, String::new()).is_retriable());
+}
@@ -0,0 +1,202 @@
+//! Structured results of `EXPLAIN` and `EXPLAIN ANALYZE`
+//!
+//! Both statements return their plan as lines of text. The figures useful
+//! for performance debugging are picked out of those lines, and the lines
+//! kept for anything else.
+
+use std::default::Default;
+use std::time::duration::Duration;
+use query::{QueryResult, Value};
+
+/// The plan of a query, from `EXPLAIN`
+#[derive(Show, Clone, PartialEq, Default)]
+pub struct QueryPlan {
+    pub shards: u64,
+    pub series: u64,
+    /// Values that would be read from the in-memory cache
+    pub cached_values: u64,
+    pub files: u64,
+    pub blocks: u64,
+    pub block_bytes: u64,
+    /// The plan as returned by the server
+    pub lines: Vec<String>
+}
+
+/// The plan and cost of a query that was run, from `EXPLAIN ANALYZE`
+///
+/// Counts are totals over every iterator the query created
+#[derive(Show, Clone, PartialEq)]
+pub struct QueryAnalysis {
+    pub planning_time: Duration,
+    pub execution_time: Duration,
+    pub total_time: Duration,
+    /// Cursors opened for selected, auxiliary and condition fields
+    pub cursors: u64,
+    pub blocks_decoded: u64,
+    pub block_bytes: u64,
+    /// The plan as returned by the server
+    pub lines: Vec<String>
+}
+
+/// The lines of text making up a plan
+fn plan_lines(result: &QueryResult) -> Vec<String> {
+    let mut lines = vec!();
+    for series in result.series.iter() {
+        for row in series.values.iter() {
+            if let Some(&Value::Text(ref line)) = row.first() {
+                lines.push(line.clone());
+            }
+        }
+    }
+    lines
+}
+
+/// Split a line of a plan into key and value, ignoring any tree drawing
+fn entry(line: &str) -> Option<(&str, &str)> {
+    let line = line.trim_left_matches(|c: char| {
+        c == '│' || c == '├' || c == '└' || c == '─' || c.is_whitespace()
+    });
+    let colon = match line.find(':') {
+        Some(colon) => colon,
+        None => return None
+    };
+    Some((line.slice_to(colon).trim(), line.slice_from(colon + 1).trim()))
+}
+
+/// Parse a Go-formatted duration such as `1m2.5s` or `18.381616ms`
+pub fn parse_duration(s: &str) -> Option<Duration> {
+    if s == "0" {
+        return Some(Duration::zero());
+    }
+    let units = [("ns", 1.0), ("us", 1e3), ("µs", 1e3), ("ms", 1e6),
+                 ("s", 1e9), ("m", 60e9), ("h", 3600e9)];
+    let mut rest = s;
+    let mut nanos = 0.0;
+    while !rest.is_empty() {
+        let digits = rest.chars().take_while(|c| c.is_digit(10) || *c == '.').count();
+        let number: f64 = match rest.slice_to(digits).parse() {
+            Ok(number) => number,
+            Err(_) => return None
+        };
+        rest = rest.slice_from(digits);
+        let unit_len = rest.find(|c: char| c.is_digit(10)).unwrap_or(rest.len());
+        let unit = rest.slice_to(unit_len);
+        match units.iter().find(|&&(name, _)| name == unit) {
+            Some(&(_, scale)) => nanos += number * scale,
+            None => return None
+        }
+        rest = rest.slice_from(unit_len);
+    }
+    Some(Duration::nanoseconds(nanos as i64))
+}
+
+/// Read the result of `EXPLAIN`
+pub fn plan(result: &QueryResult) -> Result<QueryPlan, String> {
+    let mut plan = QueryPlan { lines: plan_lines(result), ..Default::default() };
+    for line in plan.lines.clone().iter() {
+        let (key, value) = match entry(line.as_slice()) {
+            Some(entry) => entry,
+            None => continue
+        };
+        let count = match value.parse() {
+            Ok(count) => count,
+            Err(_) => continue
+        };
+        match key {
+            "NUMBER OF SHARDS" => plan.shards += count,
+            "NUMBER OF SERIES" => plan.series += count,
+            "CACHED VALUES" => plan.cached_values += count,
+            "NUMBER OF FILES" => plan.files += count,
+            "NUMBER OF BLOCKS" => plan.blocks += count,
+            "SIZE OF BLOCKS" => plan.block_bytes += count,
+            _ => {}
+        }
+    }
+    Ok(plan)
+}
+
+/// Read the result of `EXPLAIN ANALYZE`
+pub fn analysis(result: &QueryResult) -> Result<QueryAnalysis, String> {
+    let mut analysis = QueryAnalysis {
+        planning_time: Duration::zero(),
+        execution_time: Duration::zero(),
+        total_time: Duration::zero(),
+        cursors: 0,
+        blocks_decoded: 0,
+        block_bytes: 0,
+        lines: plan_lines(result)
+    };
+    let mut found_total = false;
+    for line in analysis.lines.clone().iter() {
+        let (key, value) = match entry(line.as_slice()) {
+            Some(entry) => entry,
+            None => continue
+        };
+        if key == "total_time" {
+            analysis.total_time = try!(parse_duration(value)
+                                       .ok_or(format!("Invalid total time: {}", value)));
+            found_total = true;
+        } else if key == "planning_time" || key == "execution_time" {
+            // the first of each is the statement's, the rest its iterators'
+            let time = if key == "planning_time" {
+                &mut analysis.planning_time
+            } else {
+                &mut analysis.execution_time
+            };
+            if *time == Duration::zero() {
+                *time = parse_duration(value).unwrap_or(Duration::zero());
+            }
+        } else if let Ok(count) = value.parse::<u64>() {
+            if key.starts_with("cursors_") {
+                analysis.cursors += count;
+            } else if key.ends_with("_blocks_decoded") {
+                analysis.blocks_decoded += count;
+            } else if key.ends_with("_blocks_size_bytes") {
+                analysis.block_bytes += count;
+            }
+        }
+    }
+    if !found_total {
+        return Err(String::from_str("Result is not an EXPLAIN ANALYZE plan"));
+    }
+    Ok(analysis)
+}
+
+#[test]
+fn parses_go_durations() {
+    assert_eq!(parse_duration("1m2.5s"), Some(Duration::milliseconds(62_500)));
+    assert_eq!(parse_duration("18.5µs"), Some(Duration::nanoseconds(18_500)));
+    assert_eq!(parse_duration("5 parsecs"), None);
+}
+
+#[test]
+fn reads_analysis() {
+    use query::Series;
+    let lines = [".",
+                 "└── select",
+                 "    ├── execution_time: 2.25ms",
+                 "    ├── planning_time: 18ms",
+                 "    ├── total_time: 20.25ms",
+                 "    └── field_iterators",
+                 "        └── create_iterator",
+                 "            ├── cursors_ref: 1",
+                 "            ├── cursors_aux: 2",
+                 "            ├── float_blocks_decoded: 3",
+                 "            ├── float_blocks_size_bytes: 1024",
+                 "            └── planning_time: 1.4ms"];
+    let result = QueryResult {
+        series: vec!(Series {
+            name: String::new(),
+            tags: vec!(),
+            columns: vec!(String::from_str("EXPLAIN ANALYZE")),
+            values: lines.iter().map(|l| vec!(Value::Text(String::from_str(*l)))).collect(),
+            partial: false
+        })
+    };
+    let analysis = analysis(&result).unwrap();
+    assert_eq!(analysis.planning_time, Duration::milliseconds(18));
+    assert_eq!(analysis.total_time, Duration::microseconds(20_250));
+    assert_eq!(analysis.cursors, 3);
+    assert_eq!(analysis.blocks_decoded, 3);
+    assert_eq!(analysis.block_bytes, 1024);
+}
@@ -0,0 +1,71 @@
+//! Querying InfluxDB 3 with SQL over Arrow Flight SQL (feature `flightsql`)
+//!
+//! InfluxDB 3 answers queries over gRPC as Arrow record batches rather than
+//! as JSON over HTTP, so this client is separate from `Influx`. It keeps its
+//! own runtime for the gRPC calls, and blocks on them.
+
+use arrow::record_batch::RecordBatch;
+use arrow_flight::sql::client::FlightSqlServiceClient;
+use tokio_runtime::runtime::Runtime;
+use tokio_stream::StreamExt;
+use tonic::transport::Endpoint;
+use error::InfluxError;
+
+/// A connection to the Flight SQL endpoint of an InfluxDB 3 server
+pub struct FlightSqlClient {
+    runtime: Runtime,
+    client: FlightSqlServiceClient<tonic::transport::Channel>
+}
+
+impl FlightSqlClient {
+    /// Connect to `url`, e.g. `https://us-east-1-1.aws.cloud2.influxdata.com`,
+    /// querying `database` with `token`
+    pub fn connect(url: &str, database: &str,
+                   token: &str) -> Result<FlightSqlClient, InfluxError> {
+        let runtime = try!(Runtime::new().map_err(|e| {
+            InfluxError::Request(format!("Can't start runtime: {}", e))
+        }));
+        let endpoint = try!(Endpoint::from_shared(String::from_str(url)).map_err(|e| {
+            InfluxError::Request(format!("Invalid URL {}: {}", url, e))
+        }));
+        let channel = try!(runtime.block_on(endpoint.connect()).map_err(|e| {
+            InfluxError::Connection(format!("Can't connect to {}: {}", url, e))
+        }));
+        let mut client = FlightSqlServiceClient::new(channel);
+        client.set_token(String::from_str(token));
+        client.set_header("database", database);
+        Ok(FlightSqlClient { runtime: runtime, client: client })
+    }
+
+    /// Run a SQL query, returning the record batches of every endpoint of its
+    /// result in turn
+    pub fn query(&mut self, sql: &str) -> Result<Vec<RecordBatch>, InfluxError> {
+        let info = try!(self.runtime.block_on(self.client.execute(String::from_str(sql), None))
+                        .map_err(|e| InfluxError::Query(format!("{}", e))));
+        let mut batches = vec!();
+        for endpoint in info.endpoint.into_iter() {
+            let ticket = try!(endpoint.ticket.ok_or(InfluxError::Parse(
+                String::from_str("Endpoint has no ticket"))));
+            let mut stream = try!(self.runtime.block_on(self.client.do_get(ticket)).map_err(|e| {
+                InfluxError::Connection(format!("Can't fetch results: {}", e))
+            }));
+            while let Some(batch) = self.runtime.block_on(stream.next()) {
+                batches.push(try!(batch.map_err(|e| InfluxError::Parse(format!("{}", e)))));
+            }
+        }
+        Ok(batches)
+    }
+}
+
+#[test]
+fn reports_connection_failures() {
+    match FlightSqlClient::connect("not a url", "telegraf", "token") {
+        Err(InfluxError::Request(_)) => (),
+        _ => panic!("invalid URL accepted")
+    }
+    // nothing listens on port 1
+    match FlightSqlClient::connect("http://127.0.0.1:1", "telegraf", "token") {
+        Err(InfluxError::Connection(_)) => (),
+        _ => panic!("connected to a closed port")
+    }
+}
@@ -0,0 +1,277 @@
+//! Flux queries and the annotated CSV they return
+//!
+//! Responses from `/api/v2/query` are CSV, preceded by `#datatype`, `#group`
+//! and `#default` annotation rows describing the columns. A response may hold
+//! several tables, separated by blank lines or distinguished by the `table`
+//! column.
+
+use std::mem;
+use rustc_serialize::json::Json;
+use time::Timespec;
+use timestamp;
+
+/// A value in a Flux table
+#[derive(Show, Clone, PartialEq)]
+pub enum FluxValue {
+    Null,
+    Integer(i64),
+    UInteger(u64),
+    Float(f64),
+    Boolean(bool),
+    /// `string`, `duration` and `base64Binary` columns
+    Text(String),
+    Time(Timespec)
+}
+
+/// A column of a Flux table, as described by the annotations
+#[derive(Show, Clone, PartialEq)]
+pub struct FluxColumn {
+    pub name: String,
+    /// The `#datatype` annotation, e.g. `long` or `dateTime:RFC3339`
+    pub data_type: String,
+    /// Whether the column is part of the table's group key
+    pub group: bool,
+    /// Used in place of empty values
+    pub default: Option<String>
+}
+
+/// A row of a Flux table
+#[derive(Show, Clone, PartialEq)]
+pub struct FluxRecord {
+    pub values: Vec<(String, FluxValue)>
+}
+
+impl FluxRecord {
+    /// Get the value of a column
+    pub fn get(&self, column: &str) -> Option<&FluxValue> {
+        self.values.iter()
+            .find(|&&(ref name, _)| name.as_slice() == column)
+            .map(|&(_, ref value)| value)
+    }
+
+    /// The `_time` column
+    pub fn time(&self) -> Option<Timespec> {
+        match self.get("_time") {
+            Some(&FluxValue::Time(time)) => Some(time),
+            _ => None
+        }
+    }
+
+    /// The `_value` column
+    pub fn value(&self) -> Option<&FluxValue> {
+        self.get("_value")
+    }
+
+    /// The `_field` column
+    pub fn field(&self) -> Option<&str> {
+        self.text("_field")
+    }
+
+    /// The `_measurement` column
+    pub fn measurement(&self) -> Option<&str> {
+        self.text("_measurement")
+    }
+
+    fn text(&self, column: &str) -> Option<&str> {
+        match self.get(column) {
+            Some(&FluxValue::Text(ref text)) => Some(text.as_slice()),
+            _ => None
+        }
+    }
+}
+
+/// A table of results
+#[derive(Show, Clone, PartialEq)]
+pub struct FluxTable {
+    pub columns: Vec<FluxColumn>,
+    pub records: Vec<FluxRecord>
+}
+
+impl FluxTable {
+    /// The values of the group key columns, shared by every record
+    pub fn group_key(&self) -> Vec<(String, FluxValue)> {
+        let record = match self.records.first() {
+            Some(record) => record,
+            None => return vec!()
+        };
+        self.columns.iter()
+            .zip(record.values.iter())
+            .filter(|&(column, _)| column.group)
+            .map(|(_, value)| value.clone())
+            .collect()
+    }
+}
+
+/// The JSON body of a request to `/api/v2/query`, asking for annotated CSV
+pub fn query_body(script: &str) -> String {
+    format!("{{\"query\":{},\"type\":\"flux\",\"dialect\":{{\
+             \"annotations\":[\"datatype\",\"group\",\"default\"],\"header\":true}}}}",
+            Json::String(String::from_str(script)))
+}
+
+/// Split CSV into rows of fields, handling quoting - a blank line is an
+/// empty row
+fn parse_csv(body: &str) -> Vec<Vec<String>> {
+    let mut rows = vec!();
+    let mut row = vec!();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = body.chars().peekable();
+    while let Some(c) = chars.next() {
+        if quoted {
+            if c != '"' {
+                field.push(c);
+            } else if chars.peek() == Some(&'"') {
+                chars.next();
+                field.push('"');
+            } else {
+                quoted = false;
+            }
+            continue;
+        }
+        match c {
+            '"' => quoted = true,
+            ',' => row.push(mem::replace(&mut field, String::new())),
+            '\r' => {},
+            '\n' => {
+                if !row.is_empty() || !field.is_empty() {
+                    row.push(mem::replace(&mut field, String::new()));
+                }
+                rows.push(mem::replace(&mut row, vec!()));
+            },
+            c => field.push(c)
+        }
+    }
+    if !row.is_empty() || !field.is_empty() {
+        row.push(field);
+        rows.push(row);
+    }
+    rows
+}
+
+/// Build the columns of a table from its annotations and header row
+fn read_columns(annotations: &[Vec<String>], header: &[String]) -> Vec<FluxColumn> {
+    let annotation = |name: &str, i: usize| -> Option<String> {
+        annotations.iter()
+            .find(|row| row[0].as_slice() == name)
+            .and_then(|row| row.get(i).cloned())
+    };
+    // the first column holds the annotation names, so isn't part of the table
+    header.iter().enumerate().skip(1).map(|(i, name)| {
+        FluxColumn {
+            name: name.clone(),
+            data_type: annotation("#datatype", i).unwrap_or(String::from_str("string")),
+            group: annotation("#group", i).map(|g| g.as_slice() == "true").unwrap_or(false),
+            default: annotation("#default", i).and_then(|d| {
+                if d.is_empty() { None } else { Some(d) }
+            })
+        }
+    }).collect()
+}
+
+/// Convert a raw value according to its column's data type
+fn parse_value(column: &FluxColumn, raw: &str) -> Result<FluxValue, String> {
+    let raw = match (raw, &column.default) {
+        ("", &Some(ref default)) => default.as_slice(),
+        ("", &None) => return Ok(FluxValue::Null),
+        (raw, _) => raw
+    };
+    let invalid = || format!("Invalid {} in column {}: {}", column.data_type, column.name, raw);
+    Ok(match column.data_type.as_slice() {
+        "long" => FluxValue::Integer(try!(raw.parse().map_err(|_| invalid()))),
+        "unsignedLong" => FluxValue::UInteger(try!(raw.parse().map_err(|_| invalid()))),
+        "double" => FluxValue::Float(try!(raw.parse().map_err(|_| invalid()))),
+        "boolean" => FluxValue::Boolean(try!(raw.parse().map_err(|_| invalid()))),
+        "dateTime:RFC3339" | "dateTime:RFC3339Nano" => {
+            FluxValue::Time(try!(timestamp::parse_rfc3339(raw)))
+        },
+        _ => FluxValue::Text(String::from_str(raw))
+    })
+}
+
+/// Parse an annotated CSV response into its tables
+///
+/// An error table (with `error` and `reference` columns) fails the parse
+/// with its message
+pub fn parse(body: String) -> Result<Vec<FluxTable>, String> {
+    let mut tables = vec!();
+    let mut annotations = vec!();
+    let mut header: Option<Vec<FluxColumn>> = None;
+    // id (`table` column) and contents of the table being read
+    let mut current: Option<(String, FluxTable)> = None;
+
+    for row in parse_csv(body.as_slice()).into_iter() {
+        let is_annotation = row.first().map(|f| f.starts_with("#")).unwrap_or(false);
+        if row.is_empty() || (is_annotation && header.is_some()) {
+            // end of a block of tables
+            tables.extend(current.take().map(|(_, table)| table).into_iter());
+            header = None;
+        }
+        if row.is_empty() {
+            continue;
+        }
+        if is_annotation {
+            annotations.push(row);
+            continue;
+        }
+
+        let columns = match header {
+            Some(ref columns) => columns.clone(),
+            None => {
+                header = Some(read_columns(annotations.as_slice(), row.as_slice()));
+                annotations.clear();
+                continue;
+            }
+        };
+        if columns.first().map(|c| c.name.as_slice() == "error").unwrap_or(false) {
+            return Err(row.get(1).cloned().unwrap_or(String::from_str("Unknown flux error")));
+        }
+
+        let mut values = vec!();
+        for (column, raw) in columns.iter().zip(row.iter().skip(1)) {
+            values.push((column.name.clone(), try!(parse_value(column, raw.as_slice()))));
+        }
+        let id = columns.iter()
+            .position(|c| c.name.as_slice() == "table")
+            .and_then(|i| row.get(i + 1).cloned())
+            .unwrap_or(String::new());
+        let same_table = current.as_ref().map(|&(ref current_id, _)| *current_id == id)
+                                .unwrap_or(false);
+        if !same_table {
+            tables.extend(current.take().map(|(_, table)| table).into_iter());
+            current = Some((id, FluxTable { columns: columns, records: vec!() }));
+        }
+        if let Some((_, ref mut table)) = current {
+            table.records.push(FluxRecord { values: values });
+        }
+    }
+    tables.extend(current.take().map(|(_, table)| table).into_iter());
+    Ok(tables)
+}
+
+#[test]
+fn parses_annotated_csv() {
+    let body = "#datatype,string,long,dateTime:RFC3339,double,string\r\n\
+                #group,false,false,false,false,true\r\n\
+                #default,_result,,,,\r\n\
+                ,result,table,_time,_value,host\r\n\
+                ,,0,2015-06-11T20:46:02Z,1.5,a\r\n\
+                ,,1,2015-06-11T20:46:02Z,,\"b,c\"\r\n\
+                \r\n";
+    let tables = parse(String::from_str(body)).unwrap();
+    assert_eq!(tables.len(), 2);
+    let record = &tables[0].records[0];
+    assert_eq!(record.get("result"), Some(&FluxValue::Text(String::from_str("_result"))));
+    assert_eq!(record.time(), Some(Timespec::new(1434055562, 0)));
+    assert_eq!(record.value(), Some(&FluxValue::Float(1.5)));
+    assert_eq!(tables[1].records[0].value(), Some(&FluxValue::Null));
+    assert_eq!(tables[1].group_key(),
+               vec!((String::from_str("host"), FluxValue::Text(String::from_str("b,c")))));
+}
+
+#[test]
+fn fails_on_error_table() {
+    let body = "#datatype,string,string\n#group,true,true\n#default,,\n\
+                ,error,reference\n,bad query,897\n";
+    assert_eq!(parse(String::from_str(body)), Err(String::from_str("bad query")));
+}
@@ -0,0 +1,108 @@
+//! Results of the `/ping`, `/health` and `/ready` endpoints, for liveness
+//! probes and waiting for a server to start
+
+use std::time::duration::Duration;
+use rustc_serialize::json::Json;
+use time::Timespec;
+use timestamp;
+
+/// An answered ping
+#[derive(Show, Clone, PartialEq)]
+pub struct Ping {
+    /// The `X-Influxdb-Version` header
+    pub version: Option<String>,
+    /// The `X-Influxdb-Build` header, `OSS` or `ENT`
+    pub build: Option<String>,
+    /// How long the server took to answer
+    pub latency: Duration
+}
+
+/// Whether a health check passed
+#[derive(Show, Clone, Copy, PartialEq)]
+pub enum HealthStatus {
+    Pass,
+    Fail
+}
+
+/// The result of a health check, and of the checks it is made up of
+#[derive(Show, Clone, PartialEq)]
+pub struct Health {
+    pub name: String,
+    pub status: HealthStatus,
+    pub message: Option<String>,
+    pub version: Option<String>,
+    pub commit: Option<String>,
+    pub checks: Vec<Health>
+}
+
+impl Health {
+    /// Read the JSON body of `/health`, or of one of its checks
+    pub fn from_json(json: &Json) -> Result<Health, String> {
+        let string = |key: &str| {
+            json.find(key).and_then(|value| value.as_string()).map(|value| String::from_str(value))
+        };
+        let mut checks = vec!();
+        if let Some(values) = json.find("checks").and_then(|checks| checks.as_array()) {
+            for check in values.iter() {
+                checks.push(try!(Health::from_json(check)));
+            }
+        }
+        Ok(Health {
+            name: try!(string("name").ok_or(String::from_str("Health has no name"))),
+            status: match string("status") {
+                Some(ref status) if status.as_slice() == "pass" => HealthStatus::Pass,
+                Some(ref status) if status.as_slice() == "fail" => HealthStatus::Fail,
+                _ => return Err(String::from_str("Health has no valid status"))
+            },
+            message: string("message"),
+            version: string("version"),
+            commit: string("commit"),
+            checks: checks
+        })
+    }
+}
+
+/// The result of `/ready`
+#[derive(Show, Clone, PartialEq)]
+pub struct Ready {
+    /// Whether the server is ready for queries and writes
+    pub ready: bool,
+    /// When the server started
+    pub started: Option<Timespec>,
+    /// How long the server has been up
+    pub up: Option<String>
+}
+
+impl Ready {
+    /// Read the JSON body of `/ready`
+    pub fn from_json(json: &Json) -> Result<Ready, String> {
+        let string = |key: &str| json.find(key).and_then(|value| value.as_string());
+        Ok(Ready {
+            ready: string("status") == Some("ready"),
+            started: match string("started") {
+                Some(started) => Some(try!(timestamp::parse_rfc3339(started))),
+                None => None
+            },
+            up: string("up").map(|up| String::from_str(up))
+        })
+    }
+}
+
+#[test]
+fn reads_health() {
+    let json = Json::from_str(r#"{"name":"influxdb","message":"ready for queries and writes",
+        "status":"pass","checks":[],"version":"2.0.4","commit":"4e7a59bb9a"}"#).unwrap();
+    assert_eq!(Health::from_json(&json), Ok(Health {
+        name: String::from_str("influxdb"),
+        status: HealthStatus::Pass,
+        message: Some(String::from_str("ready for queries and writes")),
+        version: Some(String::from_str("2.0.4")),
+        commit: Some(String::from_str("4e7a59bb9a")),
+        checks: vec!()
+    }));
+    let json = Json::from_str(r#"{"status":"ready","started":"2021-01-01T00:00:00Z",
+        "up":"1m2s"}"#).unwrap();
+    let ready = Ready::from_json(&json).unwrap();
+    assert!(ready.ready);
+    assert_eq!(ready.started, Some(Timespec::new(1609459200, 0)));
+}
@@ -0,0 +1,796 @@
+//! Building InfluxQL statements
+//!
+//! Identifiers and string literals are always quoted and escaped here, so
+//! user-supplied names and values can't change the meaning of a statement.
+
+use std::fmt;
+use regex;
+use std::time::duration::Duration;
+use time::Timespec;
+use timestamp;
+use timestamp::ToTimestamp;
+
+/// Quote an identifier (database, measurement, tag or field name)
+pub fn quote_ident(ident: &str) -> String {
+    format!("\"{}\"", ident.replace("\\", "\\\\").replace("\"", "\\\""))
+}
+
+/// Quote a string literal
+pub fn quote_literal(value: &str) -> String {
+    format!("'{}'", value.replace("\\", "\\\\").replace("'", "\\'"))
+}
+
+/// Escape text so that it matches only itself when used in a regex
+pub fn escape_regex(text: &str) -> String {
+    regex::quote(text)
+}
+
+/// Delimit a regex as an InfluxQL regex literal (`/.../`), escaping any
+/// unescaped `/` in it
+pub fn regex_literal(pattern: &str) -> String {
+    let mut literal = String::from_str("/");
+    let mut escaped = false;
+    for c in pattern.chars() {
+        if c == '/' && !escaped {
+            literal.push('\\');
+        }
+        escaped = c == '\\' && !escaped;
+        literal.push(c);
+    }
+    literal.push('/');
+    literal
+}
+
+/// Format a duration as an InfluxQL duration literal (e.g. `90m`), using the
+/// largest unit it is a whole number of
+pub fn duration_literal(duration: Duration) -> String {
+    let ns = match duration.num_nanoseconds() {
+        Some(ns) => ns,
+        // too long to count in nanoseconds, so certainly whole microseconds
+        None => return format!("{}u", duration.num_microseconds().unwrap_or(0))
+    };
+    let units = [(7 * 24 * 3600 * 1_000_000_000, "w"),
+                 (24 * 3600 * 1_000_000_000, "d"),
+                 (3600 * 1_000_000_000, "h"),
+                 (60 * 1_000_000_000, "m"),
+                 (1_000_000_000, "s"),
+                 (1_000_000, "ms"),
+                 (1_000, "u")];
+    for &(size, unit) in units.iter() {
+        if ns != 0 && ns % size == 0 {
+            return format!("{}{}", ns / size, unit);
+        }
+    }
+    format!("{}ns", ns)
+}
+
+/// Parse an InfluxQL duration literal, e.g. `90m` or `1h30m`
+pub fn parse_duration_literal(literal: &str) -> Option<Duration> {
+    let mut rest = literal;
+    let mut total = Duration::zero();
+    if rest.is_empty() {
+        return None;
+    }
+    while !rest.is_empty() {
+        let digits = rest.find(|c: char| !c.is_digit(10)).unwrap_or(rest.len());
+        let n: i64 = match rest.slice_to(digits).parse() {
+            Ok(n) => n,
+            Err(_) => return None
+        };
+        rest = rest.slice_from(digits);
+        let unit_len = rest.find(|c: char| c.is_digit(10)).unwrap_or(rest.len());
+        total = total + match rest.slice_to(unit_len) {
+            "w" => Duration::weeks(n),
+            "d" => Duration::days(n),
+            "h" => Duration::hours(n),
+            "m" => Duration::minutes(n),
+            "s" => Duration::seconds(n),
+            "ms" => Duration::milliseconds(n),
+            "u" | "µ" => Duration::microseconds(n),
+            "ns" => Duration::nanoseconds(n),
+            _ => return None
+        };
+        rest = rest.slice_from(unit_len);
+    }
+    Some(total)
+}
+
+/// Format a timestamp as an InfluxQL time literal (nanoseconds since the
+/// epoch)
+pub fn time_literal(time: &Timespec) -> String {
+    timestamp::to_nanos(time).to_string()
+}
+
+/// A range of time to query, which formats as a `WHERE` condition on
+/// `time`
+#[derive(Show, Clone, Copy, PartialEq)]
+pub enum TimeRange {
+    /// The given duration up to now, e.g. `time > now() - 1h`
+    Last(Duration),
+    /// From a time (inclusive) onwards
+    Since(Timespec),
+    /// From the first time (inclusive) up to the second (exclusive)
+    Between(Timespec, Timespec)
+}
+
+impl TimeRange {
+    pub fn last(duration: Duration) -> TimeRange {
+        TimeRange::Last(duration)
+    }
+
+    pub fn since<T: ToTimestamp>(start: T) -> TimeRange {
+        TimeRange::Since(start.to_timespec())
+    }
+
+    pub fn between<S: ToTimestamp, E: ToTimestamp>(start: S, end: E) -> TimeRange {
+        TimeRange::Between(start.to_timespec(), end.to_timespec())
+    }
+
+    /// The start and end of the range, ending open ranges at `now`
+    pub fn bounds(&self, now: Timespec) -> (Timespec, Timespec) {
+        match *self {
+            TimeRange::Last(duration) => (now - duration, now),
+            TimeRange::Since(start) => (start, now),
+            TimeRange::Between(start, end) => (start, end)
+        }
+    }
+}
+
+impl fmt::String for TimeRange {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            TimeRange::Last(duration) => {
+                write!(f, "time > now() - {}", duration_literal(duration))
+            },
+            TimeRange::Since(ref start) => write!(f, "time >= {}", time_literal(start)),
+            TimeRange::Between(ref start, ref end) => {
+                write!(f, "time >= {} AND time < {}", time_literal(start), time_literal(end))
+            }
+        }
+    }
+}
+
+/// A value substituted into a `Template`, quoted according to its kind
+#[derive(Show, Clone, PartialEq)]
+pub enum Arg {
+    /// A database, measurement, tag or field name
+    Ident(String),
+    /// A string literal
+    Literal(String),
+    Integer(i64),
+    Float(f64),
+    Boolean(bool),
+    Duration(Duration),
+    Time(Timespec),
+    /// A condition on time, e.g. following `WHERE`
+    TimeRange(TimeRange)
+}
+
+impl Arg {
+    fn render(&self) -> String {
+        match *self {
+            Arg::Ident(ref ident) => quote_ident(ident.as_slice()),
+            Arg::Literal(ref value) => quote_literal(value.as_slice()),
+            Arg::Integer(n) => n.to_string(),
+            Arg::Float(n) => n.to_string(),
+            Arg::Boolean(b) => b.to_string(),
+            Arg::Duration(d) => duration_literal(d),
+            Arg::Time(ref t) => time_literal(t),
+            Arg::TimeRange(ref range) => range.to_string()
+        }
+    }
+}
+
+/// An identifier argument - strings are otherwise taken as literals
+pub fn ident(name: &str) -> Arg {
+    Arg::Ident(String::from_str(name))
+}
+
+/// Types that can be substituted into a `Template`
+pub trait ToArg {
+    fn to_arg(&self) -> Arg;
+}
+
+impl ToArg for Arg {
+    fn to_arg(&self) -> Arg {
+        self.clone()
+    }
+}
+
+impl<'a> ToArg for &'a str {
+    fn to_arg(&self) -> Arg {
+        Arg::Literal(String::from_str(*self))
+    }
+}
+
+impl ToArg for String {
+    fn to_arg(&self) -> Arg {
+        Arg::Literal(self.clone())
+    }
+}
+
+impl ToArg for i64 {
+    fn to_arg(&self) -> Arg {
+        Arg::Integer(*self)
+    }
+}
+
+impl ToArg for i32 {
+    fn to_arg(&self) -> Arg {
+        Arg::Integer(*self as i64)
+    }
+}
+
+impl ToArg for f64 {
+    fn to_arg(&self) -> Arg {
+        Arg::Float(*self)
+    }
+}
+
+impl ToArg for bool {
+    fn to_arg(&self) -> Arg {
+        Arg::Boolean(*self)
+    }
+}
+
+impl ToArg for Duration {
+    fn to_arg(&self) -> Arg {
+        Arg::Duration(*self)
+    }
+}
+
+impl ToArg for Timespec {
+    fn to_arg(&self) -> Arg {
+        Arg::Time(*self)
+    }
+}
+
+impl ToArg for TimeRange {
+    fn to_arg(&self) -> Arg {
+        Arg::TimeRange(*self)
+    }
+}
+
+#[derive(Show, Clone, PartialEq)]
+enum Part {
+    Text(String),
+    Placeholder(String)
+}
+
+/// A reusable query with `{name}` placeholders, filled in per call with
+/// quoted arguments - `{{` and `}}` are literal braces
+///
+/// See also the `query!` macro
+#[derive(Show, Clone, PartialEq)]
+pub struct Template {
+    parts: Vec<Part>
+}
+
+impl Template {
+    pub fn new(template: &str) -> Result<Template, String> {
+        let mut parts = vec!();
+        let mut text = String::new();
+        let mut chars = template.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    text.push('{');
+                },
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    text.push('}');
+                },
+                '{' => {
+                    let mut name = String::new();
+                    let mut closed = false;
+                    for c in chars.by_ref() {
+                        if c == '}' {
+                            closed = true;
+                            break;
+                        }
+                        name.push(c);
+                    }
+                    if !closed || name.is_empty() ||
+                        !name.chars().all(|c| c.is_alphanumeric() || c == '_') {
+                        return Err(format!("Invalid placeholder in template: {{{}}}", name));
+                    }
+                    if !text.is_empty() {
+                        parts.push(Part::Text(text));
+                        text = String::new();
+                    }
+                    parts.push(Part::Placeholder(name));
+                },
+                '}' => return Err(String::from_str("Unmatched } in template")),
+                c => text.push(c)
+            }
+        }
+        if !text.is_empty() {
+            parts.push(Part::Text(text));
+        }
+        Ok(Template { parts: parts })
+    }
+
+    /// Names of the placeholders, in order
+    pub fn placeholders(&self) -> Vec<&str> {
+        self.parts.iter().filter_map(|part| match *part {
+            Part::Placeholder(ref name) => Some(name.as_slice()),
+            Part::Text(_) => None
+        }).collect()
+    }
+
+    /// Fill in the placeholders, failing if any has no argument
+    pub fn render(&self, args: &[(&str, Arg)]) -> Result<String, String> {
+        let mut query = String::new();
+        for part in self.parts.iter() {
+            match *part {
+                Part::Text(ref text) => query.push_str(text.as_slice()),
+                Part::Placeholder(ref name) => {
+                    let &(_, ref arg) = try!(args.iter()
+                        .find(|&&(n, _)| n == name.as_slice())
+                        .ok_or(format!("No argument for placeholder {{{}}}", name)));
+                    query.push_str(arg.render().as_slice());
+                }
+            }
+        }
+        Ok(query)
+    }
+}
+
+/// Render an InfluxQL template with named arguments, each quoted according
+/// to its type (strings are literals, use `influxql::ident` for names)
+///
+/// ```ignore
+/// let q = query!("SELECT {field} FROM cpu WHERE host = {host}",
+///                field = ident("usage"), host = "server01");
+/// ```
+#[macro_export]
+macro_rules! query {
+    ($template:expr, $($name:ident = $value:expr),+) => (
+        $crate::influxql::Template::new($template).and_then(|template| {
+            template.render(&[$((stringify!($name), $crate::influxql::ToArg::to_arg(&$value))),+])
+        })
+    )
+}
+
+/// Comparison operators for `WHERE` conditions
+#[derive(Show, Clone, Copy, PartialEq)]
+pub enum Op {
+    Eq,
+    NotEq,
+    Lt,
+    LtEq,
+    Gt,
+    GtEq
+}
+
+impl fmt::String for Op {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", match *self {
+            Op::Eq => "=",
+            Op::NotEq => "!=",
+            Op::Lt => "<",
+            Op::LtEq => "<=",
+            Op::Gt => ">",
+            Op::GtEq => ">="
+        })
+    }
+}
+
+/// Aggregate and selector functions
+#[derive(Show, Clone, Copy, PartialEq)]
+pub enum Aggregate {
+    Count,
+    Distinct,
+    Sum,
+    Mean,
+    Median,
+    Mode,
+    Spread,
+    Stddev,
+    Min,
+    Max,
+    First,
+    Last,
+    Percentile(f64)
+}
+
+impl Aggregate {
+    /// Render the function applied to a field
+    fn apply(&self, field: &str) -> String {
+        let name = match *self {
+            Aggregate::Count => "COUNT",
+            Aggregate::Distinct => "DISTINCT",
+            Aggregate::Sum => "SUM",
+            Aggregate::Mean => "MEAN",
+            Aggregate::Median => "MEDIAN",
+            Aggregate::Mode => "MODE",
+            Aggregate::Spread => "SPREAD",
+            Aggregate::Stddev => "STDDEV",
+            Aggregate::Min => "MIN",
+            Aggregate::Max => "MAX",
+            Aggregate::First => "FIRST",
+            Aggregate::Last => "LAST",
+            Aggregate::Percentile(n) => {
+                return format!("PERCENTILE({}, {})", quote_ident(field), n);
+            }
+        };
+        format!("{}({})", name, quote_ident(field))
+    }
+}
+
+/// Fill options for empty `GROUP BY time` intervals
+#[derive(Show, Clone, Copy, PartialEq)]
+pub enum Fill {
+    Null,
+    None,
+    Previous,
+    Linear,
+    Value(f64)
+}
+
+impl fmt::String for Fill {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Fill::Null => write!(f, "fill(null)"),
+            Fill::None => write!(f, "fill(none)"),
+            Fill::Previous => write!(f, "fill(previous)"),
+            Fill::Linear => write!(f, "fill(linear)"),
+            Fill::Value(v) => write!(f, "fill({})", v)
+        }
+    }
+}
+
+/// Builds a `SELECT` statement
+#[derive(Show, Clone)]
+pub struct QueryBuilder {
+    fields: Vec<String>,
+    into: Option<String>,
+    from: Vec<String>,
+    conditions: Vec<String>,
+    group_by: Vec<String>,
+    fill: Option<Fill>,
+    descending: bool,
+    limit: Option<usize>,
+    offset: Option<usize>
+}
+
+impl QueryBuilder {
+    pub fn new() -> QueryBuilder {
+        QueryBuilder {
+            fields: vec!(),
+            into: None,
+            from: vec!(),
+            conditions: vec!(),
+            group_by: vec!(),
+            fill: None,
+            descending: false,
+            limit: None,
+            offset: None
+        }
+    }
+
+    /// Select a field or tag
+    pub fn field(mut self, name: &str) -> QueryBuilder {
+        self.fields.push(quote_ident(name));
+        self
+    }
+
+    /// Select all fields and tags
+    pub fn all(mut self) -> QueryBuilder {
+        self.fields.push(String::from_str("*"));
+        self
+    }
+
+    /// Select an aggregate of a field
+    pub fn aggregate(mut self, function: Aggregate, field: &str) -> QueryBuilder {
+        self.fields.push(function.apply(field));
+        self
+    }
+
+    /// Select an aggregate of a field, naming the result column
+    pub fn aggregate_as(mut self, function: Aggregate, field: &str, name: &str) -> QueryBuilder {
+        self.fields.push(format!("{} AS {}", function.apply(field), quote_ident(name)));
+        self
+    }
+
+    /// Write the results into a measurement, in the given retention policy
+    /// or the database's default
+    pub fn into(mut self, retention_policy: Option<&str>, measurement: &str) -> QueryBuilder {
+        self.into = Some(match retention_policy {
+            Some(rp) => format!("{}.{}", quote_ident(rp), quote_ident(measurement)),
+            None => quote_ident(measurement)
+        });
+        self
+    }
+
+    /// Select from a measurement - may be called more than once
+    pub fn from(mut self, measurement: &str) -> QueryBuilder {
+        self.from.push(quote_ident(measurement));
+        self
+    }
+
+    /// Select from every measurement whose name matches a regex - see
+    /// `escape_regex` for matching user input literally
+    pub fn from_matching(mut self, pattern: &str) -> QueryBuilder {
+        self.from.push(regex_literal(pattern));
+        self
+    }
+
+    /// Only include rows where a tag (or string field) compares to a value
+    pub fn where_tag(mut self, key: &str, op: Op, value: &str) -> QueryBuilder {
+        self.conditions.push(format!("{} {} {}", quote_ident(key), op, quote_literal(value)));
+        self
+    }
+
+    /// Only include rows where a tag or field compares to the value bound
+    /// to the parameter `$name` (see `Database::query_params`)
+    pub fn where_param(mut self, key: &str, op: Op, name: &str) -> QueryBuilder {
+        self.conditions.push(format!("{} {} ${}", quote_ident(key), op, name));
+        self
+    }
+
+    /// Only include rows where a numeric field compares to a value
+    pub fn where_field(mut self, key: &str, op: Op, value: f64) -> QueryBuilder {
+        self.conditions.push(format!("{} {} {}", quote_ident(key), op, value));
+        self
+    }
+
+    /// Only include rows at or after `start`
+    pub fn since(mut self, start: Timespec) -> QueryBuilder {
+        self.conditions.push(format!("time >= {}", time_literal(&start)));
+        self
+    }
+
+    /// Only include rows before `end`
+    pub fn until(mut self, end: Timespec) -> QueryBuilder {
+        self.conditions.push(format!("time < {}", time_literal(&end)));
+        self
+    }
+
+    /// Only include rows from `start` up to (not including) `end`
+    pub fn between(self, start: Timespec, end: Timespec) -> QueryBuilder {
+        self.since(start).until(end)
+    }
+
+    /// Only include rows in a range of time
+    pub fn time_range(mut self, range: TimeRange) -> QueryBuilder {
+        self.conditions.push(range.to_string());
+        self
+    }
+
+    /// Group rows into time intervals
+    pub fn group_by_time(mut self, interval: Duration) -> QueryBuilder {
+        self.group_by.push(format!("time({})", duration_literal(interval)));
+        self
+    }
+
+    /// Group rows by a tag
+    pub fn group_by_tag(mut self, key: &str) -> QueryBuilder {
+        self.group_by.push(quote_ident(key));
+        self
+    }
+
+    /// Group rows by every tag, e.g. to keep tags when writing `INTO`
+    pub fn group_by_all_tags(mut self) -> QueryBuilder {
+        self.group_by.push(String::from_str("*"));
+        self
+    }
+
+    /// How to fill empty time intervals
+    pub fn fill(mut self, fill: Fill) -> QueryBuilder {
+        self.fill = Some(fill);
+        self
+    }
+
+    /// Return the newest rows first
+    pub fn descending(mut self) -> QueryBuilder {
+        self.descending = true;
+        self
+    }
+
+    pub fn limit(mut self, limit: usize) -> QueryBuilder {
+        self.limit = Some(limit);
+        self
+    }
+
+    pub fn offset(mut self, offset: usize) -> QueryBuilder {
+        self.offset = Some(offset);
+        self
+    }
+
+    /// Render the statement, failing if nothing is selected or there is no
+    /// `FROM` clause
+    pub fn build(&self) -> Result<String, String> {
+        if self.fields.is_empty() {
+            return Err(String::from_str("Query selects nothing"));
+        }
+        if self.from.is_empty() {
+            return Err(String::from_str("Query has no FROM clause"));
+        }
+        let mut query = format!("SELECT {}", self.fields.connect(", "));
+        if let Some(ref into) = self.into {
+            query.push_str(format!(" INTO {}", into).as_slice());
+        }
+        query.push_str(format!(" FROM {}", self.from.connect(", ")).as_slice());
+        if !self.conditions.is_empty() {
+            query.push_str(" WHERE ");
+            query.push_str(self.conditions.connect(" AND ").as_slice());
+        }
+        if !self.group_by.is_empty() {
+            query.push_str(" GROUP BY ");
+            query.push_str(self.group_by.connect(", ").as_slice());
+        }
+        if let Some(fill) = self.fill {
+            query.push_str(format!(" {}", fill).as_slice());
+        }
+        if self.descending {
+            query.push_str(" ORDER BY time DESC");
+        }
+        if let Some(limit) = self.limit {
+            query.push_str(format!(" LIMIT {}", limit).as_slice());
+        }
+        if let Some(offset) = self.offset {
+            query.push_str(format!(" OFFSET {}", offset).as_slice());
+        }
+        Ok(query)
+    }
+}
+
+/// Rolls a measurement up into another at a coarser interval, with
+/// `SELECT ... INTO` statements - see `Database::downsample`
+///
+/// Tags are kept, and each aggregated field keeps its name in the target
+#[derive(Show, Clone)]
+pub struct Downsample {
+    source: String,
+    target: String,
+    retention_policy: Option<String>,
+    aggregates: Vec<(Aggregate, String)>,
+    interval: Duration,
+    chunk: Option<Duration>
+}
+
+impl Downsample {
+    pub fn new(source: &str, target: &str, interval: Duration) -> Downsample {
+        Downsample {
+            source: String::from_str(source),
+            target: String::from_str(target),
+            retention_policy: None,
+            aggregates: vec!(),
+            interval: interval,
+            chunk: None
+        }
+    }
+
+    /// Write into a retention policy other than the database's default
+    pub fn retention_policy(mut self, name: &str) -> Downsample {
+        self.retention_policy = Some(String::from_str(name));
+        self
+    }
+
+    /// Aggregate a field
+    pub fn aggregate(mut self, function: Aggregate, field: &str) -> Downsample {
+        self.aggregates.push((function, String::from_str(field)));
+        self
+    }
+
+    /// Backfill with one statement per chunk of time, rather than one
+    /// statement for the whole range - the chunk is rounded up to a whole
+    /// number of intervals
+    pub fn chunked(mut self, chunk: Duration) -> Downsample {
+        self.chunk = Some(chunk);
+        self
+    }
+
+    /// The statements covering `start` up to (not including) `end`
+    ///
+    /// `start` is rounded down to a whole interval, so no interval is split
+    /// between statements
+    pub fn statements(&self, start: Timespec, end: Timespec) -> Result<Vec<String>, String> {
+        if self.aggregates.is_empty() {
+            return Err(String::from_str("Downsample has no aggregates"));
+        }
+        let interval = match self.interval.num_nanoseconds() {
+            Some(interval) if interval > 0 => interval,
+            _ => return Err(format!("Invalid downsample interval: {}", self.interval))
+        };
+        let end = timestamp::to_nanos(&end);
+        let mut from = timestamp::to_nanos(&start);
+        let offset = from % interval;
+        from -= if offset < 0 { offset + interval } else { offset };
+        let chunk = match self.chunk.and_then(|chunk| chunk.num_nanoseconds()) {
+            Some(chunk) if chunk > interval => (chunk + interval - 1) / interval * interval,
+            Some(_) => interval,
+            None => end - from
+        };
+
+        let mut statements = vec!();
+        while from < end {
+            let to = if end - from > chunk { from + chunk } else { end };
+            let mut query = QueryBuilder::new();
+            for &(function, ref field) in self.aggregates.iter() {
+                query = query.aggregate_as(function, field.as_slice(), field.as_slice());
+            }
+            let statement = try!(query
+                .into(self.retention_policy.as_ref().map(|rp| rp.as_slice()),
+                      self.target.as_slice())
+                .from(self.source.as_slice())
+                .between(timestamp::from_nanos(from), timestamp::from_nanos(to))
+                .group_by_time(self.interval)
+                .group_by_all_tags()
+                .build());
+            statements.push(statement);
+            from = to;
+        }
+        Ok(statements)
+    }
+}
+
+#[test]
+fn builds_quoted_select() {
+    let query = QueryBuilder::new()
+        .aggregate(Aggregate::Mean, "usage")
+        .from("cpu")
+        .where_tag("host", Op::Eq, "a' OR 1=1")
+        .group_by_time(Duration::minutes(5))
+        .build()
+        .unwrap();
+    assert_eq!(query, "SELECT MEAN(\"usage\") FROM \"cpu\" \
+                       WHERE \"host\" = 'a\\' OR 1=1' GROUP BY time(5m)");
+}
+
+#[test]
+fn parses_duration_literals() {
+    assert_eq!(parse_duration_literal("1h30m"), Some(Duration::minutes(90)));
+    assert_eq!(parse_duration_literal("2d"), Some(Duration::hours(48)));
+    assert_eq!(parse_duration_literal("2x"), None);
+}
+
+#[test]
+fn formats_duration_literals() {
+    assert_eq!(duration_literal(Duration::hours(48)), "2d");
+    assert_eq!(duration_literal(Duration::seconds(90)), "90s");
+    assert_eq!(duration_literal(Duration::milliseconds(1500)), "1500ms");
+}
+
+#[test]
+fn chunks_downsample() {
+    let statements = Downsample::new("cpu", "cpu_1h", Duration::hours(1))
+        .aggregate(Aggregate::Mean, "usage")
+        .chunked(Duration::minutes(90))
+        .statements(Timespec::new(1800, 0), Timespec::new(4 * 3600, 0))
+        .unwrap();
+    assert_eq!(statements.len(), 2);
+    assert_eq!(statements[0],
+               "SELECT MEAN(\"usage\") AS \"usage\" INTO \"cpu_1h\" FROM \"cpu\" \
+                WHERE time >= 0 AND time < 7200000000000 GROUP BY time(1h), *");
+}
+
+#[test]
+fn renders_template() {
+    let template = Template::new("SELECT {f} FROM {m} WHERE host = {host} AND n > {n} {{x}}")
+        .unwrap();
+    let query = template.render(&[("f", ident("us\"age")), ("m", ident("cpu")),
+                                  ("host", "a'b".to_arg()), ("n", 3i64.to_arg())]).unwrap();
+    assert_eq!(query, "SELECT \"us\\\"age\" FROM \"cpu\" WHERE host = 'a\\'b' AND n > 3 {x}");
+    assert!(template.render(&[("f", ident("usage"))]).is_err());
+}
+
+#[test]
+fn formats_time_ranges() {
+    assert_eq!(TimeRange::last(Duration::hours(1)).to_string(), "time > now() - 1h");
+    assert_eq!(TimeRange::between(1000000000i64, 2000000000i64).to_string(),
+               "time >= 1000000000 AND time < 2000000000");
+}
+
+#[test]
+fn delimits_regexes() {
+    assert_eq!(regex_literal("cpu/.*"), "/cpu\\/.*/");
+    assert_eq!(regex_literal("a\\/b"), "/a\\/b/");
+    let query = QueryBuilder::new().all()
+        .from_matching(format!("^{}", escape_regex("disk.io")).as_slice())
+        .build()
+        .unwrap();
+    assert_eq!(query, "SELECT * FROM /^disk\\.io/");
+}
@@ -3,17 +3,77 @@
 // We need regex plugin
 #![feature(plugin)]
 
+extern crate futures;
 extern crate hyper;
 extern crate url;
 extern crate regex;
 #[plugin] #[no_link] extern crate regex_macros;
 extern crate time;
+extern crate "rustc-serialize" as rustc_serialize;
+extern crate crypto;
+extern crate flate2;
+#[cfg(feature = "chrono")] extern crate chrono;
+#[cfg(feature = "tokio")] extern crate "tokio-core" as tokio_core;
+#[cfg(feature = "tokio")] extern crate "tokio-io" as tokio_io;
+#[cfg(feature = "tls")] extern crate rustls;
+#[cfg(feature = "tls")] extern crate webpki;
+#[cfg(feature = "tls")] extern crate "webpki-roots" as webpki_roots;
+#[cfg(feature = "srv")] extern crate "trust-dns-resolver" as trust_dns_resolver;
+#[cfg(feature = "config-file")] extern crate toml;
+#[cfg(feature = "tracing")] #[macro_use] extern crate tracing;
+#[cfg(feature = "flightsql")] extern crate arrow;
+#[cfg(feature = "flightsql")] extern crate "arrow-flight" as arrow_flight;
+#[cfg(feature = "flightsql")] extern crate tonic;
+#[cfg(feature = "flightsql")] extern crate "tokio-runtime" as tokio_runtime;
+#[cfg(feature = "flightsql")] extern crate "tokio-stream" as tokio_stream;
 
-pub use client::Influx;
-pub use transport::Scheme;
+pub use client::{Auth, Influx, InfluxFuture, OnComplete};
+pub use error::InfluxError;
+pub use signing::RequestSigner;
+pub use transport::{Scheme, PoolConfig, HealthCheck, Timeout, Timeouts, TopologyEvent};
+pub use transport::{ConcurrencyLimit, Overflow};
+pub use transport::{Transport, HyperTransport, Failure, Instance, Role, Request, Response};
 
+pub mod balancer;
+pub mod batch;
+#[cfg(feature = "blocking")] pub mod blocking;
 pub mod client;
+pub mod config;
+pub mod continuous;
+#[cfg(feature = "srv")] pub mod discovery;
+pub mod error;
+pub mod explain;
+#[cfg(feature = "flightsql")] pub mod flightsql;
+pub mod flux;
+pub mod health;
+pub mod influxql;
+pub mod lineproto;
+pub mod metrics;
+pub mod middleware;
+pub mod mirror;
+pub mod partial;
+pub mod point;
+pub mod proxy;
+pub mod query;
+pub mod queue;
+mod resolver;
+#[cfg(feature = "tokio")] mod reactor;
+pub mod retention;
+pub mod retry;
+pub mod schema;
+pub mod shards;
+pub mod signing;
+pub mod spool;
+pub mod stats;
+pub mod subscriptions;
+pub mod timestamp;
+pub mod users;
+pub mod v2;
+pub mod wait;
+#[cfg(feature = "tls")] pub mod tls;
+mod trace;
 mod transport;
+mod workers;
 
 #[test]
 fn it_works() {
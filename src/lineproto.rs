@@ -0,0 +1,217 @@
+//! Serialization of data points into the influxdb line protocol
+//!
+//! Each point becomes one line of the form
+//!
+//! ```text
+//! measurement[,tag=value...] field=value[,field=value...] [timestamp]
+//! ```
+//!
+//! Measurement names, tag keys/values and field keys have their special
+//! characters backslash-escaped, string field values are double-quoted.
+
+use std::default::Default;
+use std::fmt;
+use time::Timespec;
+use client::{DataPoint, FieldValue};
+use timestamp;
+
+/// The precision timestamps are written with
+#[derive(Show, Clone, Copy, PartialEq)]
+pub enum Precision {
+    Nanoseconds,
+    Microseconds,
+    Milliseconds,
+    Seconds,
+    Minutes,
+    Hours
+}
+
+impl Precision {
+    /// Number of nanoseconds in one unit of this precision
+    pub fn nanos(&self) -> i64 {
+        match *self {
+            Precision::Nanoseconds => 1,
+            Precision::Microseconds => 1_000,
+            Precision::Milliseconds => 1_000_000,
+            Precision::Seconds => 1_000_000_000,
+            Precision::Minutes => 60 * 1_000_000_000,
+            Precision::Hours => 3600 * 1_000_000_000
+        }
+    }
+
+    /// The value of the `precision` parameter of `/api/v2/write`, which has
+    /// no minutes or hours
+    pub fn v2_name(&self) -> Option<&'static str> {
+        match *self {
+            Precision::Nanoseconds => Some("ns"),
+            Precision::Microseconds => Some("us"),
+            Precision::Milliseconds => Some("ms"),
+            Precision::Seconds => Some("s"),
+            Precision::Minutes | Precision::Hours => None
+        }
+    }
+
+    /// The value of the `epoch` parameter of `/query`, which spells
+    /// nanoseconds `ns` where writes have always sent `n`
+    pub fn epoch_name(&self) -> String {
+        match *self {
+            Precision::Nanoseconds => String::from_str("ns"),
+            precision => precision.to_string()
+        }
+    }
+}
+
+impl Default for Precision {
+    fn default() -> Precision {
+        Precision::Nanoseconds
+    }
+}
+
+/// Formats as the value of the `precision` query parameter of `/write`
+impl fmt::String for Precision {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", match *self {
+            Precision::Nanoseconds => "n",
+            Precision::Microseconds => "u",
+            Precision::Milliseconds => "ms",
+            Precision::Seconds => "s",
+            Precision::Minutes => "m",
+            Precision::Hours => "h"
+        })
+    }
+}
+
+/// Characters that must be escaped in a measurement name
+const MEASUREMENT_SPECIAL: &'static [char] = &[',', ' '];
+
+/// Characters that must be escaped in tag keys, tag values and field keys
+const KEY_SPECIAL: &'static [char] = &[',', '=', ' '];
+
+/// Characters that must be escaped inside a quoted string field value
+const STRING_SPECIAL: &'static [char] = &['"', '\\'];
+
+/// Backslash-escape any of `special` found in `s`
+fn escape(s: &str, special: &[char]) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        if special.contains(&c) {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out
+}
+
+/// Escape a measurement name
+pub fn escape_measurement(name: &str) -> String {
+    escape(name, MEASUREMENT_SPECIAL)
+}
+
+/// Escape a tag key, tag value or field key
+pub fn escape_key(key: &str) -> String {
+    escape(key, KEY_SPECIAL)
+}
+
+/// Quote and escape a string field value
+pub fn quote_string(value: &str) -> String {
+    format!("\"{}\"", escape(value, STRING_SPECIAL))
+}
+
+/// Convert a timestamp to whole units of `precision` since the epoch
+pub fn timestamp(time: &Timespec, precision: Precision) -> i64 {
+    timestamp::to_nanos(time) / precision.nanos()
+}
+
+/// Serialize a field value, applying the type suffix/quoting rules
+pub fn serialize_value(value: &FieldValue) -> String {
+    match *value {
+        FieldValue::Integer(i) => format!("{}i", i),
+        FieldValue::UInteger(u) => format!("{}u", u),
+        FieldValue::Float(f) => format!("{}", f),
+        FieldValue::Boolean(b) => format!("{}", b),
+        FieldValue::Text(ref s) => quote_string(s.as_slice())
+    }
+}
+
+/// Serialize a single point of the given measurement to a line (without
+/// trailing newline)
+pub fn serialize_point(measurement: &str, point: &DataPoint,
+                       precision: Precision) -> String {
+    let mut out = escape_measurement(measurement);
+    for &(ref key, ref value) in point.tags.iter() {
+        out.push(',');
+        out.push_str(escape_key(key.as_slice()).as_slice());
+        out.push('=');
+        out.push_str(escape_key(value.as_slice()).as_slice());
+    }
+    out.push(' ');
+    for (i, &(ref key, ref value)) in point.fields.iter().enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        out.push_str(escape_key(key.as_slice()).as_slice());
+        out.push('=');
+        out.push_str(serialize_value(value).as_slice());
+    }
+    out.push(' ');
+    out.push_str(timestamp(&point.time, precision).to_string().as_slice());
+    out
+}
+
+/// Serialize a batch of points of the same measurement, one per line
+pub fn serialize_points(measurement: &str, points: &[DataPoint],
+                        precision: Precision) -> String {
+    let mut out = String::new();
+    for point in points.iter() {
+        out.push_str(serialize_point(measurement, point, precision).as_slice());
+        out.push('\n');
+    }
+    out
+}
+
+/// Serialize several measurements' worth of points
+pub fn serialize_series(series: &[(String, Vec<DataPoint>)],
+                        precision: Precision) -> String {
+    let mut out = String::new();
+    for &(ref measurement, ref points) in series.iter() {
+        out.push_str(serialize_points(measurement.as_slice(),
+                                      points.as_slice(),
+                                      precision).as_slice());
+    }
+    out
+}
+
+#[test]
+fn serializes_typed_fields() {
+    let point = DataPoint {
+        time: Timespec::new(1, 5),
+        tags: vec!((String::from_str("host"), String::from_str("a b"))),
+        fields: vec!((String::from_str("n"), FieldValue::Integer(3)),
+                     (String::from_str("up"), FieldValue::Boolean(true)),
+                     (String::from_str("msg"), FieldValue::Text(String::from_str("ok"))))
+    };
+    assert_eq!(serialize_point("cpu", &point, Precision::Nanoseconds),
+               "cpu,host=a\\ b n=3i,up=true,msg=\"ok\" 1000000005");
+}
+
+#[test]
+fn names_precisions_for_each_endpoint() {
+    assert_eq!(Precision::Nanoseconds.to_string(), "n");
+    assert_eq!(Precision::Nanoseconds.epoch_name(), "ns");
+    assert_eq!(Precision::Microseconds.epoch_name(), "u");
+    assert_eq!(Precision::Nanoseconds.v2_name(), Some("ns"));
+}
+
+#[test]
+fn scales_timestamps_to_precision() {
+    let time = Timespec::new(7200, 999);
+    assert_eq!(timestamp(&time, Precision::Milliseconds), 7200000);
+    assert_eq!(timestamp(&time, Precision::Hours), 2);
+}
+
+#[test]
+fn escapes_special_characters() {
+    assert_eq!(escape_measurement("cpu load,1"), "cpu\\ load\\,1");
+    assert_eq!(escape_key("host name=a,b"), "host\\ name\\=a\\,b");
+    assert_eq!(quote_string("say \"hi\" \\o/"), "\"say \\\"hi\\\" \\\\o/\"");
+}
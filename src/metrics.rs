@@ -0,0 +1,151 @@
+//! Counters and gauges describing the client's own requests, see
+//! `Influx::metrics`
+
+use transport::{Failure, Instance};
+
+/// Upper bounds, in milliseconds, of the buckets of a `Histogram` - the
+/// last bucket holds everything slower
+pub static LATENCY_BUCKETS_MS: &'static [u64] =
+    &[1, 2, 5, 10, 25, 50, 100, 250, 500, 1000, 2500, 5000, 10000];
+
+/// How long requests took, counted into buckets
+#[derive(Show, Clone, PartialEq)]
+pub struct Histogram {
+    /// The number of requests taking at most each of `LATENCY_BUCKETS_MS`
+    /// (and more than the one before), then of those taking longer
+    pub counts: Vec<u64>,
+    pub count: u64,
+    pub sum_ms: u64
+}
+
+impl Histogram {
+    pub fn new() -> Histogram {
+        Histogram {
+            counts: LATENCY_BUCKETS_MS.iter().map(|_| 0).chain(Some(0).into_iter()).collect(),
+            count: 0,
+            sum_ms: 0
+        }
+    }
+
+    pub fn record(&mut self, ms: u64) {
+        let bucket = LATENCY_BUCKETS_MS.iter().position(|&bound| ms <= bound)
+                                       .unwrap_or(LATENCY_BUCKETS_MS.len());
+        self.counts[bucket] += 1;
+        self.count += 1;
+        self.sum_ms += ms;
+    }
+
+    pub fn mean_ms(&self) -> Option<f64> {
+        if self.count == 0 {
+            None
+        } else {
+            Some(self.sum_ms as f64 / self.count as f64)
+        }
+    }
+
+    /// The upper bound of the bucket holding the `q`th quantile (0 to 1),
+    /// e.g. 0.99 for the 99th percentile - None if there are no requests,
+    /// or the quantile is slower than the last bound
+    pub fn quantile_ms(&self, q: f64) -> Option<u64> {
+        if self.count == 0 {
+            return None;
+        }
+        let rank = (q * self.count as f64).ceil().max(1.0) as u64;
+        let mut seen = 0;
+        for (bucket, &count) in self.counts.iter().enumerate() {
+            seen += count;
+            if seen >= rank {
+                return LATENCY_BUCKETS_MS.get(bucket).map(|&bound| bound);
+            }
+        }
+        None
+    }
+}
+
+/// Failed attempts at requests, by why they failed
+#[derive(Show, Clone, PartialEq, Default)]
+pub struct Failures {
+    /// No response, e.g. the connection was refused or reset
+    pub connection: u64,
+    /// One of the cluster's time limits was exceeded
+    pub timeout: u64,
+    /// 4xx responses, authentication failures included
+    pub client_error: u64,
+    /// 5xx responses
+    pub server_error: u64
+}
+
+/// A snapshot of the client's metrics
+///
+/// Counters count from when the client was created, and are shared by its
+/// clones.
+#[derive(Show, Clone, PartialEq, Default)]
+pub struct Metrics {
+    /// Attempts at requests sent to an instance, retries included
+    pub requests: u64,
+    /// Of `requests`, those retrying an earlier attempt
+    pub retries: u64,
+    pub failures: Failures,
+    /// Points (lines) written successfully
+    pub points_written: u64,
+    /// Bytes of request bodies sent
+    pub bytes_sent: u64,
+    /// Requests waiting for a worker thread when the snapshot was taken
+    pub queued: usize,
+    /// Requests sent and not yet answered when the snapshot was taken
+    pub in_flight: usize,
+    /// How long each instance took to answer, or fail, the attempts sent to
+    /// it
+    pub latency: Vec<(Instance, Histogram)>
+}
+
+impl Metrics {
+    /// Count an attempt at a request to `instance`, after `retry` earlier
+    /// attempts, sending `bytes` and taking `latency_ns`
+    pub fn record_attempt(&mut self, instance: &Instance, retry: u32, bytes: usize,
+                          latency_ns: u64, failure: Option<&Failure>) {
+        self.requests += 1;
+        if retry > 0 {
+            self.retries += 1;
+        }
+        self.bytes_sent += bytes as u64;
+        match failure {
+            Some(&Failure::Error(_)) | Some(&Failure::Misconfigured(_)) => {
+                self.failures.connection += 1
+            },
+            Some(&Failure::Timeout(_)) => self.failures.timeout += 1,
+            Some(&Failure::Status(500...599, _)) => self.failures.server_error += 1,
+            Some(&Failure::Status(..)) => self.failures.client_error += 1,
+            None => ()
+        }
+        let ms = latency_ns / 1_000_000;
+        match self.latency.iter().position(|&(ref i, _)| i.same_address(instance)) {
+            Some(pos) => self.latency[pos].1.record(ms),
+            None => {
+                let mut histogram = Histogram::new();
+                histogram.record(ms);
+                self.latency.push((instance.clone(), histogram));
+            }
+        }
+    }
+}
+
+#[test]
+fn counts_attempts() {
+    use transport::Timeout;
+    use std::time::duration::Duration;
+    let instance: Instance = Default::default();
+    let mut metrics: Metrics = Default::default();
+    metrics.record_attempt(&instance, 0, 100, 3_000_000, None);
+    metrics.record_attempt(&instance, 0, 100, 40_000_000,
+                           Some(&Failure::Status(503, String::new())));
+    metrics.record_attempt(&instance, 1, 100, 20_000_000_000,
+                           Some(&Failure::Timeout(Timeout::Request(Duration::seconds(20)))));
+    assert_eq!((metrics.requests, metrics.retries, metrics.bytes_sent), (3, 1, 300));
+    assert_eq!(metrics.failures, Failures { server_error: 1, timeout: 1, ..Default::default() });
+    let ref histogram = metrics.latency[0].1;
+    assert_eq!(histogram.count, 3);
+    assert_eq!(histogram.quantile_ms(0.3), Some(5));
+    assert_eq!(histogram.quantile_ms(0.5), Some(50));
+    assert_eq!(histogram.quantile_ms(1.0), None);
+}
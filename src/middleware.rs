@@ -0,0 +1,36 @@
+//! Hooks run around every request a cluster sends
+//!
+//! Middleware sees each attempt at a request, so retries are signed (or
+//! counted) again.
+
+use std::ascii::AsciiExt;
+use std::time::duration::Duration;
+pub use transport::Request;
+
+/// A response, as seen by middleware
+#[derive(Show, Clone, PartialEq)]
+pub struct Response {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    /// How long the instance took to respond
+    pub elapsed: Duration
+}
+
+impl Response {
+    /// The value of a header, matched case-insensitively
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.iter()
+            .find(|&&(ref n, _)| n.as_slice().eq_ignore_ascii_case(name))
+            .map(|&(_, ref value)| value.as_slice())
+    }
+}
+
+/// Runs before each request is sent and after each response arrives, e.g.
+/// to add headers, sign requests or record metrics
+pub trait Middleware: Send + Sync {
+    /// Modify a request before it is sent
+    fn before(&self, _request: &mut Request) {}
+
+    /// Inspect a response - not called when no response arrives
+    fn after(&self, _response: &Response) {}
+}
@@ -0,0 +1,198 @@
+//! Writing every batch to two targets, for live migrations between servers
+//!
+//! A `MirrorWriter` writes to a primary target, e.g. the 1.x database being
+//! migrated from, and a secondary one, e.g. the 2.x bucket being migrated
+//! to. Each target is an `Influx` of its own, so each can speak its own API
+//! version with its own credentials. Writes succeed or fail with the
+//! primary, without waiting for the secondary; the secondary's failures are
+//! only counted, so a struggling new server never affects the old one.
+//!
+//! Points without a timestamp are given one by each server as it receives
+//! them, so timestamps should be set for the targets to match exactly.
+
+use std::default::Default;
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread::Thread;
+use futures::Future;
+use client::{Influx, Database, DataPoint, InfluxFuture, WriteOptions};
+use error::InfluxError;
+use point::Point;
+
+/// How a target's writes have fared
+#[derive(Show, Clone, Default, PartialEq)]
+pub struct MirrorStats {
+    /// Writes that succeeded
+    pub written: u64,
+    /// Writes that failed
+    pub failed: u64,
+    /// The error of the most recent failed write
+    pub last_error: Option<InfluxError>
+}
+
+impl MirrorStats {
+    fn record(&mut self, result: &Result<(), InfluxError>) {
+        match *result {
+            Ok(()) => self.written += 1,
+            Err(ref e) => {
+                self.failed += 1;
+                self.last_error = Some(e.clone());
+            }
+        }
+    }
+}
+
+/// A database written to by a `MirrorWriter`
+#[derive(Clone)]
+struct Target {
+    influx: Influx,
+    database: String,
+    stats: Arc<Mutex<MirrorStats>>
+}
+
+impl Target {
+    /// Write with `write`, counting how it fares
+    fn write<F>(&self, write: F) -> InfluxFuture<Result<(), InfluxError>>
+        where F: FnOnce(&Database) -> InfluxFuture<()> {
+        let stats = self.stats.clone();
+        let database = self.influx.database(self.database.as_slice());
+        Box::new(write(&database).then(move |result| {
+            stats.lock().unwrap().record(&result);
+            Ok(result)
+        }))
+    }
+}
+
+/// Writes every batch to a primary and a secondary database
+#[derive(Clone)]
+pub struct MirrorWriter {
+    primary: Target,
+    secondary: Target,
+    /// The secondary's writes, waited for on a thread of their own so their
+    /// stats are counted
+    secondary_writes: Arc<Mutex<mpsc::Sender<InfluxFuture<Result<(), InfluxError>>>>>
+}
+
+impl MirrorWriter {
+    /// Mirror writes to `primary_db` of `primary` into `secondary_db` of
+    /// `secondary`, e.g. a database of a 1.x server into a bucket of a 2.x
+    /// one
+    pub fn new(primary: Influx, primary_db: &str,
+               secondary: Influx, secondary_db: &str) -> MirrorWriter {
+        let (sender, receiver) = mpsc::channel();
+        Thread::spawn(move || {
+            // stops once every clone of the writer is dropped
+            for write in receiver.iter() {
+                let _ = write.wait();
+            }
+        });
+        MirrorWriter {
+            primary: Target {
+                influx: primary,
+                database: String::from_str(primary_db),
+                stats: Arc::new(Mutex::new(Default::default()))
+            },
+            secondary: Target {
+                influx: secondary,
+                database: String::from_str(secondary_db),
+                stats: Arc::new(Mutex::new(Default::default()))
+            },
+            secondary_writes: Arc::new(Mutex::new(sender))
+        }
+    }
+
+    /// Write a batch of points to the given series of both targets
+    pub fn write_points(&self, series: &str, points: Vec<DataPoint>,
+                        options: WriteOptions) -> InfluxFuture<()> {
+        let (secondary_points, secondary_options) = (points.clone(), options.clone());
+        self.mirror(move |db| db.write_points(series, points, options),
+                    move |db| db.write_points(series, secondary_points, secondary_options))
+    }
+
+    /// Write points of any measurements to both targets
+    pub fn write(&self, points: &[Point], options: WriteOptions) -> InfluxFuture<()> {
+        let secondary_options = options.clone();
+        self.mirror(|db| db.write(points, options),
+                    |db| db.write(points, secondary_options))
+    }
+
+    /// Post a body of already serialized line protocol to both targets
+    pub fn write_lines(&self, body: String,
+                       options: WriteOptions) -> InfluxFuture<()> {
+        let (secondary_body, secondary_options) = (body.clone(), options.clone());
+        self.mirror(move |db| db.write_lines(body, options),
+                    move |db| db.write_lines(secondary_body, secondary_options))
+    }
+
+    /// How the primary target's writes have fared
+    pub fn primary_stats(&self) -> MirrorStats {
+        self.primary.stats.lock().unwrap().clone()
+    }
+
+    /// How the secondary target's writes have fared
+    pub fn secondary_stats(&self) -> MirrorStats {
+        self.secondary.stats.lock().unwrap().clone()
+    }
+
+    /// Start counting both targets' writes afresh, e.g. once a backfill of
+    /// the secondary has caught up
+    pub fn reset_stats(&self) {
+        *self.primary.stats.lock().unwrap() = Default::default();
+        *self.secondary.stats.lock().unwrap() = Default::default();
+    }
+
+    /// Write to both targets at once, resolving with the primary's result as
+    /// soon as it has answered, whatever the secondary is doing
+    fn mirror<P, S>(&self, primary: P, secondary: S) -> InfluxFuture<()>
+        where P: FnOnce(&Database) -> InfluxFuture<()>,
+              S: FnOnce(&Database) -> InfluxFuture<()> {
+        let primary = self.primary.write(primary);
+        let _ = self.secondary_writes.lock().unwrap().send(self.secondary.write(secondary));
+        Box::new(primary.and_then(|result| result))
+    }
+}
+
+#[test]
+fn counts_failures() {
+    let mut stats: MirrorStats = Default::default();
+    stats.record(&Ok(()));
+    stats.record(&Err(InfluxError::Status(404, String::from_str("bucket not found"))));
+    stats.record(&Ok(()));
+    assert_eq!(stats, MirrorStats {
+        written: 2,
+        failed: 1,
+        last_error: Some(InfluxError::Status(404, String::from_str("bucket not found")))
+    });
+}
+
+#[test]
+fn writes_without_waiting_for_the_secondary() {
+    use std::io::timer;
+    use std::time::duration::Duration;
+    use client::Auth;
+    use transport::{Canned, reply_as, stub_client};
+    let (release, gate) = mpsc::channel::<()>();
+    let gate = Mutex::new(gate);
+    // holds writes until let through
+    let gated = Canned::new(move |sent| {
+        if sent.request.path.last().map_or(false, |p| p.as_slice() == "write") {
+            let _ = gate.lock().unwrap().recv();
+        }
+        reply_as("1.8.0", 204, "")
+    });
+    let mirror = MirrorWriter::new(stub_client(Auth::None, Canned::new(|_| {
+        reply_as("1.8.0", 204, "")
+    })), "old", stub_client(Auth::None, gated), "new");
+    let write = mirror.write_lines(String::from_str("cpu value=1 1\n"), Default::default());
+    assert_eq!(write.wait(), Ok(()));
+    assert_eq!(mirror.primary_stats().written, 1);
+    assert_eq!(mirror.secondary_stats().written, 0);
+
+    release.send(()).unwrap();
+    for _ in 0..100 {
+        if mirror.secondary_stats().written == 1 {
+            break;
+        }
+        timer::sleep(Duration::milliseconds(10));
+    }
+    assert_eq!(mirror.secondary_stats().written, 1);
+}
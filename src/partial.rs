@@ -0,0 +1,235 @@
+//! Reading which lines of a write the server rejected, from its error
+//!
+//! 1.x and 2.x servers write the points of a batch they can, and reject the
+//! rest with a 400 (or 422) naming them: `unable to parse '<line>': <why>`
+//! for malformed lines, or `field type conflict: input field "<field>" on
+//! measurement "<measurement>" is type <type>, already exists as type
+//! <type>` for points whose fields don't match the existing schema.
+
+use rustc_serialize::json::Json;
+
+/// A line of a write the server rejected
+#[derive(Show, Clone, PartialEq)]
+pub struct RejectedLine {
+    /// The position of the line in the body written, from 0 - None if the
+    /// error doesn't say which line it was
+    pub line: Option<usize>,
+    /// Why the server rejected it
+    pub reason: String
+}
+
+/// A write the server rejected some lines of
+#[derive(Show, Clone, PartialEq)]
+pub struct PartialWrite {
+    /// The lines rejected, in order
+    pub rejected: Vec<RejectedLine>,
+    /// Whether the other lines were written - by the server, or by
+    /// resubmitting them
+    pub written: bool,
+    /// The number of points dropped, if the server said
+    pub dropped: Option<u64>
+}
+
+impl PartialWrite {
+    /// Read the error of a write of `body` that failed with `status`,
+    /// returning None unless it names lines the server rejected
+    pub fn from_response(status: u16, error: &str, body: &str) -> Option<PartialWrite> {
+        if status != 400 && status != 422 {
+            return None;
+        }
+        let message = match Json::from_str(error) {
+            Ok(json) => match json.find("error").or(json.find("message"))
+                                  .and_then(|m| m.as_string()) {
+                Some(message) => String::from_str(message),
+                None => return None
+            },
+            Err(_) => String::from_str(error)
+        };
+        let lines: Vec<&str> = body.lines().collect();
+        let mut rejected = vec!();
+        for reason in message.as_slice().split('\n').filter(|r| !r.trim().is_empty()) {
+            let reason = reason.trim();
+            if let Some(line) = quoted_after(reason, "unable to parse '", '\'') {
+                // the server names each malformed line once, however many
+                // times it was written
+                let indices: Vec<usize> = lines.iter().enumerate()
+                    .filter(|&(_, l)| l.trim() == line).map(|(index, _)| index).collect();
+                if indices.is_empty() {
+                    rejected.push(RejectedLine { line: None, reason: String::from_str(reason) });
+                }
+                for &index in indices.iter() {
+                    if !rejected.iter().any(|r: &RejectedLine| r.line == Some(index)) {
+                        rejected.push(RejectedLine {
+                            line: Some(index),
+                            reason: String::from_str(reason)
+                        });
+                    }
+                }
+            } else if reason.contains("field type conflict") {
+                let field = quoted_after(reason, "input field \"", '"');
+                let measurement = quoted_after(reason, "on measurement \"", '"');
+                // the type of the values rejected, unlike the existing one
+                let input = reason.find_str("is type ").map(|start| {
+                    let rest = &reason[start + "is type ".len()..];
+                    rest.split(|c: char| c == ',' || c.is_whitespace()).next().unwrap_or(rest)
+                });
+                if let (Some(field), Some(measurement)) = (field, measurement) {
+                    for (index, line) in lines.iter().enumerate() {
+                        let value = field_value(line, measurement, field);
+                        let conflicts = match (value, input) {
+                            (Some(value), Some(input)) => value_type(value.as_slice()) == input,
+                            (value, None) => value.is_some(),
+                            (None, _) => false
+                        };
+                        if conflicts {
+                            rejected.push(RejectedLine {
+                                line: Some(index),
+                                reason: String::from_str(reason)
+                            });
+                        }
+                    }
+                }
+            }
+        }
+        if rejected.is_empty() {
+            return None;
+        }
+        Some(PartialWrite {
+            rejected: rejected,
+            written: message.as_slice().contains("partial write"),
+            dropped: message.as_slice().split("dropped=").nth(1)
+                .and_then(|n| n.trim().parse().ok())
+        })
+    }
+
+    /// The lines of `body` that weren't rejected, or None if some rejected
+    /// line couldn't be found in it
+    pub fn accepted_lines(&self, body: &str) -> Option<String> {
+        if self.rejected.iter().any(|r| r.line.is_none()) {
+            return None;
+        }
+        let mut accepted = String::new();
+        for (index, line) in body.lines().enumerate() {
+            if !self.rejected.iter().any(|r| r.line == Some(index)) {
+                accepted.push_str(line);
+                accepted.push('\n');
+            }
+        }
+        Some(accepted)
+    }
+}
+
+/// The text in `s` between `prefix` and the next `end`
+fn quoted_after<'a>(s: &'a str, prefix: &str, end: char) -> Option<&'a str> {
+    s.find_str(prefix).and_then(|start| {
+        let rest = &s[start + prefix.len()..];
+        rest.find(end).map(|stop| &rest[..stop])
+    })
+}
+
+/// The raw value of the field `field` of a line of line protocol, if the
+/// line is of `measurement` and has it
+fn field_value(line: &str, measurement: &str, field: &str) -> Option<String> {
+    let parts = split_unescaped(line, ' ');
+    let series = match parts.get(0) { Some(series) => series, None => return None };
+    let fields = match parts.get(1) { Some(fields) => fields, None => return None };
+    let name = split_unescaped(series.as_slice(), ',').into_iter().next().unwrap_or(String::new());
+    if unescape(name.as_slice()) != measurement {
+        return None;
+    }
+    split_unescaped(fields.as_slice(), ',').into_iter().filter_map(|pair| {
+        let mut parts = split_unescaped(pair.as_slice(), '=').into_iter();
+        match (parts.next(), parts.next()) {
+            (Some(ref key), Some(value)) if unescape(key.as_slice()) == field => Some(value),
+            _ => None
+        }
+    }).next()
+}
+
+/// The type the server names a raw field value's type by in its errors
+fn value_type(value: &str) -> &'static str {
+    match value {
+        "t" | "T" | "true" | "True" | "TRUE" | "f" | "F" | "false" | "False" | "FALSE" => {
+            "boolean"
+        },
+        _ if value.starts_with("\"") => "string",
+        _ if value.ends_with("i") => "integer",
+        _ if value.ends_with("u") => "unsigned",
+        _ => "float"
+    }
+}
+
+/// Split `s` on `separator`, except where escaped or in a quoted string
+fn split_unescaped(s: &str, separator: char) -> Vec<String> {
+    let mut parts = vec!();
+    let mut part = String::new();
+    let (mut escaped, mut quoted) = (false, false);
+    for c in s.chars() {
+        if escaped {
+            escaped = false;
+        } else if c == '\\' {
+            escaped = true;
+        } else if c == '"' {
+            quoted = !quoted;
+        } else if c == separator && !quoted {
+            parts.push(part);
+            part = String::new();
+            continue;
+        }
+        part.push(c);
+    }
+    parts.push(part);
+    parts
+}
+
+/// Remove the backslashes escaping characters of a measurement or key
+fn unescape(s: &str) -> String {
+    let mut unescaped = String::new();
+    let mut escaped = false;
+    for c in s.chars() {
+        if c == '\\' && !escaped {
+            escaped = true;
+            continue;
+        }
+        escaped = false;
+        unescaped.push(c);
+    }
+    unescaped
+}
+
+#[test]
+fn reads_rejected_lines() {
+    let body = "cpu value=1i 1\ncpu value=0.5 2\nmem free=2i 3\n";
+    let error = concat!(r#"{"error":"partial write: field type conflict: input field \"value\" "#,
+                        r#"on measurement \"cpu\" is type float, already exists as type "#,
+                        r#"integer dropped=1"}"#);
+    let partial = PartialWrite::from_response(400, error, body).unwrap();
+    assert_eq!(partial.rejected.iter().map(|r| r.line).collect::<Vec<_>>(), vec!(Some(1)));
+    assert_eq!(partial.dropped, Some(1));
+    assert!(partial.written);
+
+    let error = r#"{"error":"unable to parse 'mem free=': missing field value"}"#;
+    let partial = PartialWrite::from_response(400, error, "cpu value=1i 1\nmem free=\n").unwrap();
+    assert_eq!(partial.rejected, vec!(RejectedLine {
+        line: Some(1),
+        reason: String::from_str("unable to parse 'mem free=': missing field value")
+    }));
+    assert!(!partial.written);
+    assert_eq!(partial.accepted_lines("cpu value=1i 1\nmem free=\n"),
+               Some(String::from_str("cpu value=1i 1\n")));
+    assert_eq!(PartialWrite::from_response(500, error, body), None);
+}
+
+#[test]
+fn rejects_every_copy_of_a_line() {
+    let body = "mem free=\ncpu value=1i 1\nmem free=\n";
+    let error = r#"{"error":"unable to parse 'mem free=': missing field value"}"#;
+    let partial = PartialWrite::from_response(400, error, body).unwrap();
+    assert_eq!(partial.rejected.iter().map(|r| r.line).collect::<Vec<_>>(),
+               vec!(Some(0), Some(2)));
+    assert_eq!(partial.accepted_lines(body), Some(String::from_str("cpu value=1i 1\n")));
+    let error = r#"{"error":"unable to parse 'disk used=': missing field value"}"#;
+    let partial = PartialWrite::from_response(400, error, body).unwrap();
+    assert_eq!(partial.rejected[0].line, None);
+    assert_eq!(partial.accepted_lines(body), None);
+}
@@ -0,0 +1,316 @@
+//! Construction of data points, and conversion between user types and data
+//! points
+//!
+//! Points can be built up fluently with `Point::measurement`.
+//!
+//! Anything `Encodable` can be turned into a `DataPoint`, and anything
+//! `Decodable` can be read back out of one. Members are mapped by name: a
+//! member called `time` holds the timestamp in nanoseconds since the epoch,
+//! members named as tags become tags, everything else becomes a field.
+
+use std::collections::{BTreeMap, HashMap};
+use time;
+use time::Timespec;
+use rustc_serialize::{Encodable, Decodable};
+use rustc_serialize::json::{self, Json};
+use client::{DataPoint, FieldValue};
+use lineproto;
+use lineproto::Precision;
+use timestamp::{ToTimestamp, from_nanos, to_nanos};
+
+/// Name of the member holding the timestamp
+const TIME: &'static str = "time";
+
+/// Types that can be written as a point
+pub trait IntoPoint {
+    /// Convert to a point, with the members named in `tags` becoming tags
+    fn into_point(&self, tags: &[&str]) -> Result<DataPoint, String>;
+}
+
+/// Types that can be read from a point
+pub trait FromPoint {
+    /// Read a value from the tags, fields and timestamp of a point
+    fn from_point(point: &DataPoint) -> Result<Self, String>;
+}
+
+impl<T: Encodable> IntoPoint for T {
+    fn into_point(&self, tags: &[&str]) -> Result<DataPoint, String> {
+        let members = match Json::from_str(json::encode(self).as_slice()) {
+            Ok(Json::Object(members)) => members,
+            Ok(_) => return Err(String::from_str("Value must encode as a struct")),
+            Err(e) => return Err(format!("{:?}", e))
+        };
+        let mut point = DataPoint {
+            time: time::get_time(),
+            tags: vec!(),
+            fields: vec!()
+        };
+        for (name, value) in members.into_iter() {
+            if name.as_slice() == TIME {
+                point.time = match value {
+                    Json::I64(ns) => from_nanos(ns),
+                    Json::U64(ns) => from_nanos(ns as i64),
+                    _ => return Err(String::from_str("time must be an integer"))
+                };
+            } else if tags.contains(&name.as_slice()) {
+                let value = match value {
+                    Json::String(s) => s,
+                    Json::I64(i) => i.to_string(),
+                    Json::U64(u) => u.to_string(),
+                    Json::Boolean(b) => b.to_string(),
+                    _ => return Err(format!("Tag {} must be a string or integer", name))
+                };
+                point.tags.push((name, value));
+            } else {
+                let value = match value {
+                    Json::I64(i) => FieldValue::Integer(i),
+                    Json::U64(u) => FieldValue::UInteger(u),
+                    Json::F64(f) => FieldValue::Float(f),
+                    Json::Boolean(b) => FieldValue::Boolean(b),
+                    Json::String(s) => FieldValue::Text(s),
+                    // missing optional fields are left out of the point
+                    Json::Null => continue,
+                    _ => return Err(format!("Field {} must be a scalar", name))
+                };
+                point.fields.push((name, value));
+            }
+        }
+        Ok(point)
+    }
+}
+
+impl<T: Decodable> FromPoint for T {
+    fn from_point(point: &DataPoint) -> Result<T, String> {
+        let mut members = BTreeMap::new();
+        members.insert(String::from_str(TIME),
+                       Json::I64(to_nanos(&point.time)));
+        for &(ref name, ref value) in point.tags.iter() {
+            members.insert(name.clone(), Json::String(value.clone()));
+        }
+        for &(ref name, ref value) in point.fields.iter() {
+            members.insert(name.clone(), match *value {
+                FieldValue::Integer(i) => Json::I64(i),
+                FieldValue::UInteger(u) => Json::U64(u),
+                FieldValue::Float(f) => Json::F64(f),
+                FieldValue::Boolean(b) => Json::Boolean(b),
+                FieldValue::Text(ref s) => Json::String(s.clone())
+            });
+        }
+        let mut decoder = json::Decoder::new(Json::Object(members));
+        Decodable::decode(&mut decoder).map_err(|e| format!("{:?}", e))
+    }
+}
+
+/// A data point together with the measurement it belongs to
+#[derive(Show, Clone)]
+pub struct Point {
+    pub measurement: String,
+    pub data: DataPoint
+}
+
+impl Point {
+    /// Start building a point of the given measurement
+    pub fn measurement(name: &str) -> PointBuilder {
+        PointBuilder {
+            measurement: String::from_str(name),
+            time: None,
+            tags: vec!(),
+            fields: vec!()
+        }
+    }
+
+    /// Serialize to a line of line protocol (without trailing newline)
+    pub fn to_line(&self, precision: Precision) -> String {
+        lineproto::serialize_point(self.measurement.as_slice(), &self.data, precision)
+    }
+}
+
+/// Values that can be stored in a field
+pub trait ToFieldValue {
+    fn to_field_value(&self) -> FieldValue;
+}
+
+impl ToFieldValue for i64 {
+    fn to_field_value(&self) -> FieldValue { FieldValue::Integer(*self) }
+}
+
+impl ToFieldValue for i32 {
+    fn to_field_value(&self) -> FieldValue { FieldValue::Integer(*self as i64) }
+}
+
+impl ToFieldValue for u64 {
+    fn to_field_value(&self) -> FieldValue { FieldValue::UInteger(*self) }
+}
+
+impl ToFieldValue for u32 {
+    fn to_field_value(&self) -> FieldValue { FieldValue::UInteger(*self as u64) }
+}
+
+impl ToFieldValue for f64 {
+    fn to_field_value(&self) -> FieldValue { FieldValue::Float(*self) }
+}
+
+impl ToFieldValue for bool {
+    fn to_field_value(&self) -> FieldValue { FieldValue::Boolean(*self) }
+}
+
+impl<'a> ToFieldValue for &'a str {
+    fn to_field_value(&self) -> FieldValue { FieldValue::Text(String::from_str(*self)) }
+}
+
+impl ToFieldValue for String {
+    fn to_field_value(&self) -> FieldValue { FieldValue::Text(self.clone()) }
+}
+
+impl ToFieldValue for FieldValue {
+    fn to_field_value(&self) -> FieldValue { self.clone() }
+}
+
+/// Builds up a `Point`
+///
+/// Names and values are escaped when the point is serialized, so they can
+/// contain any characters
+#[derive(Show, Clone)]
+pub struct PointBuilder {
+    measurement: String,
+    time: Option<Timespec>,
+    tags: Vec<(String, String)>,
+    fields: Vec<(String, FieldValue)>
+}
+
+impl PointBuilder {
+    /// Add a tag
+    pub fn tag(mut self, key: &str, value: &str) -> PointBuilder {
+        self.tags.push((String::from_str(key), String::from_str(value)));
+        self
+    }
+
+    /// Add a field
+    pub fn field<V: ToFieldValue>(mut self, key: &str, value: V) -> PointBuilder {
+        self.fields.push((String::from_str(key), value.to_field_value()));
+        self
+    }
+
+    /// Set the timestamp - default is the time `build` is called
+    pub fn timestamp<T: ToTimestamp>(mut self, time: T) -> PointBuilder {
+        self.time = Some(time.to_timespec());
+        self
+    }
+
+    /// Check the point is valid and build it
+    pub fn build(self) -> Result<Point, String> {
+        if self.measurement.is_empty() {
+            return Err(String::from_str("Measurement name must not be empty"));
+        }
+        if self.fields.is_empty() {
+            return Err(format!("Point for {} must have at least one field",
+                               self.measurement));
+        }
+        if self.tags.iter().any(|&(ref k, ref v)| k.is_empty() || v.is_empty()) ||
+           self.fields.iter().any(|&(ref k, _)| k.is_empty()) {
+            return Err(format!("Point for {} has an empty tag or field name",
+                               self.measurement));
+        }
+        Ok(Point {
+            measurement: self.measurement,
+            data: DataPoint {
+                time: self.time.unwrap_or_else(time::get_time),
+                tags: self.tags,
+                fields: self.fields
+            }
+        })
+    }
+}
+
+/// Key identifying the series and timestamp of a point - two points with
+/// the same key overwrite each other on the server
+pub fn series_key(measurement: &str, point: &DataPoint) -> String {
+    let mut tags = point.tags.clone();
+    tags.sort();
+    let mut key = lineproto::escape_measurement(measurement);
+    for &(ref k, ref v) in tags.iter() {
+        key.push(',');
+        key.push_str(lineproto::escape_key(k.as_slice()).as_slice());
+        key.push('=');
+        key.push_str(lineproto::escape_key(v.as_slice()).as_slice());
+    }
+    key.push(' ');
+    key.push_str(lineproto::timestamp(&point.time, Precision::Nanoseconds)
+                 .to_string().as_slice());
+    key
+}
+
+/// Merge items whose key is repeated in the batch into the first of them,
+/// folding each later one in with `merge`
+pub fn dedup<T, K, M>(items: Vec<T>, key: K, merge: M) -> Vec<T>
+    where K: Fn(&T) -> String, M: Fn(&mut T, T) {
+    let mut first = HashMap::new();
+    let mut merged: Vec<T> = vec!();
+    for item in items.into_iter() {
+        let k = key(&item);
+        match first.get(&k).map(|&i| i) {
+            Some(i) => merge(&mut merged[i], item),
+            None => {
+                first.insert(k, merged.len());
+                merged.push(item);
+            }
+        }
+    }
+    merged
+}
+
+/// Merge the fields of `later`, a point of the same series and time, into
+/// `point` (matching the server, where a later point's fields overwrite
+/// those of an earlier one, and its other fields are kept)
+pub fn merge_fields(point: &mut DataPoint, later: DataPoint) {
+    for (name, value) in later.fields.into_iter() {
+        match point.fields.iter().position(|&(ref n, _)| *n == name) {
+            Some(pos) => point.fields[pos].1 = value,
+            None => point.fields.push((name, value))
+        }
+    }
+}
+
+#[test]
+fn merges_points_of_the_same_series() {
+    use client::FieldValue::{Integer, Float};
+    let point = |secs: i64, fields: Vec<(&str, FieldValue)>| DataPoint {
+        time: Timespec::new(secs, 0),
+        tags: vec!((String::from_str("host"), String::from_str("a"))),
+        fields: fields.into_iter().map(|(k, v)| (String::from_str(k), v)).collect()
+    };
+    let points = vec!(point(10, vec!(("idle", Integer(1)), ("busy", Integer(2)))),
+                      point(20, vec!(("idle", Integer(9)))),
+                      point(10, vec!(("idle", Integer(3)), ("load", Float(0.5)))));
+    let merged = dedup(points, |p| series_key("cpu", p), |p, later| merge_fields(p, later));
+    assert_eq!(merged.len(), 2);
+    assert_eq!(merged[0].fields, vec!((String::from_str("idle"), Integer(3)),
+                                      (String::from_str("busy"), Integer(2)),
+                                      (String::from_str("load"), Float(0.5))));
+    assert_eq!(merged[1].fields, vec!((String::from_str("idle"), Integer(9))));
+}
+
+#[test]
+fn builds_escaped_points() {
+    let point = Point::measurement("cpu load")
+        .tag("host name", "a,b=c")
+        .field("us=er", 3.5)
+        .field("note", "say \"hi\" \\o/")
+        .field("count", 2i64)
+        .timestamp(1_500_000_000i64)
+        .build().unwrap();
+    assert_eq!(point.to_line(Precision::Nanoseconds),
+               concat!(r#"cpu\ load,host\ name=a\,b\=c "#,
+                       r#"us\=er=3.5,note="say \"hi\" \\o/",count=2i 1500000000"#));
+    assert_eq!(point.to_line(Precision::Seconds).as_slice().split(' ').last(), Some("1"));
+}
+
+#[test]
+fn validates_points_before_building() {
+    assert!(Point::measurement("cpu").tag("host", "a").build().is_err());
+    assert!(Point::measurement("").field("value", 1i64).build().is_err());
+    assert!(Point::measurement("cpu").field("", 1i64).build().is_err());
+    assert!(Point::measurement("cpu").tag("host", "").field("value", 1i64).build().is_err());
+    assert!(Point::measurement("cpu").tag("", "a").field("value", 1i64).build().is_err());
+    assert!(Point::measurement("cpu").field("value", true).build().is_ok());
+}
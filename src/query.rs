@@ -0,0 +1,702 @@
+//! Results of queries, and parsing of the `/query` endpoint's JSON responses
+
+use std::collections::BTreeMap;
+use std::mem;
+use std::io::{self, Buffer};
+use std::num::Float;
+use std::time::duration::Duration;
+use rustc_serialize::Decodable;
+use rustc_serialize::json::{self, Json};
+use time::Timespec;
+use influxql;
+use lineproto::Precision;
+use schema::{FieldKey, FieldType};
+use timestamp;
+use error::InfluxError;
+use transport::Instance;
+
+/// A single value in a row of query results
+#[derive(Show, Clone, PartialEq)]
+pub enum Value {
+    Null,
+    Integer(i64),
+    Float(f64),
+    Boolean(bool),
+    Text(String),
+    /// A timestamp, for the `time` column of queries made with an epoch
+    Time(Timespec),
+    /// A list of values, e.g. the destinations of `SHOW SUBSCRIPTIONS`
+    Array(Vec<Value>)
+}
+
+impl Value {
+    fn from_json(json: &Json) -> Result<Value, String> {
+        Ok(match *json {
+            Json::Null => Value::Null,
+            Json::I64(i) => Value::Integer(i),
+            Json::U64(u) => Value::Integer(u as i64),
+            Json::F64(f) => Value::Float(f),
+            Json::Boolean(b) => Value::Boolean(b),
+            Json::String(ref s) => Value::Text(s.clone()),
+            Json::Array(ref values) => {
+                Value::Array(try!(values.iter().map(Value::from_json).collect()))
+            },
+            _ => return Err(format!("Unexpected value in results: {}", json))
+        })
+    }
+
+    fn to_json(&self) -> Json {
+        match *self {
+            Value::Null => Json::Null,
+            Value::Integer(i) => Json::I64(i),
+            Value::Float(f) => Json::F64(f),
+            Value::Boolean(b) => Json::Boolean(b),
+            Value::Text(ref s) => Json::String(s.clone()),
+            Value::Time(ref time) => Json::I64(timestamp::to_nanos(time)),
+            Value::Array(ref values) => Json::Array(values.iter().map(|v| v.to_json()).collect())
+        }
+    }
+}
+
+/// One series of a query result - rows of values under the given columns
+#[derive(Show, Clone, PartialEq)]
+pub struct Series {
+    pub name: String,
+    pub tags: Vec<(String, String)>,
+    pub columns: Vec<String>,
+    pub values: Vec<Vec<Value>>,
+    /// The server truncated the series, e.g. to its `max-row-limit`
+    pub partial: bool
+}
+
+impl Series {
+    /// Split the series into its rows
+    pub fn into_rows(self) -> Vec<Row> {
+        let Series { name, tags, columns, values, .. } = self;
+        values.into_iter().map(|values| {
+            Row {
+                name: name.clone(),
+                tags: tags.clone(),
+                columns: columns.clone(),
+                values: values
+            }
+        }).collect()
+    }
+
+    /// Set the rows to `rows`, each with the index of the result it came
+    /// from, sorted by time (if there is a time column)
+    ///
+    /// A row is dropped as a repeat only if another result has it too - a
+    /// row is kept as many times as the result repeating it most has it.
+    fn sort_dedup(&mut self, mut rows: Vec<(usize, Vec<Value>)>) {
+        self.values.clear();
+        let time = self.column_index("time");
+        if let Some(time) = time {
+            rows.sort_by(|a, b| time_key(&a.1[time]).cmp(&time_key(&b.1[time])));
+        }
+        // only rows at the same time can be repeats of each other: those
+        // seen so far, and where the ones kept start
+        let (mut same_time, mut kept_from) = (vec!(), 0);
+        for (source, row) in rows.into_iter() {
+            if let Some(time) = time {
+                let start = self.values.get(kept_from).map(|r| time_key(&r[time]));
+                if start != Some(time_key(&row[time])) {
+                    same_time.clear();
+                    kept_from = self.values.len();
+                }
+            }
+            let from_source = same_time.iter().filter(|&&(s, ref r)| s == source && *r == row)
+                                              .count();
+            let kept = self.values.slice_from(kept_from).iter().filter(|r| **r == row).count();
+            if from_source >= kept {
+                self.values.push(row.clone());
+            }
+            same_time.push((source, row));
+        }
+    }
+
+    /// Position of the named column in each row
+    pub fn column_index(&self, name: &str) -> Option<usize> {
+        self.columns.iter().position(|column| column.as_slice() == name)
+    }
+
+    /// Decode each row into a `T`, mapping columns (including `time`) and
+    /// tags to members of the same name
+    pub fn decode<T: Decodable>(&self) -> Result<Vec<T>, String> {
+        let mut rows = Vec::with_capacity(self.values.len());
+        for row in self.values.iter() {
+            let mut members = BTreeMap::new();
+            for &(ref key, ref value) in self.tags.iter() {
+                members.insert(key.clone(), Json::String(value.clone()));
+            }
+            for (column, value) in self.columns.iter().zip(row.iter()) {
+                members.insert(column.clone(), value.to_json());
+            }
+            let mut decoder = json::Decoder::new(Json::Object(members));
+            rows.push(try!(Decodable::decode(&mut decoder).map_err(|e| format!("{:?}", e))));
+        }
+        Ok(rows)
+    }
+}
+
+/// The time of a value in the `time` column, for ordering
+fn time_key(value: &Value) -> Option<Timespec> {
+    match *value {
+        Value::Time(time) => Some(time),
+        Value::Integer(ns) => Some(timestamp::from_nanos(ns)),
+        Value::Text(ref text) => timestamp::parse_rfc3339(text.as_slice()).ok(),
+        _ => None
+    }
+}
+
+/// The values of one column of a series, stored contiguously
+#[derive(Show, Clone, PartialEq)]
+pub enum Column {
+    /// Numeric columns, unless declared integers - nulls are NaN
+    Float(Vec<f64>),
+    /// Columns of fields declared integers, with no nulls
+    Integer(Vec<i64>),
+    Boolean(Vec<bool>),
+    Text(Vec<String>),
+    Time(Vec<Timespec>),
+    /// Columns with mixed types, or nulls in non-numeric columns
+    Mixed(Vec<Value>)
+}
+
+impl Column {
+    /// Lay out the values of a column whose field is of type `declared`,
+    /// if known
+    ///
+    /// Servers write floats with whole values without a fraction (`2` for
+    /// 2.0), so numeric columns are only taken for integers when declared.
+    fn from_values(values: Vec<&Value>, declared: Option<FieldType>) -> Column {
+        macro_rules! all {
+            ($variant:ident) => {{
+                let mut out = Vec::with_capacity(values.len());
+                for value in values.iter() {
+                    match **value {
+                        Value::$variant(ref v) => out.push(v.clone()),
+                        _ => break
+                    }
+                }
+                if out.len() == values.len() {
+                    return Column::$variant(out);
+                }
+            }}
+        }
+        match declared {
+            Some(FieldType::Integer) | Some(FieldType::UInteger) => all!(Integer),
+            _ => ()
+        }
+        all!(Boolean);
+        all!(Text);
+        all!(Time);
+        let numeric = values.iter().all(|v| match **v {
+            Value::Integer(_) | Value::Float(_) | Value::Null => true,
+            _ => false
+        });
+        if numeric {
+            return Column::Float(values.iter().map(|v| match **v {
+                Value::Integer(i) => i as f64,
+                Value::Float(f) => f,
+                _ => Float::nan()
+            }).collect());
+        }
+        Column::Mixed(values.into_iter().map(|v| v.clone()).collect())
+    }
+}
+
+/// Types a `Column` can be viewed as a slice of
+pub trait ColumnType {
+    fn slice(column: &Column) -> Option<&[Self]>;
+}
+
+macro_rules! column_type {
+    ($t:ty, $variant:ident) => {
+        impl ColumnType for $t {
+            fn slice(column: &Column) -> Option<&[$t]> {
+                match *column {
+                    Column::$variant(ref values) => Some(values.as_slice()),
+                    _ => None
+                }
+            }
+        }
+    }
+}
+
+column_type!(f64, Float);
+column_type!(i64, Integer);
+column_type!(bool, Boolean);
+column_type!(String, Text);
+column_type!(Timespec, Time);
+column_type!(Value, Mixed);
+
+/// A series laid out by column rather than by row
+#[derive(Show, Clone, PartialEq)]
+pub struct Columns {
+    pub name: String,
+    pub tags: Vec<(String, String)>,
+    pub names: Vec<String>,
+    pub columns: Vec<Column>
+}
+
+impl Columns {
+    /// Get the named column as a slice of `T`, or None if there is no such
+    /// column or it isn't of type `T`
+    pub fn column<T: ColumnType>(&self, name: &str) -> Option<&[T]> {
+        self.names.iter()
+            .position(|n| n.as_slice() == name)
+            .and_then(|i| ColumnType::slice(&self.columns[i]))
+    }
+
+    /// Get the named column in whatever type it has
+    pub fn raw_column(&self, name: &str) -> Option<&Column> {
+        self.names.iter()
+            .position(|n| n.as_slice() == name)
+            .map(|i| &self.columns[i])
+    }
+}
+
+impl Series {
+    /// Lay the series out by column, numeric columns as floats
+    pub fn columnar(&self) -> Columns {
+        self.columnar_typed(&[])
+    }
+
+    /// Lay the series out by column, columns of the fields declared integers
+    /// in `fields` (e.g. from `Database::show_field_keys`) as integers
+    pub fn columnar_typed(&self, fields: &[FieldKey]) -> Columns {
+        let columns = self.columns.iter().enumerate().map(|(i, name)| {
+            let declared = fields.iter().find(|field| field.name == *name)
+                .map(|field| field.field_type);
+            Column::from_values(self.values.iter().filter_map(|row| row.get(i)).collect(),
+                                declared)
+        }).collect();
+        Columns {
+            name: self.name.clone(),
+            tags: self.tags.clone(),
+            names: self.columns.clone(),
+            columns: columns
+        }
+    }
+}
+
+/// The result of a query statement
+#[derive(Show, Clone, PartialEq)]
+pub struct QueryResult {
+    pub series: Vec<Series>
+}
+
+impl QueryResult {
+    /// Combine the results of the same query from several servers
+    ///
+    /// Series with the same name, tags and columns are merged and ordered by
+    /// time, dropping rows repeated between servers - but not rows a single
+    /// server repeats
+    pub fn merge(results: Vec<QueryResult>) -> QueryResult {
+        let mut merged: Vec<(Series, Vec<(usize, Vec<Value>)>)> = vec!();
+        for (source, result) in results.into_iter().enumerate() {
+            for mut series in result.series.into_iter() {
+                let rows = mem::replace(&mut series.values, vec!());
+                let rows = rows.into_iter().map(|row| (source, row));
+                let position = merged.iter().position(|&(ref m, _)| {
+                    m.name == series.name && m.tags == series.tags && m.columns == series.columns
+                });
+                match position {
+                    Some(i) => {
+                        merged[i].0.partial |= series.partial;
+                        merged[i].1.extend(rows);
+                    },
+                    None => merged.push((series, rows.collect()))
+                }
+            }
+        }
+        QueryResult {
+            series: merged.into_iter().map(|(mut series, rows)| {
+                series.sort_dedup(rows);
+                series
+            }).collect()
+        }
+    }
+
+    /// Lay every series out by column, see `Series::columnar`
+    pub fn columnar(&self) -> Vec<Columns> {
+        self.series.iter().map(|series| series.columnar()).collect()
+    }
+
+    /// Lay every series out by column, see `Series::columnar_typed`
+    pub fn columnar_typed(&self, fields: &[FieldKey]) -> Vec<Columns> {
+        self.series.iter().map(|series| series.columnar_typed(fields)).collect()
+    }
+
+    /// Decode the rows of every series into `T`s, see `Series::decode`
+    pub fn decode<T: Decodable>(&self) -> Result<Vec<T>, String> {
+        let mut rows = vec!();
+        for series in self.series.iter() {
+            rows.extend(try!(series.decode()).into_iter());
+        }
+        Ok(rows)
+    }
+}
+
+/// A row of query results, with the series it belongs to
+#[derive(Show, Clone, PartialEq)]
+pub struct Row {
+    pub name: String,
+    pub tags: Vec<(String, String)>,
+    pub columns: Vec<String>,
+    pub values: Vec<Value>
+}
+
+/// A query running on the server, as listed by `SHOW QUERIES`
+#[derive(Show, Clone, PartialEq)]
+pub struct RunningQuery {
+    /// Id to pass to `Influx::kill_query`
+    pub id: u64,
+    pub query: String,
+    pub database: String,
+    /// How long the query has been running for, e.g. `1m12s`
+    pub duration: String
+}
+
+impl RunningQuery {
+    /// Read the rows of a `SHOW QUERIES` result
+    pub fn from_result(result: &QueryResult) -> Result<Vec<RunningQuery>, String> {
+        let mut queries = vec!();
+        for series in result.series.iter() {
+            let index = |name: &str| {
+                series.column_index(name)
+                    .ok_or(format!("SHOW QUERIES result has no {} column", name))
+            };
+            let (qid, query, database, duration) =
+                (try!(index("qid")), try!(index("query")),
+                 try!(index("database")), try!(index("duration")));
+            for row in series.values.iter() {
+                let text = |i: usize| match row[i] {
+                    Value::Text(ref text) => text.clone(),
+                    _ => String::new()
+                };
+                let id = match row[qid] {
+                    Value::Integer(id) if id >= 0 => id as u64,
+                    ref other => return Err(format!("Invalid query id: {:?}", other))
+                };
+                queries.push(RunningQuery {
+                    id: id,
+                    query: text(query),
+                    database: text(database),
+                    duration: text(duration)
+                });
+            }
+        }
+        Ok(queries)
+    }
+
+    /// How long the query has been running for, None if the server's
+    /// duration isn't a duration literal
+    pub fn elapsed(&self) -> Option<Duration> {
+        influxql::parse_duration_literal(self.duration.as_slice())
+    }
+}
+
+/// Encode bound parameters as the JSON object expected in the `params`
+/// query parameter
+pub fn encode_params(params: &[(String, Value)]) -> String {
+    let mut object = BTreeMap::new();
+    for &(ref name, ref value) in params.iter() {
+        object.insert(name.clone(), value.to_json());
+    }
+    Json::Object(object).to_string()
+}
+
+/// Iterator over the chunks of a chunked query response, parsing each as it
+/// is read
+pub struct Chunks<R> {
+    reader: R,
+    done: bool
+}
+
+impl<R: Buffer> Chunks<R> {
+    pub fn new(reader: R) -> Chunks<R> {
+        Chunks {
+            reader: reader,
+            done: false
+        }
+    }
+}
+
+impl<R: Buffer> Iterator for Chunks<R> {
+    type Item = Result<QueryResult, InfluxError>;
+
+    fn next(&mut self) -> Option<Result<QueryResult, InfluxError>> {
+        if self.done {
+            return None;
+        }
+        loop {
+            match self.reader.read_line() {
+                Ok(line) => {
+                    if line.as_slice().trim().is_empty() {
+                        continue;
+                    }
+                    let chunk = parse(line, None);
+                    self.done = chunk.is_err();
+                    return Some(chunk);
+                },
+                Err(ref e) if e.kind == io::EndOfFile => {
+                    self.done = true;
+                    return None;
+                },
+                Err(e) => {
+                    self.done = true;
+                    return Some(Err(InfluxError::Request(format!("{}", e))));
+                }
+            }
+        }
+    }
+}
+
+/// Options for a query
+#[derive(Show, Clone, Default)]
+pub struct QueryOptions {
+    /// Values bound by the server to the `$name` placeholders in the query
+    pub params: Vec<(String, Value)>,
+    /// Have the server return timestamps as integers of this precision, which
+    /// are parsed as `Value::Time` - default None (RFC3339 strings, parsed as
+    /// `Value::Text`)
+    pub epoch: Option<Precision>,
+    /// Send the query to this instance while it is available, e.g. to keep
+    /// the queries for the pages of a result on one instance - default None
+    pub instance: Option<Instance>
+}
+
+impl QueryOptions {
+    /// Query parameters for the query endpoint
+    pub fn query(&self) -> Vec<(String, String)> {
+        let mut query = vec!();
+        if !self.params.is_empty() {
+            query.push((String::from_str("params"), encode_params(&self.params)));
+        }
+        if let Some(epoch) = self.epoch {
+            query.push((String::from_str("epoch"), epoch.epoch_name()));
+        }
+        query
+    }
+}
+
+/// The result of one statement of a multi-statement query
+#[derive(Show, Clone, PartialEq)]
+pub struct StatementResult {
+    /// Position of the statement in the query, from 0
+    pub statement_id: usize,
+    /// The statement's result, or the error the server reported for it
+    pub result: Result<QueryResult, InfluxError>
+}
+
+/// Parse a `/query` response body, returning the result of the first (only)
+/// statement
+///
+/// `epoch` must match the epoch the query was made with
+pub fn parse(body: String, epoch: Option<Precision>) -> Result<QueryResult, InfluxError> {
+    let mut results = try!(parse_all(body, epoch));
+    if results.is_empty() {
+        return Err(InfluxError::Parse(String::from_str("Response has no results")));
+    }
+    results.swap_remove(0).result
+}
+
+/// The error of a query the server rejected with a 4xx other than 401 and
+/// 403, e.g. for a syntax error, as `InfluxError::Query` - other errors are
+/// returned as they are
+pub fn rejected(error: InfluxError) -> InfluxError {
+    let message = match error {
+        InfluxError::Status(400...499, ref body) => {
+            Json::from_str(body.as_slice()).ok().and_then(|json| {
+                json.find("error").and_then(|e| e.as_string()).map(String::from_str)
+            })
+        },
+        _ => None
+    };
+    match message {
+        Some(message) => InfluxError::Query(message),
+        None => error
+    }
+}
+
+/// Parse a `/query` response body, returning the results of all statements
+/// ordered by statement id
+///
+/// Only fails as a whole if the entire request failed, errors in individual
+/// statements are reported in their results
+pub fn parse_all(body: String,
+                 epoch: Option<Precision>) -> Result<Vec<StatementResult>, InfluxError> {
+    let json = try!(Json::from_str(body.as_slice())
+                    .map_err(|e| InfluxError::Parse(format!("{:?}", e))));
+    if let Some(error) = json.find("error").and_then(|e| e.as_string()) {
+        return Err(InfluxError::Query(String::from_str(error)));
+    }
+    let results = try!(json.find("results").and_then(|r| r.as_array())
+                       .ok_or(InfluxError::Parse(String::from_str("Response has no results"))));
+    let mut statements = Vec::with_capacity(results.len());
+    for (i, result) in results.iter().enumerate() {
+        let statement_id = result.find("statement_id")
+            .and_then(|id| id.as_u64())
+            .map(|id| id as usize)
+            .unwrap_or(i);
+        statements.push(StatementResult {
+            statement_id: statement_id,
+            result: parse_result(result, statement_id, epoch)
+        });
+    }
+    statements.sort_by(|a, b| a.statement_id.cmp(&b.statement_id));
+    Ok(statements)
+}
+
+/// Parse the result of one statement
+fn parse_result(result: &Json, statement_id: usize,
+                epoch: Option<Precision>) -> Result<QueryResult, InfluxError> {
+    if let Some(error) = result.find("error").and_then(|e| e.as_string()) {
+        return Err(InfluxError::Statement(statement_id, String::from_str(error)));
+    }
+    let mut series = vec!();
+    if let Some(list) = result.find("series").and_then(|s| s.as_array()) {
+        for s in list.iter() {
+            series.push(try!(parse_series(s, epoch).map_err(InfluxError::Parse)));
+        }
+    }
+    Ok(QueryResult {
+        series: series
+    })
+}
+
+fn parse_series(json: &Json, epoch: Option<Precision>) -> Result<Series, String> {
+    let name = json.find("name").and_then(|n| n.as_string()).unwrap_or("");
+    let mut tags = vec!();
+    if let Some(object) = json.find("tags").and_then(|t| t.as_object()) {
+        for (key, value) in object.iter() {
+            tags.push((key.clone(), String::from_str(value.as_string().unwrap_or(""))));
+        }
+    }
+    let mut columns = vec!();
+    if let Some(list) = json.find("columns").and_then(|c| c.as_array()) {
+        for column in list.iter() {
+            columns.push(String::from_str(column.as_string().unwrap_or("")));
+        }
+    }
+    let time_column = match epoch {
+        Some(_) => columns.iter().position(|c| c.as_slice() == "time"),
+        None => None
+    };
+    let mut values = vec!();
+    if let Some(rows) = json.find("values").and_then(|v| v.as_array()) {
+        for row in rows.iter() {
+            let row = try!(row.as_array().ok_or(String::from_str("Row is not an array")));
+            let mut parsed = Vec::with_capacity(row.len());
+            for (i, value) in row.iter().enumerate() {
+                let value = match (time_column, epoch, value) {
+                    (Some(column), Some(precision), &Json::I64(t)) if column == i => {
+                        Value::Time(timestamp::from_nanos(t * precision.nanos()))
+                    },
+                    (Some(column), Some(precision), &Json::U64(t)) if column == i => {
+                        Value::Time(timestamp::from_nanos(t as i64 * precision.nanos()))
+                    },
+                    _ => try!(Value::from_json(value))
+                };
+                parsed.push(value);
+            }
+            values.push(parsed);
+        }
+    }
+    Ok(Series {
+        name: String::from_str(name),
+        tags: tags,
+        columns: columns,
+        values: values,
+        partial: json.find("partial").and_then(|p| p.as_boolean()).unwrap_or(false)
+    })
+}
+
+#[test]
+fn parses_series() {
+    let body = String::from_str(r#"{"results":[{"statement_id":0,"series":[
+        {"name":"cpu","tags":{"host":"a"},"columns":["time","value"],
+         "values":[["2015-01-29T21:55:43.702900257Z",2],[null,0.5]]}]}]}"#);
+    let result = parse(body, None).unwrap();
+    assert_eq!(result.series[0].name, "cpu");
+    assert_eq!(result.series[0].tags, vec!((String::from_str("host"), String::from_str("a"))));
+    assert_eq!(result.series[0].values[1], vec!(Value::Null, Value::Float(0.5)));
+}
+
+#[test]
+fn lays_out_columns() {
+    let series = Series {
+        name: String::from_str("cpu"),
+        tags: vec!(),
+        columns: vec!(String::from_str("n"), String::from_str("usage")),
+        values: vec!(vec!(Value::Integer(1), Value::Integer(2)),
+                     vec!(Value::Integer(3), Value::Float(0.5))),
+        partial: false
+    };
+    let columns = series.columnar();
+    // whole floats come back without a fraction, so `n` may be floats
+    assert_eq!(columns.column::<f64>("n"), Some([1.0, 3.0].as_slice()));
+    assert_eq!(columns.column::<f64>("usage"), Some([2.0, 0.5].as_slice()));
+    assert_eq!(columns.column::<i64>("n"), None);
+    let fields = vec!(FieldKey { name: String::from_str("n"), field_type: FieldType::Integer },
+                      FieldKey { name: String::from_str("usage"), field_type: FieldType::Float });
+    let columns = series.columnar_typed(fields.as_slice());
+    assert_eq!(columns.column::<i64>("n"), Some([1, 3].as_slice()));
+    assert_eq!(columns.column::<f64>("usage"), Some([2.0, 0.5].as_slice()));
+}
+
+#[test]
+fn reports_statement_errors() {
+    let body = String::from_str(r#"{"results":[{"statement_id":0,
+        "error":"database not found: nope"}]}"#);
+    assert_eq!(parse(body, None),
+               Err(InfluxError::Statement(0, String::from_str("database not found: nope"))));
+    let body = String::from_str(r#"{"error":"error parsing query: found EOF"}"#);
+    assert_eq!(parse(body, None),
+               Err(InfluxError::Query(String::from_str("error parsing query: found EOF"))));
+}
+
+#[test]
+fn reports_rejected_queries() {
+    let body = String::from_str(r#"{"error":"error parsing query: found EOF"}"#);
+    assert_eq!(rejected(InfluxError::Status(400, body)),
+               InfluxError::Query(String::from_str("error parsing query: found EOF")));
+    let body = String::from_str(r#"{"error":"timeout"}"#);
+    assert_eq!(rejected(InfluxError::Status(500, body.clone())), InfluxError::Status(500, body));
+    let body = String::from_str("Bad Request");
+    assert_eq!(rejected(InfluxError::Status(400, body.clone())), InfluxError::Status(400, body));
+}
+
+#[test]
+fn merges_results() {
+    let body = |rows: &str| format!(r#"{{"results":[{{"series":[
+        {{"name":"cpu","columns":["time","value"],"values":[{}]}}]}}]}}"#, rows);
+    let a = parse(body(r#"["2015-01-01T00:00:02Z",2],["2015-01-01T00:00:00Z",0]"#), None);
+    let b = parse(body(r#"["2015-01-01T00:00:01Z",1],["2015-01-01T00:00:02Z",2]"#), None);
+    let merged = QueryResult::merge(vec!(a.unwrap(), b.unwrap()));
+    assert_eq!(merged.series.len(), 1);
+    let values: Vec<&Value> = merged.series[0].values.iter().map(|row| &row[1]).collect();
+    assert_eq!(values, vec!(&Value::Integer(0), &Value::Integer(1), &Value::Integer(2)));
+
+    // a server's own repeated rows are its answer, not duplicates
+    let a = parse(body(r#"["2015-01-01T00:00:00Z",1],["2015-01-01T00:00:00Z",1]"#), None);
+    let b = parse(body(r#"["2015-01-01T00:00:00Z",1]"#), None);
+    let merged = QueryResult::merge(vec!(a.unwrap(), b.unwrap()));
+    assert_eq!(merged.series[0].values.len(), 2);
+    let single = parse(body(r#"["2015-01-01T00:00:00Z",1],["2015-01-01T00:00:00Z",1]"#), None);
+    assert_eq!(QueryResult::merge(vec!(single.unwrap())).series[0].values.len(), 2);
+}
+
+#[test]
+fn lists_running_queries() {
+    let body = String::from_str(r#"{"results":[{"series":[{
+        "columns":["qid","query","database","duration","status"],
+        "values":[[37,"SELECT * FROM cpu","metrics","1m12s","running"]]}]}]}"#);
+    let queries = RunningQuery::from_result(&parse(body, None).unwrap()).unwrap();
+    assert_eq!(queries.len(), 1);
+    assert_eq!(queries[0].id, 37);
+    assert_eq!(queries[0].database, "metrics");
+    assert_eq!(queries[0].elapsed(), Some(Duration::seconds(72)));
+}
@@ -0,0 +1,156 @@
+//! A bounded, blocking queue with a configurable overflow policy
+
+use std::collections::RingBuf;
+use std::default::Default;
+use std::sync::{Mutex, Condvar};
+
+/// What to do when pushing onto a full queue
+#[derive(Show, Clone, Copy, PartialEq)]
+pub enum OverflowPolicy {
+    /// Wait until there is space
+    Block,
+    /// Discard the item being pushed
+    DropNewest,
+    /// Discard the item at the front of the queue to make space
+    DropOldest,
+    /// Hand the item back to the caller
+    Error
+}
+
+impl Default for OverflowPolicy {
+    fn default() -> OverflowPolicy {
+        OverflowPolicy::Block
+    }
+}
+
+struct State<T> {
+    /// The items, each with whether it was pushed unbounded
+    items: RingBuf<(T, bool)>,
+    /// The number of items pushed subject to the capacity
+    bounded: usize,
+    closed: bool
+}
+
+/// A queue holding at most `capacity` items, shared between threads by
+/// reference (e.g. in an `Arc`)
+///
+/// Items pushed with `push_unbounded`, e.g. control messages, don't count
+/// towards the capacity, and are never dropped to make room.
+pub struct BoundedQueue<T> {
+    state: Mutex<State<T>>,
+    not_empty: Condvar,
+    not_full: Condvar,
+    capacity: usize,
+    policy: OverflowPolicy
+}
+
+impl<T: Send> BoundedQueue<T> {
+    /// Panics if `capacity` is 0, as nothing could ever be pushed
+    pub fn new(capacity: usize, policy: OverflowPolicy) -> BoundedQueue<T> {
+        assert!(capacity > 0, "A queue needs a capacity of at least 1");
+        BoundedQueue {
+            state: Mutex::new(State {
+                items: RingBuf::with_capacity(capacity),
+                bounded: 0,
+                closed: false
+            }),
+            not_empty: Condvar::new(),
+            not_full: Condvar::new(),
+            capacity: capacity,
+            policy: policy
+        }
+    }
+
+    /// Push an item, applying the overflow policy if the queue is full
+    ///
+    /// The item is handed back if it was refused (`OverflowPolicy::Error`) or
+    /// the queue has been closed
+    pub fn push(&self, item: T) -> Result<(), T> {
+        let mut state = self.state.lock().unwrap();
+        while !state.closed && state.bounded >= self.capacity {
+            match self.policy {
+                OverflowPolicy::Block => {
+                    state = self.not_full.wait(state).unwrap();
+                },
+                OverflowPolicy::DropNewest => return Ok(()),
+                OverflowPolicy::DropOldest => {
+                    let oldest = state.items.iter().position(|&(_, unbounded)| !unbounded);
+                    if let Some(oldest) = oldest {
+                        state.items.remove(oldest);
+                        state.bounded -= 1;
+                    }
+                },
+                OverflowPolicy::Error => return Err(item)
+            }
+        }
+        if state.closed {
+            return Err(item);
+        }
+        state.items.push_back((item, false));
+        state.bounded += 1;
+        self.not_empty.notify_one();
+        Ok(())
+    }
+
+    /// Push an item regardless of capacity, for control messages that must
+    /// not be dropped or block
+    pub fn push_unbounded(&self, item: T) -> Result<(), T> {
+        let mut state = self.state.lock().unwrap();
+        if state.closed {
+            return Err(item);
+        }
+        state.items.push_back((item, true));
+        self.not_empty.notify_one();
+        Ok(())
+    }
+
+    /// Take the item at the front of the queue, waiting for one if empty
+    ///
+    /// Returns None once the queue is closed and drained
+    pub fn pop(&self) -> Option<T> {
+        let mut state = self.state.lock().unwrap();
+        loop {
+            if let Some((item, unbounded)) = state.items.pop_front() {
+                if !unbounded {
+                    state.bounded -= 1;
+                    self.not_full.notify_one();
+                }
+                return Some(item);
+            }
+            if state.closed {
+                return None;
+            }
+            state = self.not_empty.wait(state).unwrap();
+        }
+    }
+
+    /// Number of items currently queued
+    pub fn len(&self) -> usize {
+        self.state.lock().unwrap().items.len()
+    }
+
+    /// Whether the queue has been closed
+    pub fn is_closed(&self) -> bool {
+        self.state.lock().unwrap().closed
+    }
+
+    /// Refuse any further pushes, waking anyone blocked on the queue
+    pub fn close(&self) {
+        self.state.lock().unwrap().closed = true;
+        self.not_empty.notify_all();
+        self.not_full.notify_all();
+    }
+}
+
+#[test]
+fn never_drops_unbounded_items() {
+    let queue = BoundedQueue::new(2, OverflowPolicy::DropOldest);
+    queue.push_unbounded("flush").unwrap();
+    for item in ["a", "b", "c"].iter() {
+        queue.push(*item).unwrap();
+    }
+    queue.push_unbounded("stop").unwrap();
+    queue.close();
+    let items: Vec<&str> = (0..4).filter_map(|_| queue.pop()).collect();
+    assert_eq!(items, vec!("flush", "b", "c", "stop"));
+}
@@ -0,0 +1,242 @@
+//! Non-blocking requests on a tokio reactor (feature `tokio`)
+//!
+//! A minimal HTTP/1.1 client over `tokio_core`'s `TcpStream`, used by a
+//! `Cluster` once it has been given a reactor. Each request gets its own
+//! connection, which the server closes after responding. Only plain HTTP is
+//! supported on the reactor.
+
+use std::ascii::AsciiExt;
+use std::error::FromError;
+use std::str;
+use std::sync::Arc;
+use futures::{future, Future};
+use futures::sync::oneshot::{self, Canceled};
+use hyper::method::Method;
+use tokio_core::net::TcpStream;
+use tokio_core::reactor::{self, Handle, Remote};
+use tokio_io::io::{read_to_end, write_all};
+use url::percent_encoding::{percent_encode, DEFAULT_ENCODE_SET};
+use url::form_urlencoded;
+use time;
+use client::RequestFuture;
+use trace::AttemptSpan;
+use transport::{self, Cluster, Failure, Instance, Request, RequestError, Response, Scheme,
+                Timeout};
+
+type BoxFuture<T> = Box<Future<Item = T, Error = Failure>>;
+
+/// A failed future
+fn fail<T: 'static>(error: String) -> BoxFuture<T> {
+    Box::new(future::err(Failure::Error(error)))
+}
+
+/// Fail `work` with `timeout` if it isn't done within the timeout's limit
+fn within<T: 'static>(work: BoxFuture<T>, timeout: Option<Timeout>,
+                      handle: &Handle) -> BoxFuture<T> {
+    let timeout = match timeout {
+        Some(timeout) => timeout,
+        None => return work
+    };
+    let timer = match reactor::Timeout::new(transport::std_duration(timeout.limit()), handle) {
+        Ok(timer) => timer,
+        Err(e) => return fail(format!("{}", e))
+    };
+    let expired = timer.then(move |_| -> Result<T, Failure> { Err(Failure::Timeout(timeout)) });
+    Box::new(work.select(expired).map(|(value, _)| value).map_err(|(error, _)| error))
+}
+
+/// The request as sent on the wire
+fn encode(instance: &Instance, request: &Request) -> Vec<u8> {
+    let mut path = String::new();
+    for segment in request.path.iter() {
+        path.push('/');
+        path.push_str(percent_encode(segment.as_bytes(), DEFAULT_ENCODE_SET).as_slice());
+    }
+    let method = match request.method {
+        Method::Get => "GET",
+        Method::Post => "POST",
+        Method::Put => "PUT",
+        Method::Delete => "DELETE",
+        _ => "GET"
+    };
+    let query = form_urlencoded::serialize_owned(request.query.as_slice());
+    let mut head = format!("{} {}?{} HTTP/1.1\r\nHost: {}:{}\r\nConnection: close\r\n",
+                           method, path, query, instance.host, instance.port);
+    for &(ref name, ref value) in request.headers.iter() {
+        head.push_str(format!("{}: {}\r\n", name, value).as_slice());
+    }
+    let body = request.body.clone().unwrap_or(String::new());
+    head.push_str(format!("Content-Length: {}\r\n\r\n", body.len()).as_slice());
+    let mut bytes = head.into_bytes();
+    bytes.extend(body.into_bytes().into_iter());
+    bytes
+}
+
+/// Decode a body sent with `Transfer-Encoding: chunked`
+fn decode_chunked(mut body: &[u8]) -> Result<Vec<u8>, String> {
+    let mut decoded = vec!();
+    loop {
+        let line_end = try!(body.windows(2).position(|w| w == b"\r\n")
+                            .ok_or(String::from_str("Truncated chunked body")));
+        let size = try!(str::from_utf8(&body[..line_end]).ok()
+                        .and_then(|line| line.split(';').next())
+                        .and_then(|size| usize::from_str_radix(size.trim(), 16).ok())
+                        .ok_or(String::from_str("Invalid chunk size")));
+        body = &body[line_end + 2..];
+        if size == 0 {
+            return Ok(decoded);
+        }
+        if body.len() < size + 2 {
+            return Err(String::from_str("Truncated chunked body"));
+        }
+        decoded.extend(body[..size].iter().cloned());
+        body = &body[size + 2..];
+    }
+}
+
+/// Parse a complete response
+fn parse_response(bytes: &[u8]) -> Result<Response, String> {
+    let head_end = try!(bytes.windows(4).position(|w| w == b"\r\n\r\n")
+                        .ok_or(String::from_str("Truncated response")));
+    let head = try!(str::from_utf8(&bytes[..head_end])
+                    .map_err(|_| String::from_str("Invalid response head")));
+    let mut lines = head.split("\r\n");
+    let status = try!(lines.next()
+                      .and_then(|line| line.split(' ').nth(1))
+                      .and_then(|code| code.parse().ok())
+                      .ok_or(String::from_str("Invalid status line")));
+    let headers: Vec<(String, String)> = lines.filter_map(|line| {
+        line.find(':').map(|colon| (String::from_str(line[..colon].trim()),
+                                    String::from_str(line[colon + 1..].trim())))
+    }).collect();
+    let mut response = Response { status: status, headers: headers, body: String::new() };
+    let body = &bytes[head_end + 4..];
+    let chunked = response.header("Transfer-Encoding")
+        .map(|te| te.eq_ignore_ascii_case("chunked"))
+        .unwrap_or(false);
+    let body = if chunked { try!(decode_chunked(body)) } else { body.to_vec() };
+    response.body = try!(transport::decode_body(response.header("Content-Encoding"), body));
+    Ok(response)
+}
+
+/// Send a request to an instance on the reactor, within the cluster's
+/// connect and request timeouts
+fn send(handle: &Handle, cluster: &Cluster, instance: &Instance,
+        request: &Request) -> BoxFuture<Response> {
+    if instance.scheme == Scheme::Https {
+        return Box::new(future::err(Failure::Misconfigured(
+            String::from_str("HTTPS is not supported on the reactor"))));
+    }
+    // resolution blocks, but addresses are cached between requests
+    let host = instance.host.to_string();
+    let resolver = cluster.resolver();
+    let address = match resolver.resolve(host.as_slice(), instance.port).ok()
+        .and_then(|addresses| addresses.first().cloned()) {
+        Some(address) => address,
+        None => {
+            return Box::new(future::err(Failure::Misconfigured(
+                format!("Can't resolve {}", instance.host))))
+        }
+    };
+    let port = instance.port;
+    let timeouts = cluster.timeouts();
+    let request = cluster.outgoing(instance, request, true);
+    let bytes = encode(instance, &request);
+    let connect = Box::new(TcpStream::connect(&address, handle).map_err(move |e| {
+        // the address may be stale
+        resolver.invalidate(host.as_slice(), port);
+        Failure::Error(format!("{}", e))
+    }));
+    let exchange_handle = handle.clone();
+    Box::new(within(connect, timeouts.connect.map(Timeout::Connect), handle)
+        .and_then(move |stream| {
+            let exchange = Box::new(write_all(stream, bytes)
+                .and_then(|(stream, _)| read_to_end(stream, vec!()))
+                .map_err(|e| Failure::Error(format!("{}", e))));
+            within(exchange, timeouts.request.map(Timeout::Request), &exchange_handle)
+        })
+        .and_then(|(_, bytes)| parse_response(bytes.as_slice()).map_err(Failure::Error)))
+}
+
+/// Perform a request, retrying against the next available instance
+/// according to the retry policy, and return the response body
+fn execute(cluster: Arc<Cluster>, handle: Handle, request: Arc<Request>,
+           retries: u32) -> BoxFuture<String> {
+    let instance = match cluster.get_instance(&*request) {
+        Some(instance) => instance,
+        None => return fail(String::from_str("No instances available"))
+    };
+    let start = time::precise_time_ns();
+    let _ = cluster.request_started(&instance, None);
+    let span = AttemptSpan::start(&instance, &*request, retries);
+    Box::new(send(&handle, &cluster, &instance, &request).then(move |result| -> BoxFuture<String> {
+        cluster.request_finished(&instance);
+        let (status, error, after) = match result {
+            Ok(res) => {
+                cluster.received(res.status, res.headers.as_slice(), start);
+                match res.status {
+                    200...299 => {
+                        cluster.forget_failovers(&instance);
+                        cluster.attempt_done(span, &instance, &*request, retries,
+                                             Some(res.status), None);
+                        return Box::new(future::ok(res.body));
+                    },
+                    status => {
+                        let after = transport::retry_after(&res);
+                        (Some(status), Failure::Status(status, res.body), after)
+                    }
+                }
+            },
+            Err(e) => (None, e, None)
+        };
+        cluster.attempt_done(span, &instance, &*request, retries, status, Some(&error));
+        if error.disables_instance() {
+            Cluster::disable_instance(&cluster, &instance);
+        }
+        if !error.is_retriable() {
+            return Box::new(future::err(error));
+        }
+        let delay = match cluster.retry_delay(retries + 1) {
+            Some(delay) => transport::std_duration(transport::later(delay, after)),
+            None => return Box::new(future::err(error))
+        };
+        match reactor::Timeout::new(delay, &handle) {
+            Ok(timer) => Box::new(timer.map_err(|e| Failure::Error(format!("{}", e)))
+                                  .and_then(move |_| {
+                                      execute(cluster, handle, request, retries + 1)
+                                  })),
+            Err(e) => fail(format!("{}", e))
+        }
+    }))
+}
+
+/// Perform a request on the reactor, within the cluster's deadline, handing
+/// the body to `parse`
+pub fn request<T, E, F>(cluster: Arc<Cluster>, remote: &Remote,
+                        request: Request, parse: F) -> RequestFuture<T, E>
+    where T: Send + 'static,
+          E: RequestError + Send + 'static,
+          F: FnOnce(String) -> Result<T, E> + Send + 'static
+{
+    let (complete, result) = oneshot::channel();
+    remote.spawn(move |handle| {
+        let deadline = cluster.timeouts().deadline.map(Timeout::Deadline);
+        let work = execute(cluster, handle.clone(), Arc::new(request), 0);
+        within(work, deadline, handle).then(move |body| {
+            let _ = complete.send(body.map_err(Failure::into_error).and_then(parse));
+            Ok::<(), ()>(())
+        })
+    });
+    Box::new(result.then(|result| match result {
+        Ok(result) => result,
+        Err(Canceled) => Err(FromError::from_error(String::from_str("Reactor shut down")))
+    }))
+}
+
+#[test]
+fn parses_chunked_response() {
+    let response = parse_response(b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n\
+                                    5\r\nhello\r\n6\r\n world\r\n0\r\n\r\n").unwrap();
+    assert_eq!(response.status, 200);
+    assert_eq!(response.body, "hello world");
+}
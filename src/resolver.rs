@@ -0,0 +1,68 @@
+//! Cached resolution of instance hosts
+//!
+//! Addresses are kept for a TTL, and forgotten early when connecting to all
+//! of them fails, so a client pointed at a load-balanced DNS name follows the
+//! backends behind it.
+
+use std::collections::HashMap;
+use std::io;
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::sync::{Mutex, RwLock};
+use std::time::duration::Duration;
+use time;
+
+/// Resolves hosts, caching their addresses
+pub struct Resolver {
+    /// How long addresses are cached - None to resolve for every connection
+    ttl: RwLock<Option<Duration>>,
+    /// Addresses by host and port, with when they expire (in ns)
+    cache: Mutex<HashMap<(String, u16), (Vec<SocketAddr>, u64)>>
+}
+
+impl Resolver {
+    pub fn new(ttl: Option<Duration>) -> Resolver {
+        Resolver {
+            ttl: RwLock::new(ttl),
+            cache: Mutex::new(HashMap::new())
+        }
+    }
+
+    /// Set how long addresses are cached, forgetting those already cached
+    pub fn set_ttl(&self, ttl: Option<Duration>) {
+        *self.ttl.write().unwrap() = ttl;
+        self.cache.lock().unwrap().clear();
+    }
+
+    /// The addresses of `host`, from the cache while they are fresh
+    pub fn resolve(&self, host: &str, port: u16) -> io::Result<Vec<SocketAddr>> {
+        let key = (String::from_str(host), port);
+        let now = time::precise_time_ns();
+        if let Some(&(ref addresses, expires)) = self.cache.lock().unwrap().get(&key) {
+            if now < expires {
+                return Ok(addresses.clone());
+            }
+        }
+        let addresses: Vec<SocketAddr> = try!((host, port).to_socket_addrs()).collect();
+        if let Some(ttl) = *self.ttl.read().unwrap() {
+            let expires = now + ttl.num_nanoseconds().unwrap_or(0) as u64;
+            self.cache.lock().unwrap().insert(key, (addresses.clone(), expires));
+        }
+        Ok(addresses)
+    }
+
+    /// Forget the cached addresses of `host`, so the next connection resolves
+    /// it again
+    pub fn invalidate(&self, host: &str, port: u16) {
+        self.cache.lock().unwrap().remove(&(String::from_str(host), port));
+    }
+}
+
+#[test]
+fn caches_until_invalidated() {
+    let resolver = Resolver::new(Some(Duration::minutes(1)));
+    let addresses = resolver.resolve("127.0.0.1", 8086).unwrap();
+    assert_eq!(addresses.len(), 1);
+    assert!(resolver.cache.lock().unwrap().contains_key(&(String::from_str("127.0.0.1"), 8086)));
+    resolver.invalidate("127.0.0.1", 8086);
+    assert!(resolver.cache.lock().unwrap().is_empty());
+}
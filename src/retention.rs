@@ -0,0 +1,143 @@
+//! Retention policies, as created by `CREATE RETENTION POLICY` and listed by
+//! `SHOW RETENTION POLICIES`
+
+use std::time::duration::Duration;
+use influxql::{self, quote_ident};
+use query::{QueryResult, Value};
+
+/// How long a database keeps data, and how it is sharded
+#[derive(Show, Clone, PartialEq)]
+pub struct RetentionPolicy {
+    pub name: String,
+    /// How long data is kept - None keeps it forever
+    pub duration: Option<Duration>,
+    /// The time range each shard group covers - None lets the server choose
+    /// from the duration
+    pub shard_group_duration: Option<Duration>,
+    /// How many copies of the data a cluster keeps
+    pub replication: u32,
+    /// Whether writes go to this policy unless they name another
+    pub is_default: bool
+}
+
+impl RetentionPolicy {
+    /// A policy keeping data for `duration` (None for ever), with a single
+    /// copy, that isn't the default
+    pub fn new(name: &str, duration: Option<Duration>) -> RetentionPolicy {
+        RetentionPolicy {
+            name: String::from_str(name),
+            duration: duration,
+            shard_group_duration: None,
+            replication: 1,
+            is_default: false
+        }
+    }
+
+    /// The statement creating this policy on `database`
+    pub fn create_statement(&self, database: &str) -> String {
+        format!("CREATE RETENTION POLICY {} ON {}{}{}",
+                quote_ident(self.name.as_slice()), quote_ident(database), self.clauses(),
+                if self.is_default { " DEFAULT" } else { "" })
+    }
+
+    /// The statement changing the existing policy of this name on `database`
+    /// to match this one
+    ///
+    /// A policy can't be made not the default, only another made the default
+    /// instead. Leaving `shard_group_duration` as None keeps the current one.
+    pub fn alter_statement(&self, database: &str) -> String {
+        format!("ALTER RETENTION POLICY {} ON {}{}{}",
+                quote_ident(self.name.as_slice()), quote_ident(database), self.clauses(),
+                if self.is_default { " DEFAULT" } else { "" })
+    }
+
+    /// The statement creating `database` with this as its default policy
+    pub fn create_database_statement(&self, database: &str) -> String {
+        format!("CREATE DATABASE {} WITH{} NAME {}",
+                quote_ident(database), self.clauses(), quote_ident(self.name.as_slice()))
+    }
+
+    /// The clauses describing this policy, other than whether it is the
+    /// default
+    fn clauses(&self) -> String {
+        let duration = match self.duration {
+            Some(duration) => influxql::duration_literal(duration),
+            None => String::from_str("INF")
+        };
+        let mut clauses = format!(" DURATION {} REPLICATION {}", duration, self.replication);
+        if let Some(shard_group_duration) = self.shard_group_duration {
+            clauses.push_str(format!(" SHARD DURATION {}",
+                                     influxql::duration_literal(shard_group_duration))
+                             .as_slice());
+        }
+        clauses
+    }
+
+    /// Read the result of `SHOW RETENTION POLICIES`
+    pub fn from_result(result: &QueryResult) -> Result<Vec<RetentionPolicy>, String> {
+        let mut policies = vec!();
+        for series in result.series.iter() {
+            let column = |name: &str| {
+                series.column_index(name).ok_or(format!("Result has no {} column", name))
+            };
+            let name = try!(column("name"));
+            let duration = try!(column("duration"));
+            let shard_group_duration = try!(column("shardGroupDuration"));
+            let replication = try!(column("replicaN"));
+            let is_default = try!(column("default"));
+            for row in series.values.iter() {
+                let policy = match (&row[name], &row[duration], &row[shard_group_duration],
+                                    &row[replication], &row[is_default]) {
+                    (&Value::Text(ref name), &Value::Text(ref duration),
+                     &Value::Text(ref shard_group_duration), &Value::Integer(replication),
+                     &Value::Boolean(is_default)) => RetentionPolicy {
+                        name: name.clone(),
+                        duration: try!(parse_duration(duration.as_slice())),
+                        shard_group_duration: try!(parse_duration(
+                            shard_group_duration.as_slice())),
+                        replication: replication as u32,
+                        is_default: is_default
+                    },
+                    _ => return Err(String::from_str("Invalid retention policy row"))
+                };
+                policies.push(policy);
+            }
+        }
+        Ok(policies)
+    }
+}
+
+/// Read a duration as shown by `SHOW RETENTION POLICIES` (e.g. `168h0m0s`),
+/// where `0s` stands for infinite
+fn parse_duration(literal: &str) -> Result<Option<Duration>, String> {
+    match influxql::parse_duration_literal(literal) {
+        Some(duration) if duration == Duration::zero() => Ok(None),
+        Some(duration) => Ok(Some(duration)),
+        None => Err(format!("Invalid duration: {}", literal))
+    }
+}
+
+#[test]
+fn reads_and_writes_policies() {
+    use query;
+    let result = query::parse(String::from_str(r#"{"results":[{"series":[{
+        "columns":["name","duration","shardGroupDuration","replicaN","default"],
+        "values":[["autogen","0s","168h0m0s",1,true],["week","168h0m0s","24h0m0s",2,false]]
+        }]}]}"#), None);
+    let policies = RetentionPolicy::from_result(&result.unwrap()).unwrap();
+    assert_eq!(policies[0], RetentionPolicy {
+        shard_group_duration: Some(Duration::weeks(1)),
+        is_default: true,
+        ..RetentionPolicy::new("autogen", None)
+    });
+    assert_eq!(policies[1].duration, Some(Duration::weeks(1)));
+    assert_eq!(policies[1].create_statement("metrics"),
+               "CREATE RETENTION POLICY \"week\" ON \"metrics\" DURATION 1w REPLICATION 2 \
+                SHARD DURATION 1d");
+    assert_eq!(policies[0].alter_statement("metrics"),
+               "ALTER RETENTION POLICY \"autogen\" ON \"metrics\" DURATION INF REPLICATION 1 \
+                SHARD DURATION 1w DEFAULT");
+    assert_eq!(policies[1].create_database_statement("metrics"),
+               "CREATE DATABASE \"metrics\" WITH DURATION 1w REPLICATION 2 SHARD DURATION 1d \
+                NAME \"week\"");
+}
@@ -0,0 +1,93 @@
+//! Policies for retrying failed requests
+//!
+//! Requests that fail in a way that may not happen again - a connection
+//! error, a timeout, or a 408, 429 or 5xx response - are retried (against
+//! the next available instance) after a delay chosen by the cluster's
+//! `RetryPolicy`. Others, such as a 400 or an authentication failure, fail
+//! straight away.
+
+use std::default::Default;
+use std::num::Float;
+use std::rand;
+use std::time::duration::Duration;
+
+/// Decides whether, and when, to retry a failed request
+pub trait RetryPolicy: Send + Sync {
+    /// How long to wait before retry number `retry` (starting at 1), or None
+    /// to give up and fail the request
+    fn backoff(&self, retry: u32) -> Option<Duration>;
+}
+
+/// Never retry
+#[derive(Show, Clone, Copy)]
+pub struct NoRetry;
+
+impl RetryPolicy for NoRetry {
+    fn backoff(&self, _: u32) -> Option<Duration> {
+        None
+    }
+}
+
+/// Retry with exponentially increasing, jittered delays
+#[derive(Show, Clone, Copy)]
+pub struct ExponentialBackoff {
+    /// Delay before the first retry
+    pub base: Duration,
+    /// Factor the delay grows by with each retry
+    pub multiplier: f64,
+    /// Upper bound on the delay
+    pub max_delay: Duration,
+    /// Fraction (0 to 1) of each delay that is randomised, to stop clients
+    /// retrying in lockstep
+    pub jitter: f64,
+    /// Give up after this many retries
+    pub max_retries: u32
+}
+
+impl Default for ExponentialBackoff {
+    fn default() -> ExponentialBackoff {
+        ExponentialBackoff {
+            base: Duration::milliseconds(100),
+            multiplier: 2.0,
+            max_delay: Duration::seconds(10),
+            jitter: 0.5,
+            max_retries: 3
+        }
+    }
+}
+
+impl ExponentialBackoff {
+    /// The jittered delay before attempt number `attempt` (starting at 1),
+    /// ignoring `max_retries`
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let delay = (self.base.num_milliseconds() as f64 *
+                     self.multiplier.powi(attempt as i32 - 1))
+                    .min(self.max_delay.num_milliseconds() as f64);
+        let jittered = delay * (1.0 - self.jitter * rand::random::<f64>());
+        Duration::milliseconds(jittered as i64)
+    }
+}
+
+impl RetryPolicy for ExponentialBackoff {
+    fn backoff(&self, retry: u32) -> Option<Duration> {
+        if retry > self.max_retries {
+            return None;
+        }
+        Some(self.delay(retry))
+    }
+}
+
+#[test]
+fn backs_off_exponentially() {
+    let backoff = ExponentialBackoff { jitter: 0.0, ..Default::default() };
+    assert_eq!(backoff.backoff(1), Some(Duration::milliseconds(100)));
+    assert_eq!(backoff.backoff(3), Some(Duration::milliseconds(400)));
+    assert_eq!(backoff.backoff(4), None);
+    assert_eq!(backoff.delay(10), Duration::seconds(10));
+    let jittered = ExponentialBackoff { jitter: 0.5, ..Default::default() };
+    for _ in 0..100 {
+        let delay = jittered.delay(2);
+        assert!(delay >= Duration::milliseconds(100) && delay <= Duration::milliseconds(200));
+    }
+    assert_eq!(NoRetry.backoff(1), None);
+}
@@ -0,0 +1,141 @@
+//! Typed results of the schema exploration statements - `SHOW MEASUREMENTS`,
+//! `SHOW TAG KEYS`, `SHOW TAG VALUES` and `SHOW FIELD KEYS`
+
+use std::fmt;
+use query::{QueryResult, Series, Value};
+
+/// The type of a field, as reported by `SHOW FIELD KEYS`
+#[derive(Show, Clone, Copy, PartialEq)]
+pub enum FieldType {
+    Float,
+    Integer,
+    UInteger,
+    String,
+    Boolean
+}
+
+impl FieldType {
+    fn from_str(name: &str) -> Result<FieldType, String> {
+        match name {
+            "float" => Ok(FieldType::Float),
+            "integer" => Ok(FieldType::Integer),
+            "unsigned" => Ok(FieldType::UInteger),
+            "string" => Ok(FieldType::String),
+            "boolean" => Ok(FieldType::Boolean),
+            other => Err(format!("Unknown field type: {}", other))
+        }
+    }
+}
+
+impl fmt::String for FieldType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", match *self {
+            FieldType::Float => "float",
+            FieldType::Integer => "integer",
+            FieldType::UInteger => "unsigned",
+            FieldType::String => "string",
+            FieldType::Boolean => "boolean"
+        })
+    }
+}
+
+/// A field of a measurement
+#[derive(Show, Clone, PartialEq)]
+pub struct FieldKey {
+    pub name: String,
+    pub field_type: FieldType
+}
+
+/// The values of a text column of a series
+fn text_column(series: &Series, column: &str) -> Result<Vec<String>, String> {
+    let index = try!(series.column_index(column)
+                     .ok_or(format!("Result has no {} column", column)));
+    Ok(series.values.iter().map(|row| match row[index] {
+        Value::Text(ref text) => text.clone(),
+        _ => String::new()
+    }).collect())
+}
+
+/// Read the result of `SHOW DATABASES`
+pub fn databases(result: &QueryResult) -> Result<Vec<String>, String> {
+    let mut names = vec!();
+    for series in result.series.iter() {
+        names.extend(try!(text_column(series, "name")).into_iter());
+    }
+    Ok(names)
+}
+
+/// Read the result of `SHOW MEASUREMENTS`
+pub fn measurements(result: &QueryResult) -> Result<Vec<String>, String> {
+    let mut names = vec!();
+    for series in result.series.iter() {
+        names.extend(try!(text_column(series, "name")).into_iter());
+    }
+    Ok(names)
+}
+
+/// Read the result of `SHOW SERIES`, as the key of each series
+pub fn series_keys(result: &QueryResult) -> Result<Vec<String>, String> {
+    let mut keys = vec!();
+    for series in result.series.iter() {
+        keys.extend(try!(text_column(series, "key")).into_iter());
+    }
+    Ok(keys)
+}
+
+/// Read the result of `SHOW TAG KEYS`, as the tag keys of each measurement
+pub fn tag_keys(result: &QueryResult) -> Result<Vec<(String, Vec<String>)>, String> {
+    let mut keys = vec!();
+    for series in result.series.iter() {
+        keys.push((series.name.clone(), try!(text_column(series, "tagKey"))));
+    }
+    Ok(keys)
+}
+
+/// Read the result of `SHOW TAG VALUES`, as the values of each measurement
+pub fn tag_values(result: &QueryResult) -> Result<Vec<(String, Vec<String>)>, String> {
+    let mut values = vec!();
+    for series in result.series.iter() {
+        values.push((series.name.clone(), try!(text_column(series, "value"))));
+    }
+    Ok(values)
+}
+
+/// Read the result of `SHOW FIELD KEYS`, as the fields of each measurement
+pub fn field_keys(result: &QueryResult) -> Result<Vec<(String, Vec<FieldKey>)>, String> {
+    let mut keys = vec!();
+    for series in result.series.iter() {
+        let names = try!(text_column(series, "fieldKey"));
+        let types = try!(text_column(series, "fieldType"));
+        let mut fields = Vec::with_capacity(names.len());
+        for (name, field_type) in names.into_iter().zip(types.iter()) {
+            fields.push(FieldKey {
+                name: name,
+                field_type: try!(FieldType::from_str(field_type.as_slice()))
+            });
+        }
+        keys.push((series.name.clone(), fields));
+    }
+    Ok(keys)
+}
+
+#[test]
+fn reads_field_keys() {
+    let body = String::from_str(r#"{"results":[{"statement_id":0,"series":[
+        {"name":"cpu","columns":["fieldKey","fieldType"],
+         "values":[["usage","float"],["cores","integer"]]}]}]}"#);
+    let result = ::query::parse(body, None).unwrap();
+    let usage = FieldKey { name: String::from_str("usage"), field_type: FieldType::Float };
+    let cores = FieldKey { name: String::from_str("cores"), field_type: FieldType::Integer };
+    assert_eq!(field_keys(&result).unwrap(),
+               vec!((String::from_str("cpu"), vec!(usage, cores))));
+}
+
+#[test]
+fn reads_series_keys() {
+    let body = String::from_str(r#"{"results":[{"statement_id":0,"series":[
+        {"columns":["key"],"values":[["cpu,host=a"],["cpu,host=b"]]}]}]}"#);
+    let result = ::query::parse(body, None).unwrap();
+    assert_eq!(series_keys(&result).unwrap(),
+               vec!(String::from_str("cpu,host=a"), String::from_str("cpu,host=b")));
+}
@@ -0,0 +1,150 @@
+//! Shards and shard groups, as listed by `SHOW SHARDS` and
+//! `SHOW SHARD GROUPS`
+
+use time::Timespec;
+use query::{QueryResult, Series, Value};
+use timestamp;
+
+/// A shard: the data of one retention policy over one shard group's time range
+#[derive(Show, Clone, PartialEq)]
+pub struct Shard {
+    pub id: u64,
+    pub database: String,
+    pub retention_policy: String,
+    /// The id of the shard group the shard belongs to
+    pub shard_group: u64,
+    /// The start of the time range the shard holds
+    pub start: Timespec,
+    /// The end (exclusive) of the time range the shard holds
+    pub end: Timespec,
+    /// When the shard is dropped by the retention policy
+    pub expiry: Timespec,
+    /// The ids of the data nodes holding the shard - empty on a single
+    /// server
+    pub owners: Vec<u64>
+}
+
+/// A shard group: the shards of a retention policy covering one time range
+#[derive(Show, Clone, PartialEq)]
+pub struct ShardGroup {
+    pub id: u64,
+    pub database: String,
+    pub retention_policy: String,
+    /// The start of the time range the group covers
+    pub start: Timespec,
+    /// The end (exclusive) of the time range the group covers
+    pub end: Timespec,
+    /// When the group is dropped by the retention policy
+    pub expiry: Timespec
+}
+
+/// The index of `column` in `series`
+fn column(series: &Series, column: &str) -> Result<usize, String> {
+    series.column_index(column).ok_or(format!("Result has no {} column", column))
+}
+
+fn id(value: &Value) -> Result<u64, String> {
+    match *value {
+        Value::Integer(id) if id >= 0 => Ok(id as u64),
+        _ => Err(format!("Invalid id: {:?}", value))
+    }
+}
+
+fn text(value: &Value) -> Result<String, String> {
+    match *value {
+        Value::Text(ref text) => Ok(text.clone()),
+        _ => Err(format!("Invalid text: {:?}", value))
+    }
+}
+
+fn time(value: &Value) -> Result<Timespec, String> {
+    match *value {
+        Value::Text(ref text) => timestamp::parse_rfc3339(text.as_slice()),
+        Value::Time(time) => Ok(time),
+        _ => Err(format!("Invalid time: {:?}", value))
+    }
+}
+
+/// Read the owners of a shard, a comma separated list of node ids
+fn owners(value: &Value) -> Result<Vec<u64>, String> {
+    let owners = try!(text(value));
+    owners.as_slice().split(',')
+        .map(|owner| owner.trim())
+        .filter(|owner| !owner.is_empty())
+        .map(|owner| owner.parse().map_err(|_| format!("Invalid shard owner: {}", owner)))
+        .collect()
+}
+
+/// Read the result of `SHOW SHARDS`, which lists the shards of each database
+/// as a series
+pub fn shards(result: &QueryResult) -> Result<Vec<Shard>, String> {
+    let mut shards = vec!();
+    for series in result.series.iter() {
+        let id_column = try!(column(series, "id"));
+        let database = try!(column(series, "database"));
+        let retention_policy = try!(column(series, "retention_policy"));
+        let shard_group = try!(column(series, "shard_group"));
+        let start = try!(column(series, "start_time"));
+        let end = try!(column(series, "end_time"));
+        let expiry = try!(column(series, "expiry_time"));
+        let owners_column = try!(column(series, "owners"));
+        for row in series.values.iter() {
+            shards.push(Shard {
+                id: try!(id(&row[id_column])),
+                database: try!(text(&row[database])),
+                retention_policy: try!(text(&row[retention_policy])),
+                shard_group: try!(id(&row[shard_group])),
+                start: try!(time(&row[start])),
+                end: try!(time(&row[end])),
+                expiry: try!(time(&row[expiry])),
+                owners: try!(owners(&row[owners_column]))
+            });
+        }
+    }
+    Ok(shards)
+}
+
+/// Read the result of `SHOW SHARD GROUPS`
+pub fn shard_groups(result: &QueryResult) -> Result<Vec<ShardGroup>, String> {
+    let mut groups = vec!();
+    for series in result.series.iter() {
+        let id_column = try!(column(series, "id"));
+        let database = try!(column(series, "database"));
+        let retention_policy = try!(column(series, "retention_policy"));
+        let start = try!(column(series, "start_time"));
+        let end = try!(column(series, "end_time"));
+        let expiry = try!(column(series, "expiry_time"));
+        for row in series.values.iter() {
+            groups.push(ShardGroup {
+                id: try!(id(&row[id_column])),
+                database: try!(text(&row[database])),
+                retention_policy: try!(text(&row[retention_policy])),
+                start: try!(time(&row[start])),
+                end: try!(time(&row[end])),
+                expiry: try!(time(&row[expiry]))
+            });
+        }
+    }
+    Ok(groups)
+}
+
+#[test]
+fn reads_shards() {
+    use query;
+    let result = query::parse(String::from_str(r#"{"results":[{"series":[{"name":"metrics",
+        "columns":["id","database","retention_policy","shard_group","start_time","end_time",
+                   "expiry_time","owners"],
+        "values":[[3,"metrics","autogen",2,"2015-01-05T00:00:00Z","2015-01-12T00:00:00Z",
+                   "2015-01-12T00:00:00Z","1,2"]]}]}]}"#), None);
+    let shards = shards(&result.unwrap()).unwrap();
+    assert_eq!(shards, vec!(Shard {
+        id: 3,
+        database: String::from_str("metrics"),
+        retention_policy: String::from_str("autogen"),
+        shard_group: 2,
+        start: Timespec::new(1420416000, 0),
+        end: Timespec::new(1421020800, 0),
+        expiry: Timespec::new(1421020800, 0),
+        owners: vec!(1, 2)
+    }));
+}
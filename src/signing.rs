@@ -0,0 +1,35 @@
+//! Signing requests for instances behind authenticating gateways
+//!
+//! A signer runs after the middleware, once the instance is chosen, so it
+//! signs exactly what is sent - e.g. AWS SigV4 for a managed InfluxDB behind
+//! an API gateway.
+
+use crypto::digest::Digest;
+use crypto::sha2::Sha256;
+use hyper::method::Method;
+use url::Url;
+
+/// Signs each request just before it is sent
+pub trait RequestSigner: Send + Sync {
+    /// Sign a request, adding headers such as `Authorization`
+    ///
+    /// `body_hash` is the lowercase hex SHA-256 of the body, or of the empty
+    /// string when there is none
+    fn sign(&self, method: &Method, url: &Url, headers: &mut Vec<(String, String)>,
+            body_hash: &str);
+}
+
+/// The lowercase hex SHA-256 of a body
+pub fn body_hash(body: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.input_str(body);
+    hasher.result_str()
+}
+
+#[test]
+fn hashes_bodies() {
+    assert_eq!(body_hash(""),
+               "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855");
+    assert_eq!(body_hash("abc"),
+               "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad");
+}
@@ -0,0 +1,127 @@
+//! An append-only file journal of unsent line protocol
+//!
+//! Used by the batch writer to keep batches that could not be sent because
+//! no instances were available, so they survive a restart of the process.
+
+use std::error::FromError;
+use std::io::{File, BufferedReader, IoError, IoResult, Append, Write};
+use std::io::fs::{self, PathExtensions};
+
+/// A journal of line protocol batches waiting to be sent
+#[derive(Show, Clone)]
+pub struct Spool {
+    path: Path
+}
+
+impl Spool {
+    /// Use the file at `path` as the journal, creating it when first needed
+    ///
+    /// Any batches already in the file (e.g. from a previous run) will be
+    /// replayed.
+    pub fn new(path: Path) -> Spool {
+        Spool {
+            path: path
+        }
+    }
+
+    /// Whether there is anything waiting in the journal
+    pub fn is_empty(&self) -> bool {
+        match fs::stat(&self.path) {
+            Ok(stat) => stat.size == 0,
+            Err(_) => true
+        }
+    }
+
+    /// Append a batch of lines to the journal
+    pub fn append(&self, lines: &str) -> IoResult<()> {
+        let mut file = try!(File::open_mode(&self.path, Append, Write));
+        try!(file.write_str(lines));
+        if !lines.ends_with("\n") {
+            try!(file.write_str("\n"));
+        }
+        file.fsync()
+    }
+
+    /// Send the journal, in chunks of roughly `max_bytes`, with `send`
+    ///
+    /// Chunks are only removed from the journal once `send` succeeds. If it
+    /// fails, the chunk and those after it are kept for the next replay, and
+    /// the failure is returned.
+    pub fn replay<E, F>(&self, max_bytes: usize, mut send: F) -> Result<(), E>
+        where E: FromError<IoError>, F: FnMut(String) -> Result<(), E>
+    {
+        let mut chunks = try!(self.chunks(max_bytes)).into_iter();
+        while let Some(chunk) = chunks.next() {
+            if let Err(e) = send(chunk.clone()) {
+                let mut rest = chunk;
+                for chunk in chunks {
+                    rest.push_str(chunk.as_slice());
+                }
+                try!(self.replace(rest.as_slice()));
+                return Err(e);
+            }
+        }
+        try!(File::create(&self.path));
+        Ok(())
+    }
+
+    /// Read the journal in chunks of roughly `max_bytes`
+    fn chunks(&self, max_bytes: usize) -> IoResult<Vec<String>> {
+        let mut chunks = vec!();
+        if !self.path.exists() {
+            return Ok(chunks);
+        }
+        let mut reader = BufferedReader::new(try!(File::open(&self.path)));
+        let mut chunk = String::new();
+        for line in reader.lines() {
+            let line = try!(line);
+            if !chunk.is_empty() && chunk.len() + line.len() > max_bytes {
+                chunks.push(chunk);
+                chunk = String::new();
+            }
+            chunk.push_str(line.as_slice());
+        }
+        if !chunk.is_empty() {
+            chunks.push(chunk);
+        }
+        Ok(chunks)
+    }
+
+    /// Replace the journal with `lines`, via a file next to it so a crash
+    /// part way leaves the old journal whole
+    fn replace(&self, lines: &str) -> IoResult<()> {
+        let next = self.path.with_extension("replay");
+        {
+            let mut file = try!(File::create(&next));
+            try!(file.write_str(lines));
+            try!(file.fsync());
+        }
+        fs::rename(&next, &self.path)
+    }
+}
+
+#[test]
+fn keeps_chunks_until_sent() {
+    use std::io::{self, TempDir};
+    let dir = TempDir::new("influx-spool").unwrap();
+    let spool = Spool::new(dir.path().join("spool.lp"));
+    spool.append("cpu value=1 1").unwrap();
+    spool.append("cpu value=2 2").unwrap();
+    let mut sent = vec!();
+    let failed: Result<(), IoError> = spool.replay(1, |chunk| {
+        if sent.is_empty() {
+            sent.push(chunk);
+            Ok(())
+        } else {
+            Err(io::standard_error(io::ConnectionRefused))
+        }
+    });
+    assert!(failed.is_err());
+    assert_eq!(sent, vec!(String::from_str("cpu value=1 1\n")));
+    assert!(!spool.is_empty());
+    let mut sent = vec!();
+    let replayed: Result<(), IoError> = spool.replay(1, |chunk| Ok(sent.push(chunk)));
+    assert!(replayed.is_ok());
+    assert_eq!(sent, vec!(String::from_str("cpu value=2 2\n")));
+    assert!(spool.is_empty());
+}
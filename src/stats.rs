@@ -0,0 +1,85 @@
+//! The server's view of itself, as shown by `SHOW DIAGNOSTICS` and
+//! `SHOW STATS`
+
+use std::collections::BTreeMap;
+use query::{QueryResult, Series, Value};
+
+/// The sections of `SHOW DIAGNOSTICS` (e.g. `build`, `runtime`, `network`),
+/// each a map of its entries by name
+pub type Diagnostics = BTreeMap<String, BTreeMap<String, Value>>;
+
+/// The internal statistics of one module of the server, e.g. `httpd` or a
+/// single `shard`
+#[derive(Show, Clone, PartialEq)]
+pub struct Statistics {
+    pub module: String,
+    /// Which instance of the module, e.g. the `bind` address of `httpd` or
+    /// the `id` of a shard
+    pub tags: Vec<(String, String)>,
+    /// The statistics by name, e.g. `writeReq`
+    pub values: BTreeMap<String, Value>
+}
+
+/// The row of a single row series as a map by column
+fn entries(series: &Series) -> BTreeMap<String, Value> {
+    let mut entries = BTreeMap::new();
+    if let Some(row) = series.values.first() {
+        for (column, value) in series.columns.iter().zip(row.iter()) {
+            entries.insert(column.clone(), value.clone());
+        }
+    }
+    entries
+}
+
+/// Read the result of `SHOW DIAGNOSTICS`, which shows each section as a
+/// series of one row
+pub fn diagnostics(result: &QueryResult) -> Result<Diagnostics, String> {
+    let mut sections = BTreeMap::new();
+    for series in result.series.iter() {
+        if series.values.len() > 1 {
+            return Err(format!("Diagnostics section {} has several rows", series.name));
+        }
+        sections.insert(series.name.clone(), entries(series));
+    }
+    Ok(sections)
+}
+
+/// Read the result of `SHOW STATS`, which shows each module as a series of
+/// one row
+pub fn stats(result: &QueryResult) -> Result<Vec<Statistics>, String> {
+    let mut stats = vec!();
+    for series in result.series.iter() {
+        if series.values.len() > 1 {
+            return Err(format!("Statistics of {} have several rows", series.name));
+        }
+        stats.push(Statistics {
+            module: series.name.clone(),
+            tags: series.tags.clone(),
+            values: entries(series)
+        });
+    }
+    Ok(stats)
+}
+
+#[test]
+fn reads_diagnostics_and_stats() {
+    use query;
+    let result = query::parse(String::from_str(r#"{"results":[{"series":[
+        {"name":"build","columns":["Branch","Commit","Version"],
+         "values":[["1.8","688e697","1.8.10"]]},
+        {"name":"network","columns":["hostname"],"values":[["influx-0"]]}]}]}"#), None);
+    let diagnostics = diagnostics(&result.unwrap()).unwrap();
+    let entry = |section: &str, name: &str| {
+        diagnostics.get(section).and_then(|entries| entries.get(name)).map(|v| v.clone())
+    };
+    assert_eq!(entry("build", "Version"), Some(Value::Text(String::from_str("1.8.10"))));
+    assert_eq!(entry("network", "hostname"), Some(Value::Text(String::from_str("influx-0"))));
+
+    let result = query::parse(String::from_str(r#"{"results":[{"series":[
+        {"name":"httpd","tags":{"bind":":8086"},"columns":["queryReq","writeReq"],
+         "values":[[12,40]]}]}]}"#), None);
+    let stats = stats(&result.unwrap()).unwrap();
+    assert_eq!(stats[0].module, "httpd");
+    assert_eq!(stats[0].tags, vec!((String::from_str("bind"), String::from_str(":8086"))));
+    assert_eq!(stats[0].values.get("writeReq"), Some(&Value::Integer(40)));
+}
@@ -0,0 +1,112 @@
+//! Subscriptions, which copy the writes to a retention policy to other
+//! destinations such as Kapacitor, as created by `CREATE SUBSCRIPTION` and
+//! listed by `SHOW SUBSCRIPTIONS`
+
+use std::fmt;
+use influxql::{quote_ident, quote_literal};
+use query::{QueryResult, Value};
+
+/// Where a subscription sends each write
+#[derive(Show, Clone, Copy, PartialEq)]
+pub enum Mode {
+    /// To every destination
+    All,
+    /// To one of the destinations, in turn
+    Any
+}
+
+/// Formats as in `CREATE SUBSCRIPTION` statements
+impl fmt::String for Mode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", match *self {
+            Mode::All => "ALL",
+            Mode::Any => "ANY"
+        })
+    }
+}
+
+/// A subscription to the writes to a retention policy
+#[derive(Show, Clone, PartialEq)]
+pub struct Subscription {
+    pub name: String,
+    pub database: String,
+    pub retention_policy: String,
+    pub mode: Mode,
+    /// The URLs writes are sent to, e.g. `udp://kapacitor:9090` or
+    /// `http://kapacitor:9092`
+    pub destinations: Vec<String>
+}
+
+impl Subscription {
+    /// The statement creating this subscription
+    pub fn create_statement(&self) -> String {
+        let destinations: Vec<String> = self.destinations.iter()
+            .map(|destination| quote_literal(destination.as_slice()))
+            .collect();
+        format!("CREATE SUBSCRIPTION {} ON {}.{} DESTINATIONS {} {}",
+                quote_ident(self.name.as_slice()),
+                quote_ident(self.database.as_slice()),
+                quote_ident(self.retention_policy.as_slice()),
+                self.mode, destinations.connect(", "))
+    }
+
+    /// Read the result of `SHOW SUBSCRIPTIONS`, which lists the
+    /// subscriptions of each database as a series
+    pub fn from_result(result: &QueryResult) -> Result<Vec<Subscription>, String> {
+        let mut subscriptions = vec!();
+        for series in result.series.iter() {
+            let column = |name: &str| {
+                series.column_index(name).ok_or(format!("Result has no {} column", name))
+            };
+            let retention_policy = try!(column("retention_policy"));
+            let name = try!(column("name"));
+            let mode = try!(column("mode"));
+            let destinations = try!(column("destinations"));
+            for row in series.values.iter() {
+                let subscription = match (&row[retention_policy], &row[name], &row[mode],
+                                          &row[destinations]) {
+                    (&Value::Text(ref retention_policy), &Value::Text(ref name),
+                     &Value::Text(ref mode), &Value::Array(ref destinations)) => Subscription {
+                        name: name.clone(),
+                        database: series.name.clone(),
+                        retention_policy: retention_policy.clone(),
+                        mode: match mode.as_slice() {
+                            "ALL" => Mode::All,
+                            "ANY" => Mode::Any,
+                            other => return Err(format!("Invalid subscription mode: {}", other))
+                        },
+                        destinations: try!(destinations.iter().map(|destination| {
+                            match *destination {
+                                Value::Text(ref destination) => Ok(destination.clone()),
+                                _ => Err(String::from_str("Invalid subscription destination"))
+                            }
+                        }).collect())
+                    },
+                    _ => return Err(String::from_str("Invalid subscription row"))
+                };
+                subscriptions.push(subscription);
+            }
+        }
+        Ok(subscriptions)
+    }
+}
+
+#[test]
+fn reads_and_writes_subscriptions() {
+    use query;
+    let result = query::parse(String::from_str(r#"{"results":[{"series":[{"name":"metrics",
+        "columns":["retention_policy","name","mode","destinations"],
+        "values":[["autogen","kapacitor","ANY",["udp://k1:9090","udp://k2:9090"]]]}]}]}"#),
+        None);
+    let subscriptions = Subscription::from_result(&result.unwrap()).unwrap();
+    assert_eq!(subscriptions, vec!(Subscription {
+        name: String::from_str("kapacitor"),
+        database: String::from_str("metrics"),
+        retention_policy: String::from_str("autogen"),
+        mode: Mode::Any,
+        destinations: vec!(String::from_str("udp://k1:9090"), String::from_str("udp://k2:9090"))
+    }));
+    assert_eq!(subscriptions[0].create_statement(),
+               "CREATE SUBSCRIPTION \"kapacitor\" ON \"metrics\".\"autogen\" DESTINATIONS ANY \
+                'udp://k1:9090', 'udp://k2:9090'");
+}
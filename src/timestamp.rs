@@ -0,0 +1,197 @@
+//! Conversions between the timestamp types users have to hand and the
+//! `time::Timespec` stored in a `DataPoint`
+//!
+//! Raw nanoseconds (`i64`) and `std::time::SystemTime` are always supported,
+//! `chrono::DateTime<Utc>` with the `chrono` feature.
+
+use std::ascii::AsciiExt;
+use std::time::{SystemTime, UNIX_EPOCH};
+use time;
+use time::Timespec;
+#[cfg(feature = "chrono")]
+use chrono::{DateTime, Utc, TimeZone};
+
+const NANOS_PER_SEC: i64 = 1_000_000_000;
+
+/// Types that can be used as the timestamp of a point
+pub trait ToTimestamp {
+    fn to_timespec(&self) -> Timespec;
+}
+
+/// Types a timestamp can be read back as
+pub trait FromTimestamp {
+    fn from_timespec(time: Timespec) -> Self;
+}
+
+/// Convert nanoseconds since the epoch to a timestamp
+pub fn from_nanos(ns: i64) -> Timespec {
+    let mut sec = ns / NANOS_PER_SEC;
+    let mut nsec = ns % NANOS_PER_SEC;
+    // Timespec wants a non-negative nsec, even before the epoch
+    if nsec < 0 {
+        sec -= 1;
+        nsec += NANOS_PER_SEC;
+    }
+    Timespec::new(sec, nsec as i32)
+}
+
+/// Convert a timestamp to nanoseconds since the epoch
+pub fn to_nanos(time: &Timespec) -> i64 {
+    time.sec * NANOS_PER_SEC + time.nsec as i64
+}
+
+/// Format a timestamp as RFC3339 in UTC, to the nanosecond, e.g.
+/// `2015-06-11T20:46:02.123456789Z`
+pub fn format_rfc3339(time: &Timespec) -> String {
+    let tm = time::at_utc(*time);
+    format!("{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:09}Z",
+            tm.tm_year + 1900, tm.tm_mon + 1, tm.tm_mday,
+            tm.tm_hour, tm.tm_min, tm.tm_sec, time.nsec)
+}
+
+/// Parse an RFC3339 timestamp such as `2015-06-11T20:46:02.123456789Z`
+pub fn parse_rfc3339(s: &str) -> Result<Timespec, String> {
+    let invalid = || format!("Invalid RFC3339 timestamp: {}", s);
+    let bytes = s.as_bytes();
+    if !s.is_ascii() || s.len() < 20 || bytes[4] != b'-' || bytes[7] != b'-' ||
+        bytes[10].to_ascii_uppercase() != b'T' || bytes[13] != b':' || bytes[16] != b':' {
+        return Err(invalid());
+    }
+    let number = |from: usize, to: usize| -> Result<i64, String> {
+        s.slice(from, to).parse::<i64>().map_err(|_| invalid())
+    };
+    let (year, month, day) = (try!(number(0, 4)), try!(number(5, 7)), try!(number(8, 10)));
+    let (hour, minute, second) = (try!(number(11, 13)), try!(number(14, 16)),
+                                  try!(number(17, 19)));
+    if month < 1 || month > 12 || day < 1 || day > 31 ||
+        hour > 23 || minute > 59 || second > 60 {
+        return Err(invalid());
+    }
+
+    let mut rest = s.slice_from(19);
+    let mut nsec = 0;
+    if rest.starts_with(".") {
+        let digits = rest.slice_from(1).chars().take_while(|c| c.is_digit(10)).count();
+        if digits == 0 || digits > 9 {
+            return Err(invalid());
+        }
+        nsec = try!(number(20, 20 + digits));
+        for _ in digits..9 {
+            nsec *= 10;
+        }
+        rest = rest.slice_from(1 + digits);
+    }
+
+    // offset from UTC in minutes
+    let offset = if rest.eq_ignore_ascii_case("Z") {
+        0
+    } else if rest.len() == 6 && rest.as_bytes()[3] == b':' &&
+              (rest.starts_with("+") || rest.starts_with("-")) {
+        let start = s.len() - 6;
+        let minutes = try!(number(start + 1, start + 3)) * 60 +
+                      try!(number(start + 4, start + 6));
+        if rest.starts_with("-") { -minutes } else { minutes }
+    } else {
+        return Err(invalid());
+    };
+
+    let sec = days_from_civil(year, month, day) * 86400 +
+              hour * 3600 + minute * 60 + second - offset * 60;
+    Ok(Timespec::new(sec, nsec as i32))
+}
+
+/// Days since the epoch of a date in the proleptic Gregorian calendar
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = (if year >= 0 { year } else { year - 399 }) / 400;
+    let year_of_era = year - era * 400;
+    let month_from_march = if month > 2 { month - 3 } else { month + 9 };
+    let day_of_year = (153 * month_from_march + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+impl ToTimestamp for Timespec {
+    fn to_timespec(&self) -> Timespec {
+        *self
+    }
+}
+
+impl FromTimestamp for Timespec {
+    fn from_timespec(time: Timespec) -> Timespec {
+        time
+    }
+}
+
+/// Nanoseconds since the epoch
+impl ToTimestamp for i64 {
+    fn to_timespec(&self) -> Timespec {
+        from_nanos(*self)
+    }
+}
+
+/// Nanoseconds since the epoch
+impl FromTimestamp for i64 {
+    fn from_timespec(time: Timespec) -> i64 {
+        to_nanos(&time)
+    }
+}
+
+impl ToTimestamp for SystemTime {
+    fn to_timespec(&self) -> Timespec {
+        match self.duration_since(UNIX_EPOCH) {
+            Ok(since) => Timespec::new(since.as_secs() as i64, since.subsec_nanos() as i32),
+            Err(e) => {
+                let before = e.duration();
+                from_nanos(-(before.as_secs() as i64 * NANOS_PER_SEC +
+                             before.subsec_nanos() as i64))
+            }
+        }
+    }
+}
+
+impl FromTimestamp for SystemTime {
+    fn from_timespec(time: Timespec) -> SystemTime {
+        use std::time::Duration as StdDuration;
+        if time.sec >= 0 {
+            UNIX_EPOCH + StdDuration::new(time.sec as u64, time.nsec as u32)
+        } else {
+            let before = -to_nanos(&time);
+            UNIX_EPOCH - StdDuration::new((before / NANOS_PER_SEC) as u64,
+                                          (before % NANOS_PER_SEC) as u32)
+        }
+    }
+}
+
+#[cfg(feature = "chrono")]
+impl ToTimestamp for DateTime<Utc> {
+    fn to_timespec(&self) -> Timespec {
+        Timespec::new(self.timestamp(), self.timestamp_subsec_nanos() as i32)
+    }
+}
+
+#[cfg(feature = "chrono")]
+impl FromTimestamp for DateTime<Utc> {
+    fn from_timespec(time: Timespec) -> DateTime<Utc> {
+        Utc.timestamp(time.sec, time.nsec as u32)
+    }
+}
+
+#[test]
+fn nanos_round_trip_before_epoch() {
+    let time = from_nanos(-1_500_000_000);
+    assert_eq!(time, Timespec::new(-2, 500_000_000));
+    assert_eq!(to_nanos(&time), -1_500_000_000);
+}
+
+#[test]
+fn parses_rfc3339() {
+    assert_eq!(parse_rfc3339("2015-06-11T20:46:02.5Z"),
+               Ok(Timespec::new(1434055562, 500_000_000)));
+    assert_eq!(parse_rfc3339("2015-06-11T22:46:02+02:00"),
+               Ok(Timespec::new(1434055562, 0)));
+    assert!(parse_rfc3339("2015-06-11 20:46:02").is_err());
+    let time = Timespec::new(1434055562, 123456789);
+    assert_eq!(format_rfc3339(&time), "2015-06-11T20:46:02.123456789Z");
+    assert_eq!(parse_rfc3339(format_rfc3339(&time).as_slice()), Ok(time));
+}
@@ -0,0 +1,191 @@
+//! TLS configuration for HTTPS instances, using rustls (feature `tls`)
+//!
+//! Without a configuration HTTPS instances are reached with hyper's default
+//! connector. With one, certificates are verified against the webpki roots
+//! plus any added here.
+
+use std::io::{self, Read, Write, BufReader};
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration as StdDuration;
+use hyper;
+use hyper::net::{HttpStream, NetworkStream, SslClient};
+use rustls::internal::pemfile;
+use rustls::{self, ClientConfig, ClientSession, RootCertStore, ServerCertVerified,
+             ServerCertVerifier, StreamOwned, TLSError};
+use webpki::DNSNameRef;
+use webpki_roots;
+
+/// How to make TLS connections to HTTPS instances
+#[derive(Clone)]
+pub struct TlsConfig {
+    /// Root certificates (PEM) trusted in addition to the webpki roots
+    root_certificates: Vec<Vec<u8>>,
+    /// Whether server certificates are verified at all
+    verify: bool,
+    /// Name to send in SNI and verify the certificate against, instead of
+    /// the instance's host
+    server_name: Option<String>,
+    /// Certificate chain and key presented to servers that ask for one
+    client_certificate: Option<(Vec<rustls::Certificate>, rustls::PrivateKey)>
+}
+
+impl TlsConfig {
+    pub fn new() -> TlsConfig {
+        TlsConfig {
+            root_certificates: vec!(),
+            verify: true,
+            server_name: None,
+            client_certificate: None
+        }
+    }
+
+    /// Trust the certificates of a PEM bundle, e.g. a private CA
+    pub fn add_root_certificates(mut self, pem: &[u8]) -> Result<TlsConfig, String> {
+        let mut store = RootCertStore::empty();
+        match store.add_pem_file(&mut BufReader::new(pem)) {
+            Ok((added, _)) if added > 0 => {},
+            _ => return Err(String::from_str("No valid certificates in bundle"))
+        }
+        self.root_certificates.push(pem.to_vec());
+        Ok(self)
+    }
+
+    /// Accept any server certificate - for development only, this leaves
+    /// connections open to interception
+    pub fn danger_accept_invalid_certs(mut self, accept: bool) -> TlsConfig {
+        self.verify = !accept;
+        self
+    }
+
+    /// Send `name` in SNI, and verify the certificate against it, instead of
+    /// the instance's host - for instances reached by address
+    pub fn server_name(mut self, name: &str) -> TlsConfig {
+        self.server_name = Some(String::from_str(name));
+        self
+    }
+
+    /// Present a client certificate, for servers (or proxies in front of
+    /// them) requiring mutual TLS
+    ///
+    /// `certificates` is the PEM certificate chain, leaf first, and `key` the
+    /// PEM private key (PKCS#8 or RSA)
+    pub fn client_certificate(mut self, certificates: &[u8],
+                              key: &[u8]) -> Result<TlsConfig, String> {
+        let chain = pemfile::certs(&mut BufReader::new(certificates)).unwrap_or(vec!());
+        if chain.is_empty() {
+            return Err(String::from_str("No valid client certificates"));
+        }
+        let mut keys = pemfile::pkcs8_private_keys(&mut BufReader::new(key))
+            .unwrap_or(vec!());
+        if keys.is_empty() {
+            keys = pemfile::rsa_private_keys(&mut BufReader::new(key)).unwrap_or(vec!());
+        }
+        let key = try!(keys.into_iter().next()
+                       .ok_or(String::from_str("No valid client private key")));
+        self.client_certificate = Some((chain, key));
+        Ok(self)
+    }
+
+    /// The rustls configuration
+    fn client_config(&self) -> ClientConfig {
+        let mut config = ClientConfig::new();
+        config.root_store.add_server_trust_anchors(&webpki_roots::TLS_SERVER_ROOTS);
+        for pem in self.root_certificates.iter() {
+            // checked when added
+            let _ = config.root_store.add_pem_file(&mut BufReader::new(pem.as_slice()));
+        }
+        if let Some((ref chain, ref key)) = self.client_certificate {
+            config.set_single_client_cert(chain.clone(), key.clone());
+        }
+        if !self.verify {
+            config.dangerous().set_certificate_verifier(Arc::new(NoVerification));
+        }
+        config
+    }
+
+    /// A client wrapping connections with this configuration
+    pub fn client(&self) -> TlsClient {
+        TlsClient {
+            config: Arc::new(self.client_config()),
+            server_name: self.server_name.clone()
+        }
+    }
+}
+
+/// Accepts every certificate
+struct NoVerification;
+
+impl ServerCertVerifier for NoVerification {
+    fn verify_server_cert(&self, _: &RootCertStore, _: &[rustls::Certificate],
+                          _: DNSNameRef, _: &[u8]) -> Result<ServerCertVerified, TLSError> {
+        Ok(ServerCertVerified::assertion())
+    }
+}
+
+/// Wraps hyper's connections in TLS
+#[derive(Clone)]
+pub struct TlsClient {
+    config: Arc<ClientConfig>,
+    server_name: Option<String>
+}
+
+impl SslClient for TlsClient {
+    type Stream = TlsStream;
+
+    fn wrap_client(&self, stream: HttpStream, host: &str) -> hyper::Result<TlsStream> {
+        let name = self.server_name.as_ref().map(|name| name.as_slice()).unwrap_or(host);
+        let name = try!(DNSNameRef::try_from_ascii_str(name).map_err(|_| {
+            hyper::Error::Io(io::Error::new(io::ErrorKind::InvalidInput,
+                                            format!("Invalid server name: {}", name)))
+        }));
+        let session = ClientSession::new(&self.config, name);
+        Ok(TlsStream(Arc::new(Mutex::new(StreamOwned::new(session, stream)))))
+    }
+}
+
+/// A TLS connection, shared so hyper can clone it
+#[derive(Clone)]
+pub struct TlsStream(Arc<Mutex<StreamOwned<ClientSession, HttpStream>>>);
+
+impl Read for TlsStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().read(buf)
+    }
+}
+
+impl Write for TlsStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.lock().unwrap().flush()
+    }
+}
+
+impl NetworkStream for TlsStream {
+    fn peer_addr(&mut self) -> io::Result<SocketAddr> {
+        self.0.lock().unwrap().sock.peer_addr()
+    }
+
+    fn set_read_timeout(&self, timeout: Option<StdDuration>) -> io::Result<()> {
+        self.0.lock().unwrap().sock.set_read_timeout(timeout)
+    }
+
+    fn set_write_timeout(&self, timeout: Option<StdDuration>) -> io::Result<()> {
+        self.0.lock().unwrap().sock.set_write_timeout(timeout)
+    }
+}
+
+#[test]
+fn rejects_empty_bundle() {
+    assert!(TlsConfig::new().add_root_certificates(b"not a certificate").is_err());
+}
+
+#[test]
+fn rejects_missing_client_key() {
+    let certificate = b"-----BEGIN CERTIFICATE-----\nMIIBAA==\n-----END CERTIFICATE-----\n";
+    let result = TlsConfig::new().client_certificate(certificate, b"not a key");
+    assert_eq!(result.err(), Some(String::from_str("No valid client private key")));
+}
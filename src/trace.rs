@@ -0,0 +1,72 @@
+//! Spans around request attempts (feature `tracing`)
+//!
+//! Each attempt at a request gets an `influx.request` span with the
+//! instance, method, path and retry number, which records the status,
+//! latency and outcome once the attempt is over. Without the feature these
+//! do nothing.
+
+use time;
+use transport::{Failure, Instance, Request};
+#[cfg(feature = "tracing")] use tracing::{Level, Span};
+#[cfg(feature = "tracing")] use tracing::field::Empty;
+
+/// The span of one attempt at a request
+pub struct AttemptSpan {
+    #[cfg(feature = "tracing")]
+    span: Span,
+    start: u64
+}
+
+impl AttemptSpan {
+    /// Open the span of attempt number `retry` (0 for the first) at
+    /// `request` against `instance`
+    #[cfg(feature = "tracing")]
+    pub fn start(instance: &Instance, request: &Request, retry: u32) -> AttemptSpan {
+        let span = info_span!("influx.request",
+                              instance = %format!("{}://{}:{}", instance.scheme, instance.host,
+                                                  instance.port),
+                              method = %request.method,
+                              path = %request.path.connect("/"),
+                              retry = retry,
+                              status = Empty,
+                              latency_ms = Empty,
+                              outcome = Empty);
+        AttemptSpan { span: span, start: time::precise_time_ns() }
+    }
+
+    #[cfg(not(feature = "tracing"))]
+    pub fn start(_: &Instance, _: &Request, _: u32) -> AttemptSpan {
+        AttemptSpan { start: time::precise_time_ns() }
+    }
+
+    /// How long since the attempt started, in nanoseconds
+    pub fn elapsed_ns(&self) -> u64 {
+        time::precise_time_ns() - self.start
+    }
+
+    /// Record how the attempt ended: the status of the response if there was
+    /// one, and the failure if it failed, which `retry` says will be retried
+    #[cfg(feature = "tracing")]
+    pub fn finish(self, status: Option<u16>, failure: Option<&Failure>, retry: bool) {
+        if let Some(status) = status {
+            self.span.record("status", &status);
+        }
+        self.span.record("latency_ms", &(self.elapsed_ns() / 1_000_000));
+        match failure {
+            None => {
+                self.span.record("outcome", &"success");
+            },
+            Some(failure) if retry => {
+                self.span.record("outcome", &"retry");
+                event!(parent: &self.span, Level::DEBUG, error = %failure, "attempt failed");
+            },
+            Some(failure) => {
+                self.span.record("outcome", &"failed");
+                event!(parent: &self.span, Level::WARN, error = %failure, "request failed");
+            }
+        }
+    }
+
+    #[cfg(not(feature = "tracing"))]
+    pub fn finish(self, _: Option<u16>, _: Option<&Failure>, _: bool) {}
+}
@@ -1,5 +1,42 @@
+use std::cmp;
+use std::ascii::AsciiExt;
+use std::collections::HashMap;
 use std::fmt;
-use url::Host;
+use std::default::Default;
+use std::error::{Error, FromError};
+use std::sync::{Arc, Condvar, Mutex, RwLock, Weak};
+use std::thread::Thread;
+use std::time::duration::Duration;
+use std::time::Duration as StdDuration;
+use std::io::{self, Read, Write};
+use std::net::TcpStream;
+use std::ops::Deref;
+use flate2::read::GzDecoder;
+use futures::{future, Future};
+use futures::sync::oneshot::{self, Canceled};
+#[cfg(feature = "tokio")] use tokio_core::reactor::Remote;
+#[cfg(feature = "tls")] use hyper::net::HttpsConnector;
+#[cfg(feature = "tls")] use tls::{TlsClient, TlsConfig};
+use hyper::net::SslClient;
+use hyper;
+use hyper::method::Method;
+use hyper::client::ProxyConfig as HyperProxyConfig;
+use hyper::client::pool::{self, Pool};
+use hyper::net::{HttpStream, NetworkConnector};
+use hyper::header::Headers;
+use hyper::status::{StatusClass, StatusCode};
+use url::{SchemeData, RelativeSchemeData, Host, Url};
+use time;
+use balancer::{Balancer, RoundRobin};
+use client::RequestFuture;
+use middleware::{self, Middleware};
+use proxy::{self, Proxy, ProxyConfig};
+use resolver::Resolver;
+use retry::{RetryPolicy, ExponentialBackoff};
+use signing::{self, RequestSigner};
+use metrics::Metrics;
+use trace::AttemptSpan;
+use workers::{self, Timer, WorkerPool};
 
 /// Represents a url scheme
 #[derive(Show, Clone, Copy, PartialEq)]
@@ -27,12 +64,52 @@ impl fmt::String for Scheme {
     }
 }
 
+/// Which requests an instance is sent
+#[derive(Show, Clone, Copy, PartialEq)]
+pub enum Role {
+    /// Queries only, e.g. a read replica
+    Read,
+    /// Writes (and any other requests changing data) only, e.g. a primary
+    Write,
+    Both
+}
+
+impl Default for Role {
+    fn default() -> Role {
+        Role::Both
+    }
+}
+
+impl Role {
+    /// Whether an instance with this role serves read-only requests, or
+    /// otherwise other requests
+    fn serves(&self, read_only: bool) -> bool {
+        match *self {
+            Role::Read => read_only,
+            Role::Write => !read_only,
+            Role::Both => true
+        }
+    }
+}
+
 /// Represents a http-like scheme, host & port
 #[derive(Show, Clone, PartialEq)]
 pub struct Instance {
     pub scheme: Scheme,
     pub host: Host,
-    pub port: u16
+    pub port: u16,
+    /// Which requests the instance is preferred for - default `Both`
+    pub role: Role,
+    /// Share of the traffic the instance gets relative to the others -
+    /// default 1
+    pub weight: u32
+}
+
+impl Instance {
+    /// Whether `other` is reached at the same scheme, host and port
+    pub fn same_address(&self, other: &Instance) -> bool {
+        self.scheme == other.scheme && self.host == other.host && self.port == other.port
+    }
 }
 
 impl Default for Instance {
@@ -40,143 +117,2774 @@ impl Default for Instance {
         Instance {
             scheme: Default::default(),
             host: Host::Domain(String::from_str("127.0.0.1")),
-            port: 8086
+            port: 8086,
+            role: Default::default(),
+            weight: 1
+        }
+    }
+}
+
+/// A change to the instances of a cluster
+#[derive(Show, Clone, PartialEq)]
+pub enum TopologyEvent {
+    /// Added, by the application or discovery
+    Added(Instance),
+    /// Removed by discovery
+    Removed(Instance),
+    /// Taken out of use after failing
+    Disabled(Instance),
+    /// Back in use after passing its health checks
+    Enabled(Instance)
+}
+
+/// Time limits on requests - all default None (no limit)
+#[derive(Show, Clone, Copy, PartialEq, Default)]
+pub struct Timeouts {
+    /// How long to wait for a connection to an instance
+    pub connect: Option<Duration>,
+    /// How long an instance may go without sending or receiving while a
+    /// request is in progress
+    pub request: Option<Duration>,
+    /// How long a request may take overall, including retries
+    pub deadline: Option<Duration>
+}
+
+/// A time limit that a request exceeded
+#[derive(Show, Clone, Copy, PartialEq)]
+pub enum Timeout {
+    Connect(Duration),
+    Request(Duration),
+    Deadline(Duration)
+}
+
+impl Timeout {
+    /// The limit that was exceeded
+    pub fn limit(&self) -> Duration {
+        match *self {
+            Timeout::Connect(limit) | Timeout::Request(limit) | Timeout::Deadline(limit) => limit
+        }
+    }
+}
+
+impl fmt::String for Timeout {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let what = match *self {
+            Timeout::Connect(_) => "Connecting",
+            Timeout::Request(_) => "Request",
+            Timeout::Deadline(_) => "Request deadline"
+        };
+        write!(f, "{} timed out after {}ms", what, self.limit().num_milliseconds())
+    }
+}
+
+/// An error a request can fail with
+pub trait RequestError: FromError<String> {
+    /// The request exceeded a time limit - by default reported as a message
+    fn timeout(timeout: Timeout) -> Self {
+        FromError::from_error(format!("{}", timeout))
+    }
+
+    /// No instance could be reached, or the connection failed - by default
+    /// reported as the message
+    fn connection(error: String) -> Self {
+        FromError::from_error(error)
+    }
+
+    /// The server answered with a non-2xx status - by default reported as a
+    /// message
+    fn status(status: u16, body: String) -> Self {
+        FromError::from_error(format!("{}: {}", StatusCode::from_u16(status), body))
+    }
+}
+
+impl RequestError for String {}
+
+/// Why a request failed, before conversion to the caller's error type
+#[derive(Show, Clone)]
+pub enum Failure {
+    Timeout(Timeout),
+    /// A non-2xx response, with its status and body
+    Status(u16, String),
+    /// No response, e.g. the connection failed
+    Error(String),
+    /// No response because the host couldn't be resolved or TLS couldn't be
+    /// set up, which retrying won't fix
+    Misconfigured(String)
+}
+
+/// Whether a request that got a response with `status` may succeed if
+/// retried - request timeouts, rate limiting and server errors
+pub fn retriable_status(status: u16) -> bool {
+    match status {
+        408 | 429 | 500...599 => true,
+        _ => false
+    }
+}
+
+impl Failure {
+    /// Whether the request may succeed if retried, rather than failing the
+    /// same way again - never once its deadline has passed
+    pub fn is_retriable(&self) -> bool {
+        match *self {
+            Failure::Timeout(Timeout::Deadline(_)) | Failure::Misconfigured(_) => false,
+            Failure::Timeout(_) | Failure::Error(_) => true,
+            Failure::Status(status, _) => retriable_status(status)
+        }
+    }
+
+    /// Whether the instance that failed this way should be disabled until
+    /// it answers health checks: it couldn't be reached, or answered with a
+    /// server error
+    pub fn disables_instance(&self) -> bool {
+        match *self {
+            Failure::Error(_) | Failure::Status(500...599, _) => true,
+            Failure::Timeout(_) | Failure::Status(..) | Failure::Misconfigured(_) => false
+        }
+    }
+
+    pub fn into_error<E: RequestError>(self) -> E {
+        match self {
+            Failure::Timeout(timeout) => RequestError::timeout(timeout),
+            Failure::Status(status, body) => RequestError::status(status, body),
+            Failure::Error(error) | Failure::Misconfigured(error) => {
+                RequestError::connection(error)
+            }
+        }
+    }
+}
+
+impl fmt::String for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Failure::Timeout(ref timeout) => write!(f, "{}", timeout),
+            Failure::Status(status, ref body) => {
+                write!(f, "{}: {}", StatusCode::from_u16(status), body)
+            },
+            Failure::Error(ref error) | Failure::Misconfigured(ref error) => {
+                write!(f, "{}", error)
+            }
+        }
+    }
+}
+
+/// Convert a duration for use with the standard library's timeouts
+pub fn std_duration(duration: Duration) -> StdDuration {
+    StdDuration::from_millis(cmp::max(duration.num_milliseconds(), 0) as u64)
+}
+
+/// Undo the `Content-Encoding` of a response body
+pub fn decode_body(encoding: Option<&str>, body: Vec<u8>) -> Result<String, String> {
+    let body = match encoding {
+        Some(encoding) if encoding.eq_ignore_ascii_case("gzip") => {
+            let mut decoder = try!(GzDecoder::new(body.as_slice())
+                                   .map_err(|e| format!("Invalid gzip body: {}", e)));
+            try!(decoder.read_to_end().map_err(|e| format!("Invalid gzip body: {}", e)))
+        },
+        _ => body
+    };
+    Ok(String::from_utf8_lossy(body.as_slice()).into_owned())
+}
+
+/// Marks a connection error as the connect timeout expiring
+#[derive(Show)]
+struct ConnectTimedOut;
+
+impl fmt::String for ConnectTimedOut {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "connect timed out")
+    }
+}
+
+impl Error for ConnectTimedOut {
+    fn description(&self) -> &str {
+        "connect timed out"
+    }
+}
+
+/// Connects over TCP to each of a host's addresses in turn, giving up on
+/// each after the connect timeout
+#[derive(Clone)]
+struct TimeoutConnector {
+    timeout: Option<Duration>,
+    resolver: Arc<Resolver>
+}
+
+impl NetworkConnector for TimeoutConnector {
+    type Stream = HttpStream;
+
+    fn connect(&self, host: &str, port: u16, _: &str) -> hyper::Result<HttpStream> {
+        let mut error = io::Error::new(io::ErrorKind::InvalidInput,
+                                       format!("Can't resolve {}", host));
+        for address in try!(self.resolver.resolve(host, port)).iter() {
+            let result = match self.timeout {
+                Some(timeout) => TcpStream::connect_timeout(address, std_duration(timeout)),
+                None => TcpStream::connect(address)
+            };
+            match result {
+                Ok(stream) => return Ok(HttpStream(stream)),
+                Err(ref e) if e.kind() == io::ErrorKind::TimedOut => {
+                    error = io::Error::new(io::ErrorKind::TimedOut, ConnectTimedOut);
+                },
+                Err(e) => error = e
+            }
+        }
+        // the addresses may be stale
+        self.resolver.invalidate(host, port);
+        Err(hyper::Error::Io(error))
+    }
+}
+
+/// Opens a tunnel through an HTTP proxy with `CONNECT`, authenticating
+/// with the proxy, then sets up TLS through it to the instance
+#[derive(Clone)]
+struct TunnelConnector<S> {
+    proxy: Proxy,
+    connector: TimeoutConnector,
+    tls: S
+}
+
+impl<S: SslClient> NetworkConnector for TunnelConnector<S> {
+    type Stream = S::Stream;
+
+    fn connect(&self, host: &str, port: u16, _: &str) -> hyper::Result<S::Stream> {
+        let mut stream = try!(self.connector.connect(self.proxy.host.as_slice(), self.proxy.port,
+                                                     "http"));
+        try!(stream.write_all(self.proxy.connect_request(host, port).as_bytes()));
+        // read the head of the answer a byte at a time, leaving the tunnel
+        let mut head = vec!();
+        while !head.ends_with(b"\r\n\r\n") {
+            let mut byte = [0u8];
+            if try!(stream.read(&mut byte)) == 0 || head.len() > 8192 {
+                return Err(hyper::Error::Io(io::Error::new(io::ErrorKind::ConnectionAborted,
+                                                           "Proxy closed the tunnel")));
+            }
+            head.push(byte[0]);
+        }
+        if let Some(refused) = proxy::tunnel_refused(String::from_utf8_lossy(&head[]).as_slice()) {
+            // the proxy wants other credentials, which retrying won't change
+            let kind = if refused.contains(" 407 ") {
+                io::ErrorKind::InvalidInput
+            } else {
+                io::ErrorKind::ConnectionRefused
+            };
+            return Err(hyper::Error::Io(io::Error::new(kind, refused)));
+        }
+        self.tls.wrap_client(stream, host)
+    }
+}
+
+/// How connections to each instance are kept alive for reuse
+#[derive(Show, Clone, Copy, PartialEq)]
+pub struct PoolConfig {
+    /// Idle connections kept per instance
+    pub max_idle: usize,
+    /// How long a connection may sit idle before it is closed, None to keep
+    /// it until the server closes it
+    pub idle_timeout: Option<Duration>
+}
+
+impl Default for PoolConfig {
+    fn default() -> PoolConfig {
+        PoolConfig {
+            max_idle: 5,
+            idle_timeout: Some(Duration::seconds(90))
+        }
+    }
+}
+
+impl PoolConfig {
+    /// A pool of connections made by `connector`
+    fn pool<C: NetworkConnector>(&self, connector: C) -> Pool<C> {
+        let mut pool = Pool::with_connector(pool::Config { max_idle: self.max_idle },
+                                            connector);
+        pool.set_idle_timeout(self.idle_timeout.map(std_duration));
+        pool
+    }
+}
+
+/// A cap on the requests in flight to each instance
+#[derive(Show, Clone, Copy, PartialEq)]
+pub struct ConcurrencyLimit {
+    pub max_in_flight: usize,
+    /// What happens to requests beyond the cap
+    pub overflow: Overflow
+}
+
+/// What happens to a request when its instance is at its concurrency limit
+#[derive(Show, Clone, Copy, PartialEq)]
+pub enum Overflow {
+    /// Wait for one of the instance's requests to finish
+    Queue,
+    /// Go to another instance with room, waiting only when none has any
+    Spill
+}
+
+/// How disabled instances are probed before they are used again
+#[derive(Show, Clone, Copy, PartialEq)]
+pub struct HealthCheck {
+    /// Time between pings of a disabled instance
+    pub interval: Duration,
+    /// Successful pings in a row needed to enable the instance again
+    pub successes: u32
+}
+
+impl Default for HealthCheck {
+    fn default() -> HealthCheck {
+        HealthCheck {
+            interval: Duration::seconds(10),
+            successes: 3
+        }
+    }
+}
+
+/// A request to send to an instance: the path and query are relative to
+/// whichever instance ends up serving it
+#[derive(Show, Clone)]
+pub struct Request {
+    pub method: Method,
+    pub path: Vec<String>,
+    pub query: Vec<(String, String)>,
+    pub headers: Vec<(String, String)>,
+    pub body: Option<String>,
+    /// Instance to send the request to while it is available
+    pub instance: Option<Instance>
+}
+
+impl Request {
+    pub fn new(method: Method, path: Vec<String>) -> Request {
+        Request {
+            method: method,
+            path: path,
+            query: vec!(),
+            headers: vec!(),
+            body: None,
+            instance: None
+        }
+    }
+
+    /// Add a query parameter
+    pub fn param(mut self, name: &str, value: String) -> Request {
+        self.query.push((String::from_str(name), value));
+        self
+    }
+
+    /// Add several query parameters
+    pub fn params(mut self, params: Vec<(String, String)>) -> Request {
+        self.query.push_all(params.as_slice());
+        self
+    }
+
+    /// Set a header
+    pub fn header(mut self, name: &str, value: &str) -> Request {
+        self.headers.push((String::from_str(name), String::from_str(value)));
+        self
+    }
+
+    pub fn body(mut self, body: String) -> Request {
+        self.body = Some(body);
+        self
+    }
+
+    /// Send the request to `instance` while it is available, rather than
+    /// the balancer's pick - e.g. to keep related requests on one instance
+    pub fn pin(mut self, instance: Option<Instance>) -> Request {
+        self.instance = instance;
+        self
+    }
+
+    /// Whether the request only reads data: a GET, or a Flux query
+    pub fn read_only(&self) -> bool {
+        self.method == Method::Get || self.path.connect("/") == "api/v2/query"
+    }
+
+    /// Ask for a gzipped response, unless an encoding has been asked for
+    /// already
+    pub fn accept_gzip(self) -> Request {
+        let asked = self.headers.iter()
+            .any(|&(ref n, _)| n.as_slice().eq_ignore_ascii_case("Accept-Encoding"));
+        if asked {
+            self
+        } else {
+            self.header("Accept-Encoding", "gzip")
+        }
+    }
+}
+
+/// A response, read in full by a `Transport`
+#[derive(Show, Clone, PartialEq)]
+pub struct Response {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: String
+}
+
+impl Response {
+    /// The value of a header, matched case-insensitively
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.iter()
+            .find(|&&(ref n, _)| n.as_slice().eq_ignore_ascii_case(name))
+            .map(|&(_, ref value)| value.as_slice())
+    }
+}
+
+/// Sends requests to instances - implemented by `HyperTransport`, and
+/// replaceable to use another HTTP stack, a test double or a wrapper
+/// recording metrics
+pub trait Transport: Send + Sync {
+    /// Send a request to an instance and read the whole response
+    ///
+    /// A response with any status is Ok, errors are for requests that got no
+    /// response. Transports should enforce `timeouts.connect` and
+    /// `timeouts.request` where they can - the deadline is the cluster's job
+    fn send(&self, instance: &Instance, request: &Request,
+            timeouts: &Timeouts) -> Result<Response, Failure>;
+}
+
+/// A request sent through a `Canned` transport
+#[cfg(test)]
+#[derive(Show, Clone)]
+pub struct Sent {
+    /// 1 for the first request sent, 2 for the second...
+    pub number: usize,
+    pub instance: Instance,
+    pub request: Request,
+    pub timeouts: Timeouts,
+    /// When it was sent, from `time::precise_time_ns`
+    pub at: u64
+}
+
+/// A transport for tests, answering each request with a closure and
+/// keeping every request sent
+#[cfg(test)]
+pub struct Canned {
+    sent: Arc<Mutex<Vec<Sent>>>,
+    answer: Box<Fn(&Sent) -> Result<Response, Failure> + Send + Sync>
+}
+
+#[cfg(test)]
+impl Canned {
+    pub fn new<F>(answer: F) -> Canned
+        where F: Fn(&Sent) -> Result<Response, Failure> + Send + Sync + 'static
+    {
+        Canned { sent: Arc::new(Mutex::new(vec!())), answer: Box::new(answer) }
+    }
+
+    /// Answer every request with an empty response with `status`
+    pub fn status(status: u16) -> Canned {
+        Canned::new(move |_| reply(status, ""))
+    }
+
+    /// The requests sent so far, shared so they can still be read once the
+    /// transport is handed to a client
+    pub fn sent(&self) -> Arc<Mutex<Vec<Sent>>> {
+        self.sent.clone()
+    }
+}
+
+#[cfg(test)]
+impl Transport for Canned {
+    fn send(&self, instance: &Instance, request: &Request,
+            timeouts: &Timeouts) -> Result<Response, Failure> {
+        let sent = {
+            let mut sent = self.sent.lock().unwrap();
+            let number = sent.len() + 1;
+            sent.push(Sent {
+                number: number,
+                instance: instance.clone(),
+                request: request.clone(),
+                timeouts: *timeouts,
+                at: time::precise_time_ns()
+            });
+            sent[number - 1].clone()
+        };
+        (self.answer)(&sent)
+    }
+}
+
+/// A response with `status` and `body`
+#[cfg(test)]
+pub fn reply(status: u16, body: &str) -> Result<Response, Failure> {
+    Ok(Response { status: status, headers: vec!(), body: String::from_str(body) })
+}
+
+/// A response with `status` and `body` from a server of `version`
+#[cfg(test)]
+pub fn reply_as(version: &str, status: u16, body: &str) -> Result<Response, Failure> {
+    Ok(Response {
+        status: status,
+        headers: vec!((String::from_str("X-Influxdb-Version"), String::from_str(version))),
+        body: String::from_str(body)
+    })
+}
+
+/// A client sending every request through `transport` - it points at port
+/// 1, where nothing listens, so only the transport can answer
+#[cfg(test)]
+pub fn stub_client(auth: ::client::Auth, transport: Canned) -> ::client::Influx {
+    let mut influx = ::client::Influx::with_auth(Scheme::Http,
+                                                 Host::Domain(String::from_str("127.0.0.1")),
+                                                 1, auth);
+    influx.set_transport(Box::new(transport));
+    influx
+}
+
+/// A response being streamed, see `Cluster::open` - its request counts
+/// against the concurrency limit until it is dropped
+pub struct Streamed {
+    response: hyper::client::Response,
+    cluster: Arc<Cluster>,
+    instance: Instance
+}
+
+impl Deref for Streamed {
+    type Target = hyper::client::Response;
+
+    fn deref(&self) -> &hyper::client::Response {
+        &self.response
+    }
+}
+
+impl io::Reader for Streamed {
+    fn read(&mut self, buf: &mut [u8]) -> io::IoResult<usize> {
+        io::Reader::read(&mut self.response, buf)
+    }
+}
+
+impl Drop for Streamed {
+    fn drop(&mut self) {
+        self.cluster.request_finished(&self.instance);
+    }
+}
+
+/// The default transport, using hyper with a pool of connections for each
+/// instance
+pub struct HyperTransport {
+    /// A client, with its own connection pool, for each instance and set of
+    /// timeouts
+    clients: Mutex<HashMap<String, Arc<hyper::Client>>>,
+    pool_config: RwLock<PoolConfig>,
+    proxy: RwLock<ProxyConfig>,
+    resolver: Arc<Resolver>,
+    #[cfg(feature = "tls")]
+    tls: RwLock<Option<TlsConfig>>
+}
+
+impl Default for HyperTransport {
+    fn default() -> HyperTransport {
+        HyperTransport {
+            clients: Mutex::new(HashMap::new()),
+            pool_config: RwLock::new(Default::default()),
+            proxy: RwLock::new(Default::default()),
+            resolver: Arc::new(Resolver::new(Some(Duration::seconds(60)))),
+            #[cfg(feature = "tls")]
+            tls: RwLock::new(None)
+        }
+    }
+}
+
+impl HyperTransport {
+    pub fn new() -> HyperTransport {
+        Default::default()
+    }
+
+    /// Set which proxies requests are sent through - default none, see
+    /// `ProxyConfig::from_env` to use the environment's
+    pub fn set_proxy(&self, config: ProxyConfig) {
+        *self.proxy.write().unwrap() = config;
+        self.clients.lock().unwrap().clear();
+    }
+
+    /// Set how connections are kept alive for reuse - default
+    /// `PoolConfig::default()`
+    ///
+    /// Connections already open are closed as their requests finish
+    pub fn set_pool_config(&self, config: PoolConfig) {
+        *self.pool_config.write().unwrap() = config;
+        self.clients.lock().unwrap().clear();
+    }
+
+    /// Set how long the addresses of instance hosts are cached - default
+    /// 60s, None to resolve them for every connection
+    pub fn set_dns_ttl(&self, ttl: Option<Duration>) {
+        self.resolver.set_ttl(ttl);
+    }
+
+    /// The resolver for instance hosts
+    pub fn resolver(&self) -> Arc<Resolver> {
+        self.resolver.clone()
+    }
+
+    /// Set how TLS connections to HTTPS instances are made - default None
+    /// (hyper's default connector)
+    #[cfg(feature = "tls")]
+    pub fn set_tls(&self, config: TlsConfig) {
+        *self.tls.write().unwrap() = Some(config);
+        self.clients.lock().unwrap().clear();
+    }
+
+    /// Send a request to an instance, returning the response without reading
+    /// its body
+    pub fn open(&self, instance: &Instance, request: &Request,
+                timeouts: &Timeouts) -> Result<hyper::client::Response, Failure> {
+        let proxy = self.proxy.read().unwrap().for_instance(instance).map(|p| p.clone());
+        let client = self.client(instance, proxy.as_ref(), timeouts);
+        let url = build_url(instance, request);
+        let result = match proxy.and_then(|proxy| proxy.authorization()) {
+            Some(ref authorization) if instance.scheme == Scheme::Http => {
+                let request = request.clone().header("Proxy-Authorization",
+                                                     authorization.as_slice());
+                dispatch(&*client, url, &request)
+            },
+            _ => dispatch(&*client, url, request)
+        };
+        result.map_err(|e| failure(e, timeouts))
+    }
+
+    /// The client for an instance, created with its connection pool on first
+    /// use
+    fn client(&self, instance: &Instance, proxy: Option<&Proxy>,
+              timeouts: &Timeouts) -> Arc<hyper::Client> {
+        let key = format!("{}://{}:{} {:?} {:?}", instance.scheme, instance.host, instance.port,
+                          timeouts.connect, timeouts.request);
+        let mut clients = self.clients.lock().unwrap();
+        if let Some(client) = clients.get(&key) {
+            return client.clone();
+        }
+        let config = *self.pool_config.read().unwrap();
+        let connector = TimeoutConnector {
+            timeout: timeouts.connect,
+            resolver: self.resolver.clone()
+        };
+        let https = instance.scheme == Scheme::Https;
+        let mut client = match proxy {
+            // hyper's own tunnels don't authenticate with the proxy
+            Some(proxy) if https => hyper::Client::with_connector(config.pool(TunnelConnector {
+                proxy: proxy.clone(),
+                connector: connector,
+                tls: self.proxy_tls()
+            })),
+            Some(proxy) => hyper::Client::with_proxy_config(HyperProxyConfig::new(
+                "http", proxy.host.clone(), proxy.port, connector, self.proxy_tls())),
+            None => match self.tls_connector(https, connector.clone()) {
+                Some(connector) => hyper::Client::with_connector(config.pool(connector)),
+                None => hyper::Client::with_connector(config.pool(connector))
+            }
+        };
+        client.set_read_timeout(timeouts.request.map(std_duration));
+        client.set_write_timeout(timeouts.request.map(std_duration));
+        let client = Arc::new(client);
+        clients.insert(key, client.clone());
+        client
+    }
+
+    /// The connector to use for TLS, if the instance is HTTPS and TLS has
+    /// been configured
+    #[cfg(feature = "tls")]
+    fn tls_connector(&self, https: bool, connector: TimeoutConnector)
+                     -> Option<HttpsConnector<::tls::TlsClient, TimeoutConnector>> {
+        if !https {
+            return None;
+        }
+        self.tls.read().unwrap().as_ref().map(|config| {
+            HttpsConnector::with_connector(config.client(), connector)
+        })
+    }
+
+    #[cfg(not(feature = "tls"))]
+    fn tls_connector(&self, _: bool, _: TimeoutConnector) -> Option<TimeoutConnector> {
+        None
+    }
+
+    /// The TLS client for HTTPS tunnelled through a proxy
+    #[cfg(feature = "tls")]
+    fn proxy_tls(&self) -> TlsClient {
+        match *self.tls.read().unwrap() {
+            Some(ref config) => config.client(),
+            None => TlsConfig::new().client()
+        }
+    }
+
+    #[cfg(not(feature = "tls"))]
+    fn proxy_tls(&self) -> NoTls {
+        NoTls
+    }
+}
+
+/// Refuses HTTPS tunnelled through a proxy, without the `tls` feature
+#[cfg(not(feature = "tls"))]
+struct NoTls;
+
+#[cfg(not(feature = "tls"))]
+impl SslClient for NoTls {
+    type Stream = HttpStream;
+
+    fn wrap_client(&self, _: HttpStream, host: &str) -> hyper::Result<HttpStream> {
+        Err(hyper::Error::Io(io::Error::new(io::ErrorKind::InvalidInput,
+                                            format!("HTTPS to {} needs the tls feature", host))))
+    }
+}
+
+impl Transport for HyperTransport {
+    fn send(&self, instance: &Instance, request: &Request,
+            timeouts: &Timeouts) -> Result<Response, Failure> {
+        let mut res = try!(self.open(instance, &request.clone().accept_gzip(), timeouts));
+        let body = try!(res.read_to_end().map_err(|e| failure(hyper::Error::Io(e), timeouts)));
+        let mut response = Response {
+            status: res.status.to_u16(),
+            headers: header_list(&res.headers),
+            body: String::new()
+        };
+        response.body = try!(decode_body(response.header("Content-Encoding"), body)
+                             .map_err(Failure::Error));
+        Ok(response)
+    }
+}
+
+/// Outcome of a single attempt at a request
+enum Attempt {
+    /// 2xx response, with the body
+    Success(String),
+    /// Failed in a way that may succeed if retried, no sooner than the delay
+    /// the server asked for, if it did
+    Retry(Failure, Option<Duration>),
+    /// Failed permanently
+    Fail(Failure)
+}
+
+impl Attempt {
+    /// Retry or fail, as suits `failure`, retrying no sooner than `after`
+    fn failed(failure: Failure, after: Option<Duration>) -> Attempt {
+        if failure.is_retriable() {
+            Attempt::Retry(failure, after)
+        } else {
+            Attempt::Fail(failure)
         }
     }
 }
 
+/// The copies of a replicated request acknowledged or failed so far, and
+/// where its result goes once it has one
+struct Replicas {
+    acked: usize,
+    failed: usize,
+    complete: Option<oneshot::Sender<Result<(), Failure>>>
+}
+
+/// The attempts of a hedged request still running, and where its result
+/// goes until it has one
+struct Hedge {
+    racing: usize,
+    /// Whether a second instance has been tried, or it's too late to
+    hedged: bool,
+    complete: Option<oneshot::Sender<Result<String, Failure>>>
+}
+
 /// A cluster takes requests for operations and performs them
 /// on a cluster of influxdb instances, transparently handling
 /// replication/load balancing
 pub struct Cluster {
-    hyper_client: hyper::Client<hyper::net::HttpConnector>,
-    request_timeout: Option<u32>,
-    failover_timeout: Arc<Mutex<Duration>>,
+    /// The default transport, also used for streaming responses
+    hyper: HyperTransport,
+    /// A transport replacing the default, if set
+    transport: RwLock<Option<Box<Transport + Send + Sync>>>,
+    timeouts: RwLock<Timeouts>,
+    /// Delay before health checks start on an instance, growing each time
+    /// it is disabled
+    failover_backoff: RwLock<ExponentialBackoff>,
+    /// Times each instance has been disabled since it last answered a
+    /// request
+    failovers: Mutex<Vec<(Instance, u32)>>,
+    health_check: RwLock<HealthCheck>,
     instances_available: Arc<Mutex<Vec<Instance>>>,
     instances_disabled: Arc<Mutex<Vec<Instance>>>,
-    instances_available_pointer: Mutex<usize>,
-    pending_request_threads: Vec<Thread>
+    balancer: RwLock<Box<Balancer + Send + Sync>>,
+    retry_policy: RwLock<Box<RetryPolicy + Send + Sync>>,
+    middleware: RwLock<Vec<Box<Middleware + Send + Sync>>>,
+    signer: RwLock<Option<Box<RequestSigner + Send + Sync>>>,
+    listeners: RwLock<Vec<Box<Fn(&TopologyEvent) + Send + Sync>>>,
+    /// Called on shutdown with the time left, returning whether they
+    /// finished, by the id `on_shutdown` gave them
+    shutdown_hooks: RwLock<Vec<(usize, Box<Fn(Duration) -> bool + Send + Sync>)>>,
+    /// The id of the next shutdown hook
+    next_hook: Mutex<usize>,
+    concurrency_limit: RwLock<Option<ConcurrencyLimit>>,
+    /// How long a read-only request waits before it is hedged
+    hedge_after: RwLock<Option<Duration>>,
+    /// Requests in flight to each instance that has any
+    in_flight: Mutex<Vec<(Instance, usize)>>,
+    /// Signalled when a request finishes, for requests waiting for room
+    request_done: Condvar,
+    server_version: RwLock<Option<String>>,
+    #[cfg(feature = "tokio")]
+    reactor: RwLock<Option<Remote>>,
+    /// Threads performing requests, started on the first request
+    workers: Mutex<Option<Arc<WorkerPool>>>,
+    /// Number of worker threads, and of requests that may wait for one
+    worker_limits: RwLock<(usize, usize)>,
+    /// Counters of the requests sent, for `metrics`
+    metrics: Mutex<Metrics>,
+    /// Starts the second attempts of hedged requests
+    timer: Timer
 }
 
 impl Default for Cluster {
     fn default() -> Cluster {
         Cluster {
-            hyper_client: hyper::Client::new(),
-            request_timeout: None,
-            failover_timeout: Arc::new(Mutex::new(Duration::seconds(60))),
+            hyper: HyperTransport::new(),
+            transport: RwLock::new(None),
+            timeouts: RwLock::new(Default::default()),
+            failover_backoff: RwLock::new(ExponentialBackoff {
+                base: Duration::seconds(60),
+                max_delay: Duration::minutes(10),
+                ..Default::default()
+            }),
+            failovers: Mutex::new(vec!()),
+            health_check: RwLock::new(Default::default()),
             instances_available: Arc::new(Mutex::new(vec!(Default::default()))),
             instances_disabled: Arc::new(Mutex::new(vec!())),
-            instances_available_pointer: Mutex::new(0),
-            pending_request_threads: vec!()
+            balancer: RwLock::new(Box::new(RoundRobin::new())),
+            retry_policy: RwLock::new(Box::new(ExponentialBackoff::default())),
+            middleware: RwLock::new(vec!()),
+            signer: RwLock::new(None),
+            listeners: RwLock::new(vec!()),
+            shutdown_hooks: RwLock::new(vec!()),
+            next_hook: Mutex::new(0),
+            concurrency_limit: RwLock::new(None),
+            hedge_after: RwLock::new(None),
+            in_flight: Mutex::new(vec!()),
+            request_done: Condvar::new(),
+            server_version: RwLock::new(None),
+            #[cfg(feature = "tokio")]
+            reactor: RwLock::new(None),
+            workers: Mutex::new(None),
+            worker_limits: RwLock::new((16, 1024)),
+            metrics: Mutex::new(Default::default()),
+            timer: Timer::new()
         }
     }
 }
 
+/// Shuts the cluster down once its last handle is gone, flushing anything
+/// the shutdown hooks still buffer and waiting for the requests outstanding,
+/// within 30 seconds
+impl Drop for Cluster {
+    fn drop(&mut self) {
+        self.shutdown(Duration::seconds(30));
+    }
+}
+
 impl Cluster {
 
-    /// Add a new host to available hosts
-    fn add_host(&mut self, new_host: Instance) {
-        self.instances_available.lock().unwrap().push(new_host);
+    /// Create a cluster from a list of instances
+    pub fn new(instances: Vec<Instance>) -> Cluster {
+        let cluster: Cluster = Default::default();
+        *cluster.instances_available.lock().unwrap() = instances;
+        cluster
     }
 
-    /// Get an instance if any are available, or None if not
-    fn get_instance(&mut self) -> Option<Instance> {
-        let instances_available = self.instances_available.lock().unwrap();
-        let mut instances_available_pointer = self.instances_available_pointer.lock().unwrap();
-        if instances_available.is_empty() {
-            None
-        } else {
-            if *instances_available_pointer > instances_available.len() {
-                *instances_available_pointer = 0;
-            }
-            let host = instances_available[*instances_available_pointer].clone();
-            *instances_available_pointer += 1;
-            Some(host)
-        }
+    /// Send requests with the given transport instead of the default
+    /// `HyperTransport`, and instead of on any reactor
+    ///
+    /// Streamed responses (chunked queries and backups) are still sent with
+    /// the default, see `open`, and the proxy, pool and TLS configuration
+    /// only applies to the default
+    pub fn set_transport(&self, transport: Box<Transport + Send + Sync>) {
+        *self.transport.write().unwrap() = Some(transport);
     }
 
-    /// Enable an instance by moving it to the instances_available vector
-    fn enable_instance(&mut self, pos: usize) {
-        let host = self.instances_disabled.lock().unwrap().remove(pos);
-        self.instances_available.lock().unwrap().push(host);
-    }
-
-    /// Disable an instance, and schedule it for reenabling after failover_timeout
-    fn disable_instance(&mut self, pos: usize) {
-        let host = self.instances_available.lock().unwrap().remove(pos);
-        self.instances_disabled.lock().unwrap().push(host.clone());
-        // schedule attempt to reenable
-        let instances_disabled = self.instances_disabled.clone();
-        let instances_available = self.instances_available.clone();
-        let timeout = self.failover_timeout.lock().unwrap().clone();
-        Thread::spawn(move || {
-            io::timer::sleep(timeout);
-            Cluster::remove_instance_by_attrs(instances_disabled, host.clone());
-            instances_available.lock().unwrap().push(host);
-        });
+    /// Set how long to wait for a connection to an instance - default None
+    /// (no limit)
+    pub fn set_connect_timeout(&self, value: Option<Duration>) {
+        self.timeouts.write().unwrap().connect = value;
     }
 
-    /// Helper function to remote a host by attrs
-    ///
-    /// We do this because we cannot garuntee the position of the host (as
-    /// other hosts will have been removed/added since) in disable_instance
-    fn remove_instance_by_attrs(instances: Arc<Mutex<Vec<Instance>>>,
-                                instance: Instance) {
-        let mut instances = instances.lock().unwrap();
-        instances.retain(|ref x| **x != instance);
-    }
-
-    /// Creates a url for a request
-    fn build_url(&self,
-                 instance: Instance,
-                 path: Vec<String>,
-                 query: Vec<(String, String)>) -> Url {
-        Url {
-            // bit before ://
-            scheme: format!("{}", instance.scheme),
-            // bit after :// before ?
-            scheme_data: SchemeData::Relative(RelativeSchemeData {
-                username: String::from_str(""),
-                password: None,
-                host: instance.host.clone(),
-                port: Some(instance.port),
-                default_port: Some(instance.port), // TODO what to do here?
-                path: path
-            }),
-            // Bit after ? before #
-            query: Some(::url::form_urlencoded::serialize_owned(query.as_slice())),
-            // Bit after #
-            fragment: None
-        }
-    }
-
-    /// Builds a request and sends it - returning the request status, which can be
-    /// queried like a future
-    fn request(&mut self,
-                     method: Method,
-                     path: Vec<String>,
-                     query: Option<Vec<(String, String)>>) -> Arc<RwLock<RequestStatus<(), ()>>> {
-        // TODO handle no hosts more gracefully
-        let instance = self.get_instance().expect("No hosts left");
-        let mut query = match query {
-            Some(vec) => {
-                let mut vec = vec.clone();
-                vec.reserve(2);
-                vec
-            },
-            None => {Vec::with_capacity(2)}
-        };
-        query.push((String::from_str("u"), self.username.clone()));
-        query.push((String::from_str("p"), self.password.clone()));
-        let url = self.build_url(instance, path, query);
-        let mut builder = self.hyper_client.request(method, url);
-        let response = Arc::new(RwLock::new(RequestStatus::new()));
-        let moved_response = response.clone();
+    /// Set how long an instance may go without sending or receiving during a
+    /// request - default None (no limit)
+    pub fn set_request_timeout(&self, value: Option<Duration>) {
+        self.timeouts.write().unwrap().request = value;
+    }
 
-        Thread::spawn(move || {
-             
-        });
-        response
+    /// Set how long a request may take overall, including retries - default
+    /// None (no limit)
+    pub fn set_deadline(&self, value: Option<Duration>) {
+        self.timeouts.write().unwrap().deadline = value;
+    }
+
+    /// The time limits on requests
+    pub fn timeouts(&self) -> Timeouts {
+        *self.timeouts.read().unwrap()
+    }
+
+    /// Set failover timeout, how long an instance disabled for the first
+    /// time is left before health checks start - default 60s
+    pub fn set_failover_timeout(&self, value: Duration) {
+        self.failover_backoff.write().unwrap().base = value;
+    }
+
+    /// Set how long disabled instances are left before health checks start,
+    /// growing each time an instance is disabled again without answering a
+    /// request in between - default from 60s up to 10 minutes, with half
+    /// jitter. `max_retries` is ignored
+    pub fn set_failover_backoff(&self, backoff: ExponentialBackoff) {
+        *self.failover_backoff.write().unwrap() = backoff;
+    }
+
+    /// Note `instance` has answered a request, so the next time it is
+    /// disabled is treated as the first
+    pub fn forget_failovers(&self, instance: &Instance) {
+        let mut failovers = self.failovers.lock().unwrap();
+        if !failovers.is_empty() {
+            failovers.retain(|&(ref i, _)| i != instance);
+        }
+    }
+
+    /// Count `instance` being disabled, returning how many times in a row
+    /// it has been
+    fn count_failover(&self, instance: &Instance) -> u32 {
+        let mut failovers = self.failovers.lock().unwrap();
+        for &mut (ref i, ref mut count) in failovers.iter_mut() {
+            if i == instance {
+                *count += 1;
+                return *count;
+            }
+        }
+        failovers.push((instance.clone(), 1));
+        1
+    }
+
+    /// Set how disabled instances are probed before they are used again -
+    /// default `HealthCheck::default()`
+    pub fn set_health_check(&self, check: HealthCheck) {
+        *self.health_check.write().unwrap() = check;
+    }
+
+    /// Set the policy for retrying failed requests - default
+    /// `ExponentialBackoff::default()`
+    ///
+    /// A retry waits at least as long as a 429 or 503 response's
+    /// `Retry-After` asks, within the deadline.
+    pub fn set_retry_policy(&self, policy: Box<RetryPolicy + Send + Sync>) {
+        *self.retry_policy.write().unwrap() = policy;
+    }
+
+    /// Set how requests are spread over the available instances - default
+    /// `RoundRobin`
+    pub fn set_balancer(&self, balancer: Box<Balancer + Send + Sync>) {
+        *self.balancer.write().unwrap() = balancer;
+    }
+
+    /// Add middleware to run around every request, after any already added
+    pub fn add_middleware(&self, middleware: Box<Middleware + Send + Sync>) {
+        self.middleware.write().unwrap().push(middleware);
+    }
+
+    /// `request` as it goes to `instance`: modified by the middleware, then
+    /// signed last, so the signature covers every header it is sent with
+    ///
+    /// `gzip` asks for a gzipped response, for transports that decode them.
+    pub fn outgoing(&self, instance: &Instance, request: &Request, gzip: bool) -> Request {
+        let mut request = self.before(request);
+        if gzip {
+            request = request.accept_gzip();
+        }
+        self.sign(instance, &mut request);
+        request
+    }
+
+    /// A request as modified by the middleware
+    fn before(&self, request: &Request) -> Request {
+        let mut request = request.clone();
+        for middleware in self.middleware.read().unwrap().iter() {
+            middleware.before(&mut request);
+        }
+        request
+    }
+
+    /// Sign every request just before it is sent, after the middleware has
+    /// run - default None
+    pub fn set_signer(&self, signer: Box<RequestSigner + Send + Sync>) {
+        *self.signer.write().unwrap() = Some(signer);
+    }
+
+    /// Sign a request for `instance`, if there is a signer
+    fn sign(&self, instance: &Instance, request: &mut Request) {
+        if let Some(ref signer) = *self.signer.read().unwrap() {
+            let url = build_url(instance, request);
+            let hash = signing::body_hash(request.body.as_ref().map_or("", |b| b.as_slice()));
+            signer.sign(&request.method, &url, &mut request.headers, hash.as_slice());
+        }
+    }
+
+    /// Show a response, received `start` (in ns) after its request was
+    /// sent, to the middleware and note the server version it reports
+    pub fn received(&self, status: u16, headers: &[(String, String)], start: u64) {
+        let response = middleware::Response {
+            status: status,
+            headers: headers.to_vec(),
+            elapsed: Duration::nanoseconds((time::precise_time_ns() - start) as i64)
+        };
+        for middleware in self.middleware.read().unwrap().iter() {
+            middleware.after(&response);
+        }
+        if let Some(version) = response.header("X-Influxdb-Version") {
+            *self.server_version.write().unwrap() = Some(String::from_str(version));
+        }
+    }
+
+    /// Perform requests on a tokio reactor rather than a thread each -
+    /// default None
+    ///
+    /// Ignored while a transport is set, as transports block.
+    #[cfg(feature = "tokio")]
+    pub fn set_reactor(&self, remote: Remote) {
+        *self.reactor.write().unwrap() = Some(remote);
+    }
+
+    /// Set which proxies requests are sent through - default none, see
+    /// `ProxyConfig::from_env` to use the environment's
+    pub fn set_proxy(&self, config: ProxyConfig) {
+        self.hyper.set_proxy(config);
+    }
+
+    /// Set how connections are kept alive for reuse - default
+    /// `PoolConfig::default()`
+    pub fn set_pool_config(&self, config: PoolConfig) {
+        self.hyper.set_pool_config(config);
+    }
+
+    /// Set how TLS connections to HTTPS instances are made - default None
+    /// (hyper's default connector)
+    #[cfg(feature = "tls")]
+    pub fn set_tls(&self, config: TlsConfig) {
+        self.hyper.set_tls(config);
+    }
+
+    /// Set how long the addresses of instance hosts are cached - default
+    /// 60s, None to resolve them for every connection
+    pub fn set_dns_ttl(&self, ttl: Option<Duration>) {
+        self.hyper.set_dns_ttl(ttl);
+    }
+
+    /// The resolver for instance hosts
+    pub fn resolver(&self) -> Arc<Resolver> {
+        self.hyper.resolver()
+    }
+
+    /// The server version, as reported by the most recent response
+    pub fn server_version(&self) -> Option<String> {
+        self.server_version.read().unwrap().clone()
+    }
+
+    /// Note the server version, found other than from a response header
+    pub fn set_server_version(&self, version: &str) {
+        *self.server_version.write().unwrap() = Some(String::from_str(version));
+    }
+
+    /// How long to wait before the given retry of a request, or None to
+    /// give up
+    pub fn retry_delay(&self, retries: u32) -> Option<Duration> {
+        self.retry_policy.read().unwrap().backoff(retries)
+    }
+
+    /// Returns a copy of the vector of available hosts
+    pub fn get_instances_available(&self) -> Vec<Instance> {
+        self.instances_available.lock().unwrap().clone()
+    }
+
+    /// Returns a copy of the vector of disabled hosts
+    pub fn get_instances_disabled(&self) -> Vec<Instance> {
+        self.instances_disabled.lock().unwrap().clone()
+    }
+
+    /// Add an instance to the available instances
+    pub fn add_host(&self, instance: Instance) {
+        self.instances_available.lock().unwrap().push(instance.clone());
+        self.notify(&TopologyEvent::Added(instance));
+    }
+
+    /// Set how many threads perform requests, and how many requests may wait
+    /// for one before making more wait to be queued - default 16 and 1024
+    ///
+    /// Requests already queued finish on the previous threads
+    pub fn set_worker_threads(&self, threads: usize, queue_size: usize) {
+        *self.worker_limits.write().unwrap() = (cmp::max(threads, 1), cmp::max(queue_size, 1));
+        *self.workers.lock().unwrap() = None;
+    }
+
+    /// The threads performing requests, started if they haven't been yet
+    fn workers(&self) -> Arc<WorkerPool> {
+        let mut workers = self.workers.lock().unwrap();
+        if workers.is_none() {
+            let (threads, queue_size) = *self.worker_limits.read().unwrap();
+            *workers = Some(Arc::new(WorkerPool::new(threads, queue_size)));
+        }
+        workers.as_ref().unwrap().clone()
+    }
+
+    /// Set a cap on the requests in flight to each instance - default None
+    /// (no limit)
+    ///
+    /// Requests on a reactor never wait, so only spill to other instances
+    pub fn set_concurrency_limit(&self, limit: Option<ConcurrencyLimit>) {
+        *self.concurrency_limit.write().unwrap() = limit;
+        self.request_done.notify_all();
+    }
+
+    /// Hedge read-only requests: when the first instance hasn't answered
+    /// within `after`, send the request to a second too and use whichever
+    /// answers first - default None (never hedge)
+    ///
+    /// The slower request is abandoned, its response discarded. Requests on
+    /// a reactor aren't hedged
+    pub fn set_hedge_after(&self, after: Option<Duration>) {
+        *self.hedge_after.write().unwrap() = after;
+    }
+
+    /// Call `listener` whenever an instance is added, removed, disabled or
+    /// enabled again
+    pub fn on_topology_change(&self, listener: Box<Fn(&TopologyEvent) + Send + Sync>) {
+        self.listeners.write().unwrap().push(listener);
+    }
+
+    /// Call `hook` on shutdown, with the time left before the deadline, to
+    /// flush anything buffered - it returns whether it finished in time
+    ///
+    /// Returns an id to remove the hook with
+    pub fn on_shutdown(&self, hook: Box<Fn(Duration) -> bool + Send + Sync>) -> usize {
+        let mut next_hook = self.next_hook.lock().unwrap();
+        let id = *next_hook;
+        *next_hook += 1;
+        self.shutdown_hooks.write().unwrap().push((id, hook));
+        id
+    }
+
+    /// Stop calling the shutdown hook `on_shutdown` returned `id` for
+    pub fn remove_shutdown_hook(&self, id: usize) {
+        self.shutdown_hooks.write().unwrap().retain(|&(hook, _)| hook != id);
+    }
+
+    /// Run the shutdown hooks, then wait for the requests outstanding to
+    /// finish, all within `timeout` - true if everything finished in time
+    ///
+    /// The cluster can still be used afterwards
+    pub fn shutdown(&self, timeout: Duration) -> bool {
+        let deadline = time::precise_time_ns() + timeout.num_nanoseconds().unwrap_or(0) as u64;
+        let remaining = || {
+            let now = time::precise_time_ns();
+            Duration::nanoseconds(if now < deadline { (deadline - now) as i64 } else { 0 })
+        };
+        let mut finished = true;
+        for &(_, ref hook) in self.shutdown_hooks.read().unwrap().iter() {
+            finished = hook(remaining()) && finished;
+        }
+        let workers = self.workers.lock().unwrap().clone();
+        match workers {
+            // a job can't wait for itself to finish
+            Some(_) if workers::on_worker_thread() => finished,
+            Some(workers) => workers.wait_idle(remaining()) && finished,
+            None => finished
+        }
+    }
+
+    /// Tell the listeners about a change to the instances
+    fn notify(&self, event: &TopologyEvent) {
+        for listener in self.listeners.read().unwrap().iter() {
+            listener(event);
+        }
+    }
+
+    /// Replace the cluster's instances with `instances`, e.g. as found by
+    /// discovery
+    ///
+    /// Instances no longer listed are removed, whether available or
+    /// disabled, and new ones are added as available. Those already known
+    /// keep their state, taking the role and weight listed
+    pub fn set_hosts(&self, instances: Vec<Instance>) {
+        let listed = |instance: &Instance| instances.iter().find(|i| i.same_address(instance));
+        let mut events = vec!();
+        {
+            let mut available = self.instances_available.lock().unwrap();
+            let mut disabled = self.instances_disabled.lock().unwrap();
+            for instance in available.iter().chain(disabled.iter()) {
+                if listed(instance).is_none() {
+                    events.push(TopologyEvent::Removed(instance.clone()));
+                }
+            }
+            available.retain(|instance| listed(instance).is_some());
+            disabled.retain(|instance| listed(instance).is_some());
+            for instance in available.iter_mut().chain(disabled.iter_mut()) {
+                if let Some(update) = listed(instance) {
+                    *instance = update.clone();
+                }
+            }
+            for instance in instances.iter() {
+                let known = available.iter().chain(disabled.iter())
+                    .any(|i| i.same_address(instance));
+                if !known {
+                    available.push(instance.clone());
+                    events.push(TopologyEvent::Added(instance.clone()));
+                }
+            }
+        }
+        // with the locks released, so listeners can look at the cluster
+        for event in events.iter() {
+            self.notify(event);
+        }
+    }
+
+    /// Add an instance to the available instances, with the given share of
+    /// the traffic relative to the others
+    pub fn add_host_weighted(&self, instance: Instance, weight: u32) {
+        self.add_host(Instance { weight: weight, ..instance });
+    }
+
+    /// Remove the instance at the same address as `instance`, whether it is
+    /// available or disabled, returning whether there was one
+    pub fn remove_host(&self, instance: &Instance) -> bool {
+        let removed = {
+            let mut available = self.instances_available.lock().unwrap();
+            let mut disabled = self.instances_disabled.lock().unwrap();
+            match available.iter().position(|i| i.same_address(instance)) {
+                Some(pos) => Some(available.remove(pos)),
+                None => disabled.iter().position(|i| i.same_address(instance))
+                    .map(|pos| disabled.remove(pos))
+            }
+        };
+        match removed {
+            Some(removed) => {
+                self.notify(&TopologyEvent::Removed(removed));
+                true
+            },
+            None => false
+        }
+    }
+
+    /// The available instances whose role suits `request`, or all of them
+    /// if none do
+    fn candidates(&self, request: &Request) -> Vec<Instance> {
+        let mut available = self.get_instances_available();
+        if available.is_empty() {
+            // keep trying disabled instances rather than failing every
+            // request until the health checks pass
+            available = self.get_instances_disabled();
+        }
+        let read_only = request.read_only();
+        let preferred: Vec<Instance> = available.iter()
+            .filter(|instance| instance.role.serves(read_only))
+            .cloned()
+            .collect();
+        let candidates = if preferred.is_empty() { available } else { preferred };
+        match *self.concurrency_limit.read().unwrap() {
+            Some(limit) if limit.overflow == Overflow::Spill => {
+                let in_flight = self.in_flight.lock().unwrap();
+                let roomy: Vec<Instance> = candidates.iter()
+                    .filter(|instance| count(&*in_flight, *instance) < limit.max_in_flight)
+                    .cloned()
+                    .collect();
+                if roomy.is_empty() { candidates } else { roomy }
+            },
+            _ => candidates
+        }
+    }
+
+    /// Get an instance to send `request` to - a disabled one if none are
+    /// available, or None if the cluster has no instances
+    pub fn get_instance(&self, request: &Request) -> Option<Instance> {
+        if let Some(ref pinned) = request.instance {
+            if self.instances_available.lock().unwrap().contains(pinned) {
+                return Some(pinned.clone());
+            }
+        }
+        let candidates = self.candidates(request);
+        if candidates.is_empty() {
+            None
+        } else {
+            let index = self.balancer.read().unwrap().pick_for(candidates.as_slice(), request);
+            Some(candidates[index % candidates.len()].clone())
+        }
+    }
+
+    /// Note a request to `instance` is being sent
+    ///
+    /// With `wait` set to when the request started (in ns), first waits for
+    /// room under the concurrency limit, failing if the deadline passes
+    /// before there is.
+    pub fn request_started(&self, instance: &Instance, wait: Option<u64>) -> Result<(), Failure> {
+        let deadline = self.timeouts().deadline;
+        let mut in_flight = self.in_flight.lock().unwrap();
+        if let Some(start) = wait {
+            loop {
+                match *self.concurrency_limit.read().unwrap() {
+                    Some(limit) if count(&*in_flight, instance) >= limit.max_in_flight => {},
+                    _ => break
+                }
+                in_flight = match deadline {
+                    Some(limit) => {
+                        let elapsed = time::precise_time_ns() - start;
+                        let elapsed = Duration::nanoseconds(elapsed as i64);
+                        if elapsed >= limit {
+                            return Err(Failure::Timeout(Timeout::Deadline(limit)));
+                        }
+                        self.request_done.wait_timeout(in_flight, limit - elapsed).unwrap().0
+                    },
+                    None => self.request_done.wait(in_flight).unwrap()
+                };
+            }
+        }
+        match in_flight.iter().position(|&(ref i, _)| i == instance) {
+            Some(pos) => in_flight[pos].1 += 1,
+            None => in_flight.push((instance.clone(), 1))
+        }
+        drop(in_flight);
+        self.balancer.read().unwrap().started(instance);
+        Ok(())
+    }
+
+    /// Note a request to `instance` has been answered or has failed
+    pub fn request_finished(&self, instance: &Instance) {
+        {
+            let mut in_flight = self.in_flight.lock().unwrap();
+            if let Some(pos) = in_flight.iter().position(|&(ref i, _)| i == instance) {
+                in_flight[pos].1 -= 1;
+                if in_flight[pos].1 == 0 {
+                    in_flight.remove(pos);
+                }
+            }
+        }
+        self.request_done.notify_all();
+        self.balancer.read().unwrap().finished(instance);
+    }
+
+    /// Enable an instance by moving it to the instances_available vector
+    ///
+    /// Instances are found by address, as their positions shift while
+    /// others are added and removed. Returns whether it was disabled
+    fn enable_instance(&self, instance: &Instance) -> bool {
+        let enabled = self.move_instance(instance, true);
+        if let Some(ref host) = enabled {
+            self.notify(&TopologyEvent::Enabled(host.clone()));
+        }
+        enabled.is_some()
+    }
+
+    /// Disable an instance, and after the failover backoff start pinging it,
+    /// reenabling it once enough pings in a row succeed
+    ///
+    /// The pings are scheduled on the cluster's timer and sent from its
+    /// workers, and don't keep the cluster alive.
+    pub fn disable_instance(cluster: &Arc<Cluster>, instance: &Instance) {
+        let host = match cluster.move_instance(instance, false) {
+            Some(host) => host,
+            None => return
+        };
+        cluster.notify(&TopologyEvent::Disabled(host.clone()));
+        let failovers = cluster.count_failover(&host);
+        let timeout = cluster.failover_backoff.read().unwrap().delay(failovers);
+        Cluster::probe(cluster, host, timeout, 0);
+    }
+
+    /// Ping the disabled `host` after `delay`, from a worker, with
+    /// `successes` pings in a row already answered
+    fn probe(cluster: &Arc<Cluster>, host: Instance, delay: Duration, successes: u32) {
+        let weak: Weak<Cluster> = cluster.downgrade();
+        cluster.timer.schedule(delay, move || {
+            let cluster = match weak.upgrade() {
+                Some(cluster) => cluster,
+                None => return
+            };
+            let workers = cluster.workers();
+            workers.execute(move || {
+                let disabled = cluster.instances_disabled.lock().unwrap().iter()
+                    .any(|i| i.same_address(&host));
+                if !disabled {
+                    // removed from the cluster meanwhile
+                    return;
+                }
+                let check = *cluster.health_check.read().unwrap();
+                let successes = if cluster.ping(&host) { successes + 1 } else { 0 };
+                if successes >= check.successes {
+                    cluster.enable_instance(&host);
+                } else {
+                    Cluster::probe(&cluster, host, check.interval, successes);
+                }
+            });
+        });
+    }
+
+    /// Whether `instance` answers a `/ping` with a 2xx status
+    fn ping(&self, instance: &Instance) -> bool {
+        let ping = Request::new(Method::Get, vec!(String::from_str("ping")));
+        let gzip = self.transport.read().unwrap().is_none();
+        let request = self.outgoing(instance, &ping, gzip);
+        let timeouts = self.timeouts();
+        let result = match *self.transport.read().unwrap() {
+            Some(ref transport) => transport.send(instance, &request, &timeouts),
+            None => self.hyper.send(instance, &request, &timeouts)
+        };
+        result.map(|res| res.status >= 200 && res.status < 300).unwrap_or(false)
+    }
+
+    /// Move the instance at the same address as `instance` to the available
+    /// instances if `enable`, otherwise to the disabled ones
+    ///
+    /// Both locks are held (available first, as everywhere) so the instance
+    /// is never in neither list or both
+    fn move_instance(&self, instance: &Instance, enable: bool) -> Option<Instance> {
+        let mut available = self.instances_available.lock().unwrap();
+        let mut disabled = self.instances_disabled.lock().unwrap();
+        let (from, to) = if enable {
+            (&mut *disabled, &mut *available)
+        } else {
+            (&mut *available, &mut *disabled)
+        };
+        from.iter().position(|i| i.same_address(instance)).map(|pos| {
+            let host = from.remove(pos);
+            to.push(host.clone());
+            host
+        })
+    }
+
+    /// The timeouts of an attempt at a request started at `start` (in ns):
+    /// the connect and request timeouts, capped at the time left before the
+    /// deadline, or the deadline failure if there is none left
+    ///
+    /// Capped timeouts are rounded up to the second, so attempts share the
+    /// connection pools of a few sets of timeouts.
+    fn attempt_timeouts(&self, start: u64) -> Result<Timeouts, Failure> {
+        let mut timeouts = self.timeouts();
+        if let Some(limit) = timeouts.deadline {
+            let elapsed = Duration::nanoseconds((time::precise_time_ns() - start) as i64);
+            if elapsed >= limit {
+                return Err(Failure::Timeout(Timeout::Deadline(limit)));
+            }
+            let left = Duration::seconds(((limit - elapsed).num_milliseconds() + 999) / 1000);
+            let cap = |timeout: Option<Duration>| Some(timeout.map_or(left, |t| cmp::min(t, left)));
+            timeouts.connect = cap(timeouts.connect);
+            timeouts.request = cap(timeouts.request);
+        }
+        Ok(timeouts)
+    }
+
+    /// The deadline failure, if a request started at `start` (in ns) would
+    /// pass its deadline after waiting `delay` longer
+    fn past_deadline(&self, start: u64, delay: Duration) -> Option<Failure> {
+        self.timeouts().deadline.and_then(|limit| {
+            let elapsed = Duration::nanoseconds((time::precise_time_ns() - start) as i64);
+            if elapsed + delay >= limit {
+                Some(Failure::Timeout(Timeout::Deadline(limit)))
+            } else {
+                None
+            }
+        })
+    }
+
+    /// Make a single attempt at a request against the given instance, after
+    /// `retry` earlier attempts, for a request started at `start` (in ns)
+    ///
+    /// The attempt waits for room under the concurrency limit, and its
+    /// timeouts are capped at the time left before the deadline. An instance
+    /// that can't be reached or answers with a 5xx is disabled until health
+    /// checks find it answering again
+    fn attempt(cluster: &Arc<Cluster>, instance: Instance, request: &Request,
+               retry: u32, start: u64) -> Attempt {
+        if let Err(expired) = cluster.request_started(&instance, Some(start)) {
+            return Attempt::Fail(expired);
+        }
+        let timeouts = match cluster.attempt_timeouts(start) {
+            Ok(timeouts) => timeouts,
+            Err(expired) => {
+                cluster.request_finished(&instance);
+                return Attempt::Fail(expired);
+            }
+        };
+        let gzip = cluster.transport.read().unwrap().is_none();
+        let request = cluster.outgoing(&instance, request, gzip);
+        let span = AttemptSpan::start(&instance, &request, retry);
+        let start = time::precise_time_ns();
+        let result = match *cluster.transport.read().unwrap() {
+            Some(ref transport) => transport.send(&instance, &request, &timeouts),
+            None => cluster.hyper.send(&instance, &request, &timeouts)
+        };
+        cluster.request_finished(&instance);
+        let (status, failure, after) = match result {
+            Ok(res) => {
+                cluster.received(res.status, res.headers.as_slice(), start);
+                match res.status {
+                    200...299 => {
+                        cluster.forget_failovers(&instance);
+                        cluster.attempt_done(span, &instance, &request, retry,
+                                             Some(res.status), None);
+                        return Attempt::Success(res.body);
+                    },
+                    status => {
+                        let after = retry_after(&res);
+                        (Some(status), Failure::Status(status, res.body), after)
+                    }
+                }
+            },
+            Err(Failure::Timeout(timeout)) => {
+                // timed out because the deadline capped the timeouts
+                let expired = cluster.past_deadline(start, Duration::zero());
+                (None, expired.unwrap_or(Failure::Timeout(timeout)), None)
+            },
+            Err(failure) => (None, failure, None)
+        };
+        cluster.attempt_done(span, &instance, &request, retry, status, Some(&failure));
+        if failure.disables_instance() {
+            Cluster::disable_instance(cluster, &instance);
+        }
+        Attempt::failed(failure, after)
+    }
+
+    /// Record how an attempt at `request` ended, in its span and the metrics
+    pub fn attempt_done(&self, span: AttemptSpan, instance: &Instance, request: &Request,
+                        retry: u32, status: Option<u16>, failure: Option<&Failure>) {
+        let bytes = request.body.as_ref().map_or(0, |body| body.len());
+        self.metrics.lock().unwrap().record_attempt(instance, retry, bytes, span.elapsed_ns(),
+                                                    failure);
+        span.finish(status, failure, failure.map_or(false, |f| f.is_retriable()));
+    }
+
+    /// Count points written successfully, for `metrics`
+    pub fn points_written(&self, points: u64) {
+        self.metrics.lock().unwrap().points_written += points;
+    }
+
+    /// A snapshot of the counters of the requests sent, and of the requests
+    /// waiting and in flight now
+    pub fn metrics(&self) -> Metrics {
+        let mut metrics = self.metrics.lock().unwrap().clone();
+        metrics.queued = match *self.workers.lock().unwrap() {
+            Some(ref workers) => workers.queued(),
+            None => 0
+        };
+        metrics.in_flight = self.in_flight.lock().unwrap().iter()
+                                .fold(0, |total, &(_, n)| total + n);
+        metrics
+    }
+
+    /// Send a request and return the response without reading its body, for
+    /// streaming large responses
+    ///
+    /// The request is not retried, and any non-2xx status is an error. It is
+    /// always sent with the default transport, as a `Transport` only returns
+    /// whole responses. It waits for room under the concurrency limit, and
+    /// holds its place until the response is dropped.
+    pub fn open(cluster: &Arc<Cluster>, request: Request) -> Result<Streamed, Failure> {
+        let instance = try!(cluster.get_instance(&request)
+                            .ok_or(Failure::Error(String::from_str("No instances available"))));
+        try!(cluster.request_started(&instance, Some(time::precise_time_ns())));
+        let request = cluster.outgoing(&instance, &request, false);
+        let span = AttemptSpan::start(&instance, &request, 0);
+        let start = time::precise_time_ns();
+        let mut res = match cluster.hyper.open(&instance, &request, &cluster.timeouts()) {
+            Ok(res) => res,
+            Err(failure) => {
+                cluster.request_finished(&instance);
+                cluster.attempt_done(span, &instance, &request, 0, None, Some(&failure));
+                return Err(failure);
+            }
+        };
+        cluster.received(res.status.to_u16(), header_list(&res.headers).as_slice(), start);
+        cluster.attempt_done(span, &instance, &request, 0, Some(res.status.to_u16()), None);
+        if res.status.class() == StatusClass::Success {
+            Ok(Streamed { response: res, cluster: cluster.clone(), instance: instance })
+        } else {
+            let body = res.read_to_string().unwrap_or(String::new());
+            cluster.request_finished(&instance);
+            Err(Failure::Status(res.status.to_u16(), body))
+        }
+    }
+
+    /// Send a request once, through the cluster's transport, and return the
+    /// whole response whatever its status
+    ///
+    /// For endpoints whose headers or error responses matter, such as
+    /// `/ping` and `/health`
+    pub fn send_once(&self, request: Request) -> Result<Response, Failure> {
+        let instance = try!(self.get_instance(&request)
+                            .ok_or(Failure::Error(String::from_str("No instances available"))));
+        let gzip = self.transport.read().unwrap().is_none();
+        let request = self.outgoing(&instance, &request, gzip);
+        let span = AttemptSpan::start(&instance, &request, 0);
+        let start = time::precise_time_ns();
+        let result = match *self.transport.read().unwrap() {
+            Some(ref transport) => transport.send(&instance, &request, &self.timeouts()),
+            None => self.hyper.send(&instance, &request, &self.timeouts())
+        };
+        match result {
+            Ok(res) => {
+                self.received(res.status, res.headers.as_slice(), start);
+                self.attempt_done(span, &instance, &request, 0, Some(res.status), None);
+                Ok(res)
+            },
+            Err(failure) => {
+                self.attempt_done(span, &instance, &request, 0, None, Some(&failure));
+                Err(failure)
+            }
+        }
+    }
+
+    /// Perform a request, retrying against the next available instance
+    /// according to the retry policy, and return the response body
+    fn execute(cluster: &Arc<Cluster>, request: Request) -> Result<String, Failure> {
+        let start = time::precise_time_ns();
+        let mut retries = 0u32;
+        let mut last = None;
+        loop {
+            let instance = match cluster.get_instance(&request) {
+                Some(instance) => instance,
+                None => return Err(last.unwrap_or(Failure::Error(String::from_str(
+                    "No instances available"))))
+            };
+            let (error, after) = match Cluster::attempt(cluster, instance, &request, retries,
+                                                        start) {
+                Attempt::Success(body) => return Ok(body),
+                Attempt::Fail(failure) => return Err(failure),
+                Attempt::Retry(failure, after) => (failure, after)
+            };
+            retries += 1;
+            match cluster.retry_delay(retries).map(|delay| later(delay, after)) {
+                Some(delay) => match cluster.past_deadline(start, delay) {
+                    Some(expired) => return Err(expired),
+                    None => io::timer::sleep(delay)
+                },
+                None => return Err(error)
+            }
+            last = Some(error);
+        }
+    }
+
+    /// Perform a request against one instance, retrying according to the
+    /// retry policy, and return the response body
+    ///
+    /// `start` is when the request as a whole started, for its deadline
+    fn execute_on(cluster: &Arc<Cluster>, instance: Instance, request: &Request,
+                  start: u64) -> Result<String, Failure> {
+        Cluster::execute_until(cluster, instance, request, start, || false)
+    }
+
+    /// Perform a request against one instance like `execute_on`, but give up
+    /// instead of retrying once `stopped`
+    fn execute_until<S>(cluster: &Arc<Cluster>, instance: Instance, request: &Request,
+                        start: u64, stopped: S) -> Result<String, Failure>
+        where S: Fn() -> bool
+    {
+        let mut retries = 0u32;
+        loop {
+            let (error, after) = match Cluster::attempt(cluster, instance.clone(), request,
+                                                        retries, start) {
+                Attempt::Success(body) => return Ok(body),
+                Attempt::Fail(failure) => return Err(failure),
+                Attempt::Retry(Failure::Error(error), after) => {
+                    (Failure::Error(format!("{}: {}", instance.host, error)), after)
+                },
+                Attempt::Retry(failure, after) => (failure, after)
+            };
+            if stopped() {
+                return Err(error);
+            }
+            retries += 1;
+            match cluster.retry_delay(retries).map(|delay| later(delay, after)) {
+                Some(delay) => match cluster.past_deadline(start, delay) {
+                    Some(expired) => return Err(expired),
+                    None => io::timer::sleep(delay)
+                },
+                None => return Err(error)
+            }
+            if stopped() {
+                return Err(error);
+            }
+        }
+    }
+
+    /// Sends a request to every available instance in turn, returning a
+    /// future of the result
+    ///
+    /// The response bodies are handed to `parse` together, in instance order.
+    /// If any instance fails, so does the request
+    pub fn request_all<T, E, F>(cluster: &Arc<Cluster>,
+                                request: Request,
+                                parse: F) -> RequestFuture<T, E>
+        where T: Send + 'static,
+              E: RequestError + Send + 'static,
+              F: FnOnce(Vec<String>) -> Result<T, E> + Send + 'static
+    {
+        let workers = cluster.workers();
+        let cluster = cluster.clone();
+        spawn(&*workers, move || {
+            let start = time::precise_time_ns();
+            let mut bodies = vec!();
+            let mut error = None;
+            for instance in cluster.get_instances_available().into_iter() {
+                match Cluster::execute_on(&cluster, instance, &request, start) {
+                    Ok(body) => bodies.push(body),
+                    Err(e) => {
+                        error = Some(e);
+                        break;
+                    }
+                }
+            }
+            match error {
+                Some(e) => Err(e.into_error()),
+                None if bodies.is_empty() => {
+                    Err(FromError::from_error(String::from_str("No instances available")))
+                },
+                None => parse(bodies)
+            }
+        })
+    }
+
+    /// Sends a request to `copies` available instances (or all of them) at
+    /// once, returning a future that succeeds once `acks` of them have
+    ///
+    /// The instances are consecutive ones from the balancer's pick. Each copy
+    /// is sent from a worker thread and retried on its own according to the
+    /// retry policy, and the request fails as soon as too many have failed to
+    /// reach `acks`
+    pub fn request_replicated<E>(cluster: &Arc<Cluster>, request: Request,
+                                 copies: Option<usize>, acks: usize) -> RequestFuture<(), E>
+        where E: RequestError + Send + 'static
+    {
+        let available = cluster.candidates(&request);
+        if available.is_empty() {
+            return Box::new(future::err(FromError::from_error(
+                String::from_str("No instances available"))));
+        }
+        let copies = cmp::min(copies.unwrap_or(available.len()), available.len());
+        if acks > copies {
+            return Box::new(future::err(FromError::from_error(format!(
+                "Can't get {} acknowledgements from {} instances", acks, copies))));
+        }
+        let first = cluster.balancer.read().unwrap().pick_for(available.as_slice(), &request);
+        let start = time::precise_time_ns();
+        let request = Arc::new(request);
+        let (complete, result) = oneshot::channel();
+        let replicas = Arc::new(Mutex::new(Replicas { acked: 0, failed: 0, complete: None }));
+        if acks == 0 {
+            let _ = complete.send(Ok(()));
+        } else {
+            replicas.lock().unwrap().complete = Some(complete);
+        }
+        let workers = cluster.workers();
+        for i in 0..copies {
+            let instance = available[(first + i) % available.len()].clone();
+            let (cluster, request) = (cluster.clone(), request.clone());
+            let replicas = replicas.clone();
+            workers.execute(move || {
+                let result = Cluster::execute_on(&cluster, instance, &*request, start);
+                let mut replicas = replicas.lock().unwrap();
+                let done = match result {
+                    Ok(_) => {
+                        replicas.acked += 1;
+                        if replicas.acked >= acks { Some(Ok(())) } else { None }
+                    },
+                    Err(e) => {
+                        replicas.failed += 1;
+                        if copies - replicas.failed < acks { Some(Err(e)) } else { None }
+                    }
+                };
+                if let (Some(result), Some(complete)) = (done, replicas.complete.take()) {
+                    let _ = complete.send(result);
+                }
+            });
+        }
+        Box::new(result.then(|result| match result {
+            Ok(result) => result.map_err(Failure::into_error),
+            Err(Canceled) => Err(FromError::from_error(String::from_str(
+                "Replicated request stopped")))
+        }))
+    }
+
+    /// Sends a request, returning a future of the result
+    ///
+    /// On a 2xx response the body is handed to `parse` to produce the result.
+    /// Connection errors and 5xx responses are retried according to the retry
+    /// policy, any other status fails the request (with the error converted
+    /// to `parse`'s error type)
+    ///
+    /// With a reactor set the request is performed on it, otherwise on one of
+    /// the worker threads
+    pub fn request<T, E, F>(cluster: &Arc<Cluster>,
+                            request: Request,
+                            parse: F) -> RequestFuture<T, E>
+        where T: Send + 'static,
+              E: RequestError + Send + 'static,
+              F: FnOnce(String) -> Result<T, E> + Send + 'static
+    {
+        let (request, parse) = match on_reactor(cluster, request, parse) {
+            Ok(future) => return future,
+            Err(unsent) => unsent
+        };
+        let hedge_after = *cluster.hedge_after.read().unwrap();
+        match hedge_after {
+            Some(after) if request.read_only() => {
+                return Cluster::request_hedged(cluster, request, after, parse);
+            },
+            _ => ()
+        }
+        let workers = cluster.workers();
+        let cluster = cluster.clone();
+        spawn(&*workers, move || {
+            Cluster::execute(&cluster, request).map_err(Failure::into_error).and_then(parse)
+        })
+    }
+
+    /// Send a request, sending it to a second instance too if the first
+    /// hasn't answered within `after`, and parse the first body to arrive
+    ///
+    /// Both attempts run on worker threads, the second started by the
+    /// cluster's timer. Once one has answered, the other isn't started if it
+    /// is still waiting for a thread, stops retrying if it is not, and its
+    /// result is dropped.
+    fn request_hedged<T, E, F>(cluster: &Arc<Cluster>, request: Request, after: Duration,
+                               parse: F) -> RequestFuture<T, E>
+        where T: Send + 'static,
+              E: RequestError + Send + 'static,
+              F: FnOnce(String) -> Result<T, E> + Send + 'static
+    {
+        let first = match cluster.get_instance(&request) {
+            Some(instance) => instance,
+            None => return Box::new(future::err(FromError::from_error(
+                String::from_str("No instances available"))))
+        };
+        let start = time::precise_time_ns();
+        let request = Arc::new(request);
+        let (complete, result) = oneshot::channel();
+        let hedge = Arc::new(Mutex::new(Hedge {
+            racing: 1,
+            hedged: false,
+            complete: Some(complete)
+        }));
+        Cluster::race(cluster, &hedge, first.clone(), &request, start);
+        let (shared, hedge) = (cluster.clone(), hedge.clone());
+        cluster.timer.schedule(after, move || {
+            Cluster::hedge(&shared, &hedge, &first, &request, start);
+        });
+        Box::new(result.then(|result| match result {
+            Ok(result) => result.map_err(Failure::into_error).and_then(parse),
+            Err(Canceled) => Err(FromError::from_error(String::from_str(
+                "Hedged request stopped")))
+        }))
+    }
+
+    /// Try a hedged request on another instance than `first`, unless it has
+    /// already been answered or failed
+    fn hedge(cluster: &Arc<Cluster>, hedge: &Arc<Mutex<Hedge>>, first: &Instance,
+             request: &Arc<Request>, start: u64) {
+        let second = {
+            let mut state = hedge.lock().unwrap();
+            if state.hedged || state.complete.is_none() {
+                return;
+            }
+            state.hedged = true;
+            let others: Vec<Instance> = cluster.candidates(&**request).into_iter()
+                .filter(|instance| !instance.same_address(first))
+                .collect();
+            if others.is_empty() {
+                return;
+            }
+            state.racing += 1;
+            let index = cluster.balancer.read().unwrap().pick_for(others.as_slice(), &**request);
+            others[index % others.len()].clone()
+        };
+        Cluster::race(cluster, hedge, second, request, start);
+    }
+
+    /// Run one attempt of a hedged request on a worker thread, already
+    /// counted in the hedge's `racing`
+    fn race(cluster: &Arc<Cluster>, hedge: &Arc<Mutex<Hedge>>, instance: Instance,
+            request: &Arc<Request>, start: u64) {
+        let (cluster, hedge, request) = (cluster.clone(), hedge.clone(), request.clone());
+        let workers = cluster.workers();
+        workers.execute(move || {
+            if hedge.lock().unwrap().complete.is_none() {
+                // answered while this waited for a thread
+                return;
+            }
+            // the loser stops retrying once the other attempt has answered
+            let answered = hedge.clone();
+            let result = Cluster::execute_until(&cluster, instance, &*request, start, move || {
+                answered.lock().unwrap().complete.is_none()
+            });
+            let mut state = hedge.lock().unwrap();
+            state.racing -= 1;
+            let result = match result {
+                Ok(body) => Ok(body),
+                // the last attempt standing fails the request
+                Err(e) if state.racing == 0 => {
+                    state.hedged = true;
+                    Err(e)
+                },
+                Err(_) => return
+            };
+            if let Some(complete) = state.complete.take() {
+                let _ = complete.send(result);
+            }
+        });
+    }
+}
+
+/// The requests in flight to `instance`
+fn count(in_flight: &[(Instance, usize)], instance: &Instance) -> usize {
+    in_flight.iter().find(|&&(ref i, _)| i == instance).map_or(0, |&(_, n)| n)
+}
+
+/// Creates a url for a request
+fn build_url(instance: &Instance, request: &Request) -> Url {
+    Url {
+        // bit before ://
+        scheme: format!("{}", instance.scheme),
+        // bit after :// before ?
+        scheme_data: SchemeData::Relative(RelativeSchemeData {
+            username: String::from_str(""),
+            password: None,
+            host: instance.host.clone(),
+            port: Some(instance.port),
+            default_port: Some(instance.port), // TODO what to do here?
+            path: request.path.clone()
+        }),
+        // Bit after ? before #
+        query: Some(::url::form_urlencoded::serialize_owned(request.query.as_slice())),
+        // Bit after #
+        fragment: None
+    }
+}
+
+/// The headers of a hyper response, as name/value pairs
+fn header_list(headers: &Headers) -> Vec<(String, String)> {
+    headers.iter().map(|h| (h.name().to_string(), h.value_string())).collect()
+}
+
+/// Describe an error sending a request, picking out timeouts and errors
+/// retrying won't fix
+fn failure(error: hyper::Error, timeouts: &Timeouts) -> Failure {
+    match error {
+        hyper::Error::Io(ref e) => {
+            let connect = e.get_ref().map_or(false, |inner| inner.is::<ConnectTimedOut>());
+            let timed_out = e.kind() == io::ErrorKind::TimedOut ||
+                e.kind() == io::ErrorKind::WouldBlock;
+            match (connect, timeouts.connect, timeouts.request) {
+                (true, Some(limit), _) => return Failure::Timeout(Timeout::Connect(limit)),
+                (false, _, Some(limit)) if timed_out => {
+                    return Failure::Timeout(Timeout::Request(limit))
+                },
+                _ => {}
+            }
+            if misconfigured(e) {
+                return Failure::Misconfigured(format!("{}", error));
+            }
+        },
+        hyper::Error::Ssl(_) | hyper::Error::Uri(_) => {
+            return Failure::Misconfigured(format!("{}", error))
+        },
+        _ => {}
+    }
+    Failure::Error(format!("{}", error))
+}
+
+/// Whether an IO error is a failure to resolve the host or to set up TLS
+///
+/// The TLS client reports bad server names as invalid input and failed
+/// handshakes as invalid data, while std only describes failed lookups.
+fn misconfigured(error: &io::Error) -> bool {
+    match error.kind() {
+        io::ErrorKind::InvalidInput | io::ErrorKind::InvalidData => true,
+        _ => format!("{}", error).contains("lookup address")
+    }
+}
+
+/// How long a 429 or 503 response asks to wait before retrying, from its
+/// `Retry-After` header - a number of seconds or an HTTP date
+pub fn retry_after(response: &Response) -> Option<Duration> {
+    if response.status != 429 && response.status != 503 {
+        return None;
+    }
+    let value = match response.header("Retry-After") {
+        Some(value) => value.trim(),
+        None => return None
+    };
+    if let Ok(seconds) = value.parse::<u32>() {
+        return Some(Duration::seconds(seconds as i64));
+    }
+    time::strptime(value, "%a, %d %b %Y %H:%M:%S GMT").ok().map(|date| {
+        cmp::max(date.to_timespec() - time::get_time(), Duration::zero())
+    })
+}
+
+/// The longer of the retry policy's `delay` and the one the server asked
+/// for
+pub fn later(delay: Duration, after: Option<Duration>) -> Duration {
+    after.map_or(delay, |after| cmp::max(delay, after))
+}
+
+/// Send a request with the given hyper client
+fn dispatch(client: &hyper::Client, url: Url,
+            request: &Request) -> hyper::HttpResult<hyper::client::Response> {
+    let mut headers = Headers::new();
+    for &(ref name, ref value) in request.headers.iter() {
+        headers.set_raw(name.clone(), vec!(value.clone().into_bytes()));
+    }
+    let builder = client.request(request.method.clone(), url).headers(headers);
+    match request.body {
+        Some(ref body) => builder.body(body.as_slice()).send(),
+        None => builder.send()
+    }
+}
+
+/// Perform a request on the cluster's reactor, if it has one and no
+/// transport is set, otherwise hand the request back
+#[cfg(feature = "tokio")]
+fn on_reactor<T, E, F>(cluster: &Arc<Cluster>, request: Request,
+                       parse: F) -> Result<RequestFuture<T, E>, (Request, F)>
+    where T: Send + 'static,
+          E: RequestError + Send + 'static,
+          F: FnOnce(String) -> Result<T, E> + Send + 'static
+{
+    if cluster.transport.read().unwrap().is_some() {
+        return Err((request, parse));
+    }
+    match *cluster.reactor.read().unwrap() {
+        Some(ref remote) => Ok(::reactor::request(cluster.clone(), remote, request, parse)),
+        None => Err((request, parse))
     }
 }
 
+#[cfg(not(feature = "tokio"))]
+fn on_reactor<T, E, F>(_: &Arc<Cluster>, request: Request,
+                       parse: F) -> Result<RequestFuture<T, E>, (Request, F)> {
+    Err((request, parse))
+}
+
+/// Run `work` on one of `workers`, returning a future of its result
+fn spawn<T, E, F>(workers: &WorkerPool, work: F) -> RequestFuture<T, E>
+    where T: Send + 'static,
+          E: RequestError + Send + 'static,
+          F: FnOnce() -> Result<T, E> + Send + 'static
+{
+    let (complete, result) = oneshot::channel();
+    workers.execute(move || {
+        let _ = complete.send(work());
+    });
+    Box::new(result.then(|result| match result {
+        Ok(result) => result,
+        // the thread panicked
+        Err(Canceled) => Err(FromError::from_error(String::from_str("Request thread died")))
+    }))
+}
+
+#[test]
+fn sends_with_custom_transport() {
+    let cluster = Arc::new(Cluster::new(vec!(Default::default())));
+    cluster.set_transport(Box::new(Canned::new(|_| reply_as("1.8.0", 200, "pong"))));
+    let ping = Request::new(Method::Get, vec!(String::from_str("ping")));
+    let body: Result<String, String> = Cluster::request(&cluster, ping, |body| Ok(body)).wait();
+    assert_eq!(body, Ok(String::from_str("pong")));
+    assert_eq!(cluster.server_version(), Some(String::from_str("1.8.0")));
+}
+
+#[test]
+fn decodes_gzipped_body() {
+    // `{"results":[]}`
+    let gzipped = vec!(0x1f, 0x8b, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02, 0x03, 0xab, 0x56,
+                       0x2a, 0x4a, 0x2d, 0x2e, 0xcd, 0x29, 0x29, 0x56, 0xb2, 0x8a, 0x8e, 0xad,
+                       0x05, 0x00, 0x0a, 0x27, 0x7c, 0x9e, 0x0e, 0x00, 0x00, 0x00);
+    assert_eq!(decode_body(Some("gzip"), gzipped), Ok(String::from_str("{\"results\":[]}")));
+    assert_eq!(decode_body(None, b"pong".to_vec()), Ok(String::from_str("pong")));
+    assert!(decode_body(Some("gzip"), b"pong".to_vec()).is_err());
+}
+
+#[test]
+fn pings_disabled_instances() {
+    let cluster = Cluster::new(vec!(Default::default()));
+    cluster.set_transport(Box::new(Canned::status(204)));
+    assert!(cluster.ping(&Default::default()));
+    cluster.set_transport(Box::new(Canned::status(503)));
+    assert!(!cluster.ping(&Default::default()));
+}
+
+#[test]
+fn reenables_failed_instances() {
+    use retry::NoRetry;
+    let cluster = Arc::new(Cluster::new(vec!(Default::default())));
+    cluster.set_retry_policy(Box::new(NoRetry));
+    cluster.set_failover_backoff(ExponentialBackoff {
+        base: Duration::milliseconds(10),
+        ..Default::default()
+    });
+    cluster.set_health_check(HealthCheck { interval: Duration::milliseconds(10), successes: 2 });
+    cluster.set_transport(Box::new(Canned::status(503)));
+    let query = Request::new(Method::Get, vec!(String::from_str("query")));
+    let failed: Result<String, String> = Cluster::request(&cluster, query, |body| Ok(body)).wait();
+    assert!(failed.is_err());
+    assert_eq!(cluster.get_instances_disabled(), vec!(Default::default()));
+    // still failing its health checks
+    io::timer::sleep(Duration::milliseconds(100));
+    assert_eq!(cluster.get_instances_available(), vec!());
+    cluster.set_transport(Box::new(Canned::status(204)));
+    io::timer::sleep(Duration::milliseconds(200));
+    assert_eq!(cluster.get_instances_available(), vec!(Default::default()));
+}
+
+#[test]
+fn stops_pinging_once_the_cluster_is_dropped() {
+    use retry::NoRetry;
+    let transport = Canned::new(|_| Err(Failure::Error(String::from_str("Connection refused"))));
+    let sent = transport.sent();
+    let cluster = Arc::new(Cluster::new(vec!(Default::default())));
+    cluster.set_retry_policy(Box::new(NoRetry));
+    cluster.set_failover_backoff(ExponentialBackoff {
+        base: Duration::milliseconds(10),
+        ..Default::default()
+    });
+    cluster.set_health_check(HealthCheck { interval: Duration::milliseconds(10), successes: 1 });
+    cluster.set_transport(Box::new(transport));
+    let query = Request::new(Method::Get, vec!(String::from_str("query")));
+    assert!(Cluster::execute(&cluster, query).is_err());
+    io::timer::sleep(Duration::milliseconds(100));
+    assert!(sent.lock().unwrap().len() > 1);
+    let weak = cluster.downgrade();
+    drop(cluster);
+    io::timer::sleep(Duration::milliseconds(50));
+    assert!(weak.upgrade().is_none());
+    let pinged = sent.lock().unwrap().len();
+    io::timer::sleep(Duration::milliseconds(50));
+    assert_eq!(sent.lock().unwrap().len(), pinged);
+}
+
+#[test]
+fn backs_off_repeated_failovers() {
+    let cluster = Cluster::new(vec!(Default::default()));
+    let instance: Instance = Default::default();
+    assert_eq!(cluster.count_failover(&instance), 1);
+    assert_eq!(cluster.count_failover(&instance), 2);
+    cluster.forget_failovers(&instance);
+    assert_eq!(cluster.count_failover(&instance), 1);
+}
+
+#[test]
+fn waits_out_failover_backoff() {
+    use retry::NoRetry;
+    let transport = Canned::new(|_| Err(Failure::Error(String::from_str("Connection refused"))));
+    let sent = transport.sent();
+    let instance: Instance = Default::default();
+    let cluster = Arc::new(Cluster::new(vec!(instance.clone())));
+    cluster.set_retry_policy(Box::new(NoRetry));
+    cluster.set_health_check(HealthCheck { interval: Duration::milliseconds(10), successes: 1 });
+    cluster.set_transport(Box::new(transport));
+    let query = Request::new(Method::Get, vec!(String::from_str("query")));
+    let failed: Result<String, String> = Cluster::request(&cluster, query, |body| Ok(body)).wait();
+    assert!(failed.is_err());
+    // the default backoff is a minute, so no health checks yet
+    io::timer::sleep(Duration::milliseconds(50));
+    let paths: Vec<String> = sent.lock().unwrap().iter()
+                                 .map(|sent| sent.request.path.connect("/")).collect();
+    assert_eq!(paths, vec!(String::from_str("query")));
+    // the instance failed once, so the next failover backs off further
+    assert_eq!(cluster.count_failover(&instance), 2);
+}
+
+#[test]
+fn waits_for_replica_acknowledgements() {
+    use retry::NoRetry;
+    let second = Instance { port: 8087, ..Default::default() };
+    let cluster = Arc::new(Cluster::new(vec!(Default::default(), second)));
+    cluster.set_retry_policy(Box::new(NoRetry));
+    cluster.set_transport(Box::new(Canned::status(204)));
+    let write = Request::new(Method::Post, vec!(String::from_str("write")));
+    let acked: Result<(), String> = Cluster::request_replicated(&cluster, write.clone(),
+                                                                None, 2).wait();
+    assert_eq!(acked, Ok(()));
+    let too_many: Result<(), String> = Cluster::request_replicated(&cluster, write.clone(),
+                                                                   Some(1), 2).wait();
+    assert!(too_many.is_err());
+    cluster.set_transport(Box::new(Canned::status(503)));
+    let failed: Result<(), String> = Cluster::request_replicated(&cluster, write,
+                                                                 None, 1).wait();
+    assert!(failed.is_err());
+}
+
+#[test]
+fn shuts_down_after_replicated_copies() {
+    use retry::NoRetry;
+    let answered = Arc::new(Mutex::new(0));
+    let counted = answered.clone();
+    let transport = Canned::new(move |_| {
+        io::timer::sleep(Duration::milliseconds(50));
+        *counted.lock().unwrap() += 1;
+        reply(204, "")
+    });
+    let second = Instance { port: 8087, ..Default::default() };
+    let cluster = Arc::new(Cluster::new(vec!(Default::default(), second)));
+    cluster.set_retry_policy(Box::new(NoRetry));
+    cluster.set_transport(Box::new(transport));
+    let write = Request::new(Method::Post, vec!(String::from_str("write")));
+    let _: RequestFuture<(), String> = Cluster::request_replicated(&cluster, write, None, 1);
+    assert!(cluster.shutdown(Duration::seconds(5)));
+    assert_eq!(*answered.lock().unwrap(), 2);
+}
+
+#[test]
+fn runs_shutdown_hooks_on_drop() {
+    let calls = Arc::new(Mutex::new(vec!()));
+    let cluster = Cluster::new(vec!(Default::default()));
+    let (first, second) = (calls.clone(), calls.clone());
+    let removed = cluster.on_shutdown(Box::new(move |_| {
+        first.lock().unwrap().push("removed");
+        true
+    }));
+    cluster.on_shutdown(Box::new(move |_| {
+        second.lock().unwrap().push("kept");
+        true
+    }));
+    cluster.remove_shutdown_hook(removed);
+    drop(cluster);
+    assert_eq!(*calls.lock().unwrap(), vec!("kept"));
+}
+
+#[test]
+fn routes_by_role() {
+    let primary = Instance { role: Role::Write, ..Default::default() };
+    let replica = Instance { port: 8087, role: Role::Read, ..Default::default() };
+    let cluster = Cluster::new(vec!(primary.clone(), replica.clone()));
+    let query = Request::new(Method::Get, vec!(String::from_str("query")));
+    let write = Request::new(Method::Post, vec!(String::from_str("write")));
+    assert_eq!(cluster.get_instance(&query), Some(replica));
+    assert_eq!(cluster.get_instance(&write), Some(primary.clone()));
+
+    // no read replica, so queries fall back to the primary
+    let cluster = Cluster::new(vec!(primary.clone()));
+    assert_eq!(cluster.get_instance(&query), Some(primary));
+}
+
+#[test]
+fn reconciles_discovered_instances() {
+    let kept = Instance { weight: 2, ..Default::default() };
+    let gone = Instance { port: 8087, ..Default::default() };
+    let new = Instance { port: 8088, ..Default::default() };
+    let cluster = Cluster::new(vec!(Default::default(), gone));
+    cluster.set_hosts(vec!(kept.clone(), new.clone()));
+    assert_eq!(cluster.get_instances_available(), vec!(kept, new));
+}
+
+#[test]
+fn notifies_topology_changes() {
+    let events = Arc::new(Mutex::new(vec!()));
+    let seen = events.clone();
+    let cluster = Cluster::new(vec!(Default::default()));
+    cluster.on_topology_change(Box::new(move |event: &TopologyEvent| {
+        seen.lock().unwrap().push(event.clone());
+    }));
+    let added = Instance { port: 8087, ..Default::default() };
+    cluster.add_host(added.clone());
+    cluster.set_hosts(vec!(added.clone()));
+    assert_eq!(*events.lock().unwrap(),
+               vec!(TopologyEvent::Added(added), TopologyEvent::Removed(Default::default())));
+}
+
+#[test]
+fn notifies_failed_instances() {
+    use retry::NoRetry;
+    let events = Arc::new(Mutex::new(vec!()));
+    let seen = events.clone();
+    let cluster = Arc::new(Cluster::new(vec!(Default::default())));
+    cluster.on_topology_change(Box::new(move |event: &TopologyEvent| {
+        seen.lock().unwrap().push(event.clone());
+    }));
+    cluster.set_retry_policy(Box::new(NoRetry));
+    cluster.set_failover_backoff(ExponentialBackoff {
+        base: Duration::milliseconds(10),
+        ..Default::default()
+    });
+    cluster.set_health_check(HealthCheck { interval: Duration::milliseconds(10), successes: 1 });
+    cluster.set_transport(Box::new(Canned::status(500)));
+    let write = Request::new(Method::Post, vec!(String::from_str("write")));
+    let failed: Result<String, String> = Cluster::request(&cluster, write, |body| Ok(body)).wait();
+    assert!(failed.is_err());
+    assert_eq!(*events.lock().unwrap(), vec!(TopologyEvent::Disabled(Default::default())));
+    cluster.set_transport(Box::new(Canned::status(204)));
+    io::timer::sleep(Duration::milliseconds(200));
+    assert_eq!(*events.lock().unwrap(),
+               vec!(TopologyEvent::Disabled(Default::default()),
+                    TopologyEvent::Enabled(Default::default())));
+}
+
+#[test]
+fn keeps_pinned_requests_on_their_instance() {
+    let second = Instance { port: 8087, ..Default::default() };
+    let cluster = Cluster::new(vec!(Default::default(), second.clone()));
+    let query = Request::new(Method::Get, vec!(String::from_str("query")))
+        .pin(Some(second.clone()));
+    for _ in 0..3 {
+        assert_eq!(cluster.get_instance(&query), Some(second.clone()));
+    }
+}
+
+#[test]
+fn spills_past_busy_instances() {
+    let first: Instance = Default::default();
+    let second = Instance { port: 8087, ..Default::default() };
+    let cluster = Cluster::new(vec!(first.clone(), second.clone()));
+    cluster.set_balancer(Box::new(::balancer::ConsistentHash::new(::balancer::HashKey::Database)));
+    cluster.set_concurrency_limit(Some(ConcurrencyLimit {
+        max_in_flight: 1,
+        overflow: Overflow::Spill
+    }));
+    let query = Request::new(Method::Get, vec!(String::from_str("query")));
+    let picked = cluster.get_instance(&query).unwrap();
+    cluster.request_started(&picked, None).unwrap();
+    let other = if picked == first { second } else { first };
+    assert_eq!(cluster.get_instance(&query), Some(other));
+    cluster.request_finished(&picked);
+    assert_eq!(cluster.get_instance(&query), Some(picked));
+}
+
+#[test]
+fn manages_hosts_by_address() {
+    let first: Instance = Default::default();
+    let second = Instance { port: 8087, ..Default::default() };
+    let cluster = Cluster::new(vec!(first.clone(), second.clone()));
+    cluster.move_instance(&first, false);
+    assert_eq!(cluster.get_instances_disabled(), vec!(first.clone()));
+    // found by address, whatever its weight
+    assert!(cluster.enable_instance(&Instance { weight: 5, ..first.clone() }));
+    assert_eq!(cluster.get_instances_available(), vec!(second.clone(), first.clone()));
+    assert!(cluster.remove_host(&second));
+    assert!(!cluster.remove_host(&second));
+    assert_eq!(cluster.get_instances_available(), vec!(first));
+}
+
+#[test]
+fn hedges_slow_reads() {
+    let second = Instance { port: 8087, ..Default::default() };
+    let cluster = Arc::new(Cluster::new(vec!(Default::default(), second)));
+    cluster.set_transport(Box::new(Canned::new(|sent| {
+        if sent.instance.port != 8086 {
+            return reply(200, "fast");
+        }
+        io::timer::sleep(Duration::seconds(2));
+        reply(200, "slow")
+    })));
+    cluster.set_hedge_after(Some(Duration::milliseconds(10)));
+    let query = Request::new(Method::Get, vec!(String::from_str("query")));
+    let body: Result<String, String> = Cluster::request(&cluster, query, |body| Ok(body)).wait();
+    assert_eq!(body, Ok(String::from_str("fast")));
+}
+
+#[test]
+fn stops_retrying_the_losing_hedge() {
+    // the first instance is throttled, after a while
+    let transport = Canned::new(|sent| {
+        if sent.instance.port != 8086 {
+            return reply(200, "");
+        }
+        io::timer::sleep(Duration::milliseconds(100));
+        reply(429, "")
+    });
+    let sent = transport.sent();
+    let second = Instance { port: 8087, ..Default::default() };
+    let cluster = Arc::new(Cluster::new(vec!(Default::default(), second)));
+    cluster.set_retry_policy(Box::new(ExponentialBackoff {
+        base: Duration::milliseconds(1),
+        jitter: 0.0,
+        ..Default::default()
+    }));
+    cluster.set_transport(Box::new(transport));
+    cluster.set_hedge_after(Some(Duration::milliseconds(10)));
+    let query = Request::new(Method::Get, vec!(String::from_str("query")));
+    let body: Result<String, String> = Cluster::request(&cluster, query, |body| Ok(body)).wait();
+    assert!(body.is_ok());
+    io::timer::sleep(Duration::milliseconds(300));
+    assert_eq!(sent.lock().unwrap().iter().filter(|sent| sent.instance.port == 8086).count(), 1);
+}
+
+#[test]
+fn caps_attempts_at_the_deadline() {
+    // times out once the request timeout it was given has passed
+    let transport = Canned::new(|sent| {
+        let limit = sent.timeouts.request.unwrap();
+        io::timer::sleep(limit);
+        Err(Failure::Timeout(Timeout::Request(limit)))
+    });
+    let sent = transport.sent();
+    let cluster = Arc::new(Cluster::new(vec!(Default::default())));
+    cluster.set_transport(Box::new(transport));
+    cluster.set_connect_timeout(Some(Duration::seconds(10)));
+    cluster.set_request_timeout(Some(Duration::seconds(30)));
+    cluster.set_deadline(Some(Duration::seconds(1)));
+    let write = Request::new(Method::Post, vec!(String::from_str("write")));
+    match Cluster::execute(&cluster, write) {
+        Err(Failure::Timeout(Timeout::Deadline(_))) => (),
+        other => panic!("expected the deadline to pass, got {:?}", other)
+    }
+    let sent = sent.lock().unwrap();
+    assert_eq!(sent.len(), 1);
+    assert_eq!((sent[0].timeouts.connect, sent[0].timeouts.request),
+               (Some(Duration::seconds(1)), Some(Duration::seconds(1))));
+}
+
+#[test]
+fn waits_for_a_slot_until_the_deadline() {
+    let instance: Instance = Default::default();
+    let cluster = Cluster::new(vec!(instance.clone()));
+    cluster.set_concurrency_limit(Some(ConcurrencyLimit {
+        max_in_flight: 1,
+        overflow: Overflow::Queue
+    }));
+    cluster.set_deadline(Some(Duration::milliseconds(100)));
+    cluster.request_started(&instance, None).unwrap();
+    match cluster.request_started(&instance, Some(time::precise_time_ns())) {
+        Err(Failure::Timeout(Timeout::Deadline(_))) => (),
+        other => panic!("expected the deadline to pass, got {:?}", other)
+    }
+    cluster.request_finished(&instance);
+    assert!(cluster.request_started(&instance, Some(time::precise_time_ns())).is_ok());
+}
+
+#[test]
+fn retries_failed_writes_with_backoff() {
+    /// Fails the first `failures` requests
+    fn flaky(failures: usize) -> Canned {
+        Canned::new(move |sent| reply(if sent.number <= failures { 503 } else { 204 }, ""))
+    }
+    let instances = vec!(Instance { port: 8086, ..Default::default() },
+                         Instance { port: 8087, ..Default::default() },
+                         Instance { port: 8088, ..Default::default() });
+    let backoff = ExponentialBackoff {
+        base: Duration::milliseconds(20),
+        jitter: 0.0,
+        max_retries: 2,
+        ..Default::default()
+    };
+    let write = Request::new(Method::Post, vec!(String::from_str("write")));
+
+    let transport = flaky(2);
+    let sent = transport.sent();
+    let cluster = Arc::new(Cluster::new(instances.clone()));
+    cluster.set_retry_policy(Box::new(backoff));
+    cluster.set_transport(Box::new(transport));
+    assert!(Cluster::execute(&cluster, write.clone()).is_ok());
+    let sent = sent.lock().unwrap();
+    // each failed instance is disabled, so every retry goes to another
+    let mut ports: Vec<u16> = sent.iter().map(|sent| sent.instance.port).collect();
+    ports.sort();
+    assert_eq!(ports, vec!(8086, 8087, 8088));
+    assert!(sent[1].at - sent[0].at >= 20_000_000);
+    assert!(sent[2].at - sent[1].at >= 40_000_000);
+
+    let transport = flaky(10);
+    let sent = transport.sent();
+    let cluster = Arc::new(Cluster::new(instances));
+    cluster.set_retry_policy(Box::new(backoff));
+    cluster.set_transport(Box::new(transport));
+    match Cluster::execute(&cluster, write) {
+        Err(Failure::Status(503, _)) => (),
+        other => panic!("expected the last 503, got {:?}", other)
+    }
+    assert_eq!(sent.lock().unwrap().len(), 3);
+}
+
+#[test]
+fn retries_a_lone_instance_it_disabled() {
+    /// Fails the first `failures` requests
+    fn flaky(failures: usize) -> Canned {
+        Canned::new(move |sent| reply(if sent.number <= failures { 503 } else { 204 }, ""))
+    }
+    let backoff = ExponentialBackoff {
+        base: Duration::milliseconds(1),
+        jitter: 0.0,
+        ..Default::default()
+    };
+    let write = Request::new(Method::Post, vec!(String::from_str("write")));
+
+    let transport = flaky(2);
+    let sent = transport.sent();
+    let cluster = Arc::new(Cluster::new(vec!(Default::default())));
+    cluster.set_retry_policy(Box::new(backoff));
+    cluster.set_transport(Box::new(transport));
+    assert!(Cluster::execute(&cluster, write.clone()).is_ok());
+    assert_eq!(sent.lock().unwrap().len(), 3);
+    // still disabled until its health checks pass
+    assert_eq!(cluster.get_instances_disabled(), vec!(Default::default()));
+
+    let transport = flaky(10);
+    let sent = transport.sent();
+    let cluster = Arc::new(Cluster::new(vec!(Default::default())));
+    cluster.set_retry_policy(Box::new(backoff));
+    cluster.set_transport(Box::new(transport));
+    match Cluster::execute(&cluster, write) {
+        Err(Failure::Status(503, _)) => (),
+        other => panic!("expected the last 503, got {:?}", other)
+    }
+    assert_eq!(sent.lock().unwrap().len(), 4);
+}
+
+#[test]
+fn does_not_retry_what_retrying_wont_fix() {
+    let timeouts: Timeouts = Default::default();
+    let handshake = io::Error::new(io::ErrorKind::InvalidData, "invalid certificate");
+    match failure(hyper::Error::Io(handshake), &timeouts) {
+        Failure::Misconfigured(_) => (),
+        other => panic!("expected a TLS failure not to be retried, got {:?}", other)
+    }
+    let reset = io::Error::new(io::ErrorKind::ConnectionReset, "connection reset");
+    assert!(failure(hyper::Error::Io(reset), &timeouts).is_retriable());
+    assert!(!Failure::Timeout(Timeout::Deadline(Duration::seconds(1))).is_retriable());
+    assert!(Failure::Timeout(Timeout::Request(Duration::seconds(1))).is_retriable());
+
+    let transport = Canned::new(|_| {
+        Err(Failure::Misconfigured(String::from_str("failed to lookup address")))
+    });
+    let sent = transport.sent();
+    let cluster = Arc::new(Cluster::new(vec!(Default::default())));
+    cluster.set_transport(Box::new(transport));
+    let query = Request::new(Method::Get, vec!(String::from_str("query")));
+    assert!(Cluster::execute(&cluster, query).is_err());
+    assert_eq!(sent.lock().unwrap().len(), 1);
+    assert_eq!(cluster.get_instances_disabled().len(), 0);
+}
+
+#[test]
+fn keeps_timeouts_distinct() {
+    use error::InfluxError;
+    let timeout = Timeout::Connect(Duration::seconds(2));
+    let error: InfluxError = Failure::Timeout(timeout).into_error();
+    assert_eq!(error, InfluxError::Timeout(timeout));
+    let error: String = Failure::Timeout(timeout).into_error();
+    assert_eq!(error, "Connecting timed out after 2000ms");
+}
+
+#[test]
+fn waits_as_long_as_retry_after_asks() {
+    let transport = Canned::new(|sent| {
+        if sent.number > 1 {
+            return reply(200, "ok");
+        }
+        let headers = vec!((String::from_str("Retry-After"), String::from_str("1")));
+        Ok(Response { status: 429, headers: headers, body: String::new() })
+    });
+    let sent = transport.sent();
+    let cluster = Arc::new(Cluster::new(vec!(Default::default())));
+    cluster.set_retry_policy(Box::new(ExponentialBackoff {
+        base: Duration::milliseconds(1),
+        jitter: 0.0,
+        ..Default::default()
+    }));
+    cluster.set_transport(Box::new(transport));
+    let write = Request::new(Method::Post, vec!(String::from_str("write")));
+    assert_eq!(Cluster::execute(&cluster, write).ok(), Some(String::from_str("ok")));
+    let sent = sent.lock().unwrap();
+    assert_eq!(sent.len(), 2);
+    assert!(sent[1].at - sent[0].at >= 1_000_000_000);
+
+    let response = Response {
+        status: 503,
+        headers: vec!((String::from_str("retry-after"),
+                       String::from_str("Wed, 21 Oct 2015 07:28:00 GMT"))),
+        body: String::new()
+    };
+    assert_eq!(retry_after(&response), Some(Duration::zero()));
+    let response = Response { status: 500, ..response };
+    assert_eq!(retry_after(&response), None);
+}
+
+#[test]
+fn authenticates_https_tunnels_with_the_proxy() {
+    use std::io::{Acceptor, BufferedReader, Listener, TcpListener};
+    use std::sync::mpsc;
+    let mut listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.socket_name().unwrap().port;
+    let mut acceptor = listener.listen().unwrap();
+    let (sender, heads) = mpsc::channel();
+    Thread::spawn(move || {
+        let mut stream = acceptor.accept().unwrap();
+        let mut reader = BufferedReader::new(stream.clone());
+        let mut head = vec!();
+        loop {
+            let line = reader.read_line().unwrap();
+            if line.trim().is_empty() {
+                break;
+            }
+            head.push(String::from_str(line.trim()));
+        }
+        stream.write_str("HTTP/1.1 407 Proxy Authentication Required\r\n\r\n").unwrap();
+        let _ = sender.send(head);
+    });
+    let transport = HyperTransport::new();
+    let mut config: ProxyConfig = Default::default();
+    config.https = Some(Proxy::new("127.0.0.1", port).credentials("user", "secret"));
+    transport.set_proxy(config);
+    let instance = Instance {
+        scheme: Scheme::Https,
+        host: Host::Domain(String::from_str("influx.corp")),
+        ..Default::default()
+    };
+    let ping = Request::new(Method::Get, vec!(String::from_str("ping")));
+    match transport.send(&instance, &ping, &Default::default()) {
+        Err(Failure::Misconfigured(ref error)) if error.as_slice().contains("407") => (),
+        other => panic!("expected the proxy to refuse the tunnel, got {:?}", other)
+    }
+    assert_eq!(heads.recv().unwrap(),
+               vec!(String::from_str("CONNECT influx.corp:8086 HTTP/1.1"),
+                    String::from_str("Host: influx.corp:8086"),
+                    String::from_str("Proxy-Authorization: Basic dXNlcjpzZWNyZXQ=")));
+}
+
+#[test]
+fn reuses_kept_alive_connections() {
+    use std::io::{Acceptor, BufferedReader, Listener, TcpListener};
+    use std::sync::mpsc;
+    /// Sends (connection, request on it) for each request it answers,
+    /// serving one connection at a time until the client closes it
+    fn serve() -> (u16, mpsc::Receiver<(usize, usize)>) {
+        let mut listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.socket_name().unwrap().port;
+        let mut acceptor = listener.listen().unwrap();
+        let (sender, served) = mpsc::channel();
+        Thread::spawn(move || {
+            for connection in 0.. {
+                let mut stream = acceptor.accept().unwrap();
+                let mut reader = BufferedReader::new(stream.clone());
+                for request in 1.. {
+                    let mut closed = false;
+                    loop {
+                        match reader.read_line() {
+                            Ok(ref line) if line.trim().is_empty() => break,
+                            Ok(_) => (),
+                            Err(_) => { closed = true; break }
+                        }
+                    }
+                    if closed ||
+                       stream.write_str("HTTP/1.1 204 No Content\r\n\r\n").is_err() ||
+                       sender.send((connection, request)).is_err() {
+                        break;
+                    }
+                }
+            }
+        });
+        (port, served)
+    }
+    let timeouts = Timeouts { request: Some(Duration::seconds(1)), ..Default::default() };
+    let ping = Request::new(Method::Get, vec!(String::from_str("ping")));
+
+    let (port, served) = serve();
+    let instance = Instance { host: Host::Domain(String::from_str("127.0.0.1")), port: port,
+                              ..Default::default() };
+    let transport = HyperTransport::new();
+    for _ in 0..2 {
+        assert_eq!(transport.send(&instance, &ping, &timeouts).ok().map(|r| r.status), Some(204));
+    }
+    assert_eq!((served.recv().unwrap(), served.recv().unwrap()), ((0, 1), (0, 2)));
+
+    let (port, served) = serve();
+    let instance = Instance { port: port, ..instance };
+    let transport = HyperTransport::new();
+    transport.set_pool_config(PoolConfig { max_idle: 0, ..Default::default() });
+    for _ in 0..2 {
+        assert_eq!(transport.send(&instance, &ping, &timeouts).ok().map(|r| r.status), Some(204));
+    }
+    assert_eq!((served.recv().unwrap(), served.recv().unwrap()), ((0, 1), (1, 1)));
+}
+
+#[test]
+fn runs_middleware_around_every_attempt() {
+    /// Numbers each attempt it sees, after any numbers already added
+    struct Numbered(&'static str, Arc<Mutex<usize>>);
+    impl Middleware for Numbered {
+        fn before(&self, request: &mut Request) {
+            let mut seen = self.1.lock().unwrap();
+            *seen += 1;
+            request.headers.push((String::from_str(self.0), seen.to_string()));
+        }
+    }
+    /// Keeps the status and version of each response
+    struct Recorder(Arc<Mutex<Vec<(u16, Option<String>)>>>);
+    impl Middleware for Recorder {
+        fn after(&self, response: &middleware::Response) {
+            let version = response.header("x-influxdb-version").map(String::from_str);
+            self.0.lock().unwrap().push((response.status, version));
+        }
+    }
+    let transport = Canned::new(|sent| {
+        reply_as("1.8.0", if sent.number == 1 { 429 } else { 204 }, "")
+    });
+    let (sent, responses) = (transport.sent(), Arc::new(Mutex::new(vec!())));
+    let cluster = Arc::new(Cluster::new(vec!(Default::default())));
+    cluster.set_retry_policy(Box::new(ExponentialBackoff {
+        base: Duration::milliseconds(1),
+        jitter: 0.0,
+        ..Default::default()
+    }));
+    cluster.add_middleware(Box::new(Numbered("X-First", Arc::new(Mutex::new(0)))));
+    cluster.add_middleware(Box::new(Numbered("X-Second", Arc::new(Mutex::new(10)))));
+    cluster.add_middleware(Box::new(Recorder(responses.clone())));
+    cluster.set_transport(Box::new(transport));
+    let write = Request::new(Method::Post, vec!(String::from_str("write")));
+    assert!(Cluster::execute(&cluster, write).is_ok());
+    let numbered = |sent: &Sent| -> Vec<(String, String)> {
+        sent.request.headers.iter().filter(|&&(ref name, _)| name.as_slice().starts_with("X-"))
+                            .map(|header| header.clone()).collect()
+    };
+    let header = |name: &str, value: &str| (String::from_str(name), String::from_str(value));
+    let sent = sent.lock().unwrap();
+    assert_eq!(sent.iter().map(numbered).collect::<Vec<_>>(),
+               vec!(vec!(header("X-First", "1"), header("X-Second", "11")),
+                    vec!(header("X-First", "2"), header("X-Second", "12"))));
+    let version = Some(String::from_str("1.8.0"));
+    assert_eq!(*responses.lock().unwrap(), vec!((429, version.clone()), (204, version)));
+}
+
+#[test]
+fn signs_after_the_middleware() {
+    /// Adds a header to every request
+    struct Tenant;
+    impl Middleware for Tenant {
+        fn before(&self, request: &mut Request) {
+            request.headers.push((String::from_str("X-Tenant"), String::from_str("ops")));
+        }
+    }
+    /// Signs the names of the headers it was given
+    struct Names;
+    impl RequestSigner for Names {
+        fn sign(&self, _: &Method, _: &Url, headers: &mut Vec<(String, String)>, _: &str) {
+            let names: Vec<String> = headers.iter().map(|&(ref name, _)| name.clone()).collect();
+            headers.push((String::from_str("X-Signed"), names.connect(";")));
+        }
+    }
+    let cluster = Cluster::new(vec!(Default::default()));
+    cluster.add_middleware(Box::new(Tenant));
+    cluster.set_signer(Box::new(Names));
+    let instance: Instance = Default::default();
+    let ping = Request::new(Method::Get, vec!(String::from_str("ping")));
+    let sent = cluster.outgoing(&instance, &ping, true);
+    let signed: Vec<String> = sent.headers.iter().map(|&(ref name, _)| name.clone())
+                                          .filter(|name| name.as_slice() != "X-Signed").collect();
+    assert_eq!(signed, vec!(String::from_str("X-Tenant"), String::from_str("Accept-Encoding")));
+    assert_eq!(sent.headers.last().map(|&(_, ref value)| value.clone()),
+               Some(signed.connect(";")));
+}
+
+#[test]
+fn holds_a_slot_while_streaming() {
+    use std::io::{Acceptor, BufferedReader, Listener, TcpListener};
+    let mut listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.socket_name().unwrap().port;
+    let mut acceptor = listener.listen().unwrap();
+    Thread::spawn(move || {
+        let mut stream = acceptor.accept().unwrap();
+        let mut reader = BufferedReader::new(stream.clone());
+        while !reader.read_line().unwrap().trim().is_empty() {}
+        stream.write_str("HTTP/1.1 200 OK\r\nContent-Length: 4\r\n\r\nrows").unwrap();
+    });
+    let cluster = Arc::new(Cluster::new(vec!(Instance { port: port, ..Default::default() })));
+    let query = Request::new(Method::Get, vec!(String::from_str("query")));
+    let streamed = Cluster::open(&cluster, query.clone()).unwrap();
+    assert_eq!(cluster.metrics().in_flight, 1);
+    drop(streamed);
+    assert_eq!(cluster.metrics().in_flight, 0);
+
+    // nothing listens on port 1
+    let cluster = Arc::new(Cluster::new(vec!(Instance { port: 1, ..Default::default() })));
+    assert!(Cluster::open(&cluster, query).is_err());
+    assert_eq!(cluster.metrics().in_flight, 0);
+}
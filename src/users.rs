@@ -0,0 +1,110 @@
+//! Users and their privileges, as listed by `SHOW USERS` and `SHOW GRANTS`
+
+use std::fmt;
+use query::{QueryResult, Value};
+
+/// A privilege a user can hold on a database
+#[derive(Show, Clone, Copy, PartialEq)]
+pub enum Privilege {
+    Read,
+    Write,
+    All
+}
+
+/// Formats as in `GRANT` and `REVOKE` statements
+impl fmt::String for Privilege {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", match *self {
+            Privilege::Read => "READ",
+            Privilege::Write => "WRITE",
+            Privilege::All => "ALL"
+        })
+    }
+}
+
+impl Privilege {
+    /// Read a privilege as shown by `SHOW GRANTS` - None for
+    /// `NO PRIVILEGES`
+    fn parse(privilege: &str) -> Result<Option<Privilege>, String> {
+        match privilege {
+            "READ" => Ok(Some(Privilege::Read)),
+            "WRITE" => Ok(Some(Privilege::Write)),
+            "ALL PRIVILEGES" => Ok(Some(Privilege::All)),
+            "NO PRIVILEGES" => Ok(None),
+            other => Err(format!("Invalid privilege: {}", other))
+        }
+    }
+}
+
+/// A user of the server
+#[derive(Show, Clone, PartialEq)]
+pub struct User {
+    pub name: String,
+    /// Whether the user has cluster-wide admin privileges
+    pub is_admin: bool,
+    /// The privilege the user holds on each database it has one on
+    pub privileges: Vec<(String, Privilege)>
+}
+
+impl User {
+    /// Read the result of `SHOW USERS`, without privileges
+    pub fn from_result(result: &QueryResult) -> Result<Vec<User>, String> {
+        let mut users = vec!();
+        for series in result.series.iter() {
+            let name = try!(series.column_index("user")
+                            .ok_or(String::from_str("Result has no user column")));
+            let admin = try!(series.column_index("admin")
+                             .ok_or(String::from_str("Result has no admin column")));
+            for row in series.values.iter() {
+                match (&row[name], &row[admin]) {
+                    (&Value::Text(ref name), &Value::Boolean(admin)) => users.push(User {
+                        name: name.clone(),
+                        is_admin: admin,
+                        privileges: vec!()
+                    }),
+                    _ => return Err(String::from_str("Invalid user row"))
+                }
+            }
+        }
+        Ok(users)
+    }
+
+    /// Read the result of `SHOW GRANTS FOR` the user into its privileges
+    pub fn read_grants(&mut self, result: &QueryResult) -> Result<(), String> {
+        self.privileges.clear();
+        for series in result.series.iter() {
+            let database = try!(series.column_index("database")
+                                .ok_or(String::from_str("Result has no database column")));
+            let privilege = try!(series.column_index("privilege")
+                                 .ok_or(String::from_str("Result has no privilege column")));
+            for row in series.values.iter() {
+                match (&row[database], &row[privilege]) {
+                    (&Value::Text(ref database), &Value::Text(ref privilege)) => {
+                        if let Some(privilege) = try!(Privilege::parse(privilege.as_slice())) {
+                            self.privileges.push((database.clone(), privilege));
+                        }
+                    },
+                    _ => return Err(String::from_str("Invalid grant row"))
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn reads_users_and_grants() {
+    use query;
+    let users = query::parse(String::from_str(r#"{"results":[{"series":[{
+        "columns":["user","admin"],"values":[["root",true],["grafana",false]]}]}]}"#), None);
+    let mut users = User::from_result(&users.unwrap()).unwrap();
+    assert_eq!(users.iter().map(|user| (user.name.as_slice(), user.is_admin)).collect::<Vec<_>>(),
+               vec!(("root", true), ("grafana", false)));
+    let grants = query::parse(String::from_str(r#"{"results":[{"series":[{
+        "columns":["database","privilege"],
+        "values":[["metrics","READ"],["logs","ALL PRIVILEGES"],["old","NO PRIVILEGES"]]}]}]}"#),
+        None);
+    users[1].read_grants(&grants.unwrap()).unwrap();
+    assert_eq!(users[1].privileges, vec!((String::from_str("metrics"), Privilege::Read),
+                                         (String::from_str("logs"), Privilege::All)));
+}
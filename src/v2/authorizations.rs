@@ -0,0 +1,218 @@
+//! Authorizations, the 2.x API tokens and what they may read and write
+
+use std::collections::BTreeMap;
+use hyper::method::Method;
+use rustc_serialize::json::Json;
+use client::{Influx, InfluxFuture};
+use super::{parse, string, array};
+
+/// What a permission allows
+#[derive(Show, Clone, Copy, PartialEq)]
+pub enum Action {
+    Read,
+    Write
+}
+
+impl Action {
+    fn as_str(&self) -> &'static str {
+        match *self {
+            Action::Read => "read",
+            Action::Write => "write"
+        }
+    }
+}
+
+/// Permission to read or write a resource, or every resource of a type
+#[derive(Show, Clone, PartialEq)]
+pub struct Permission {
+    pub action: Action,
+    /// The type of resource, e.g. `buckets`, `tasks` or `orgs`
+    pub resource: String,
+    /// The id of the resource - None for every resource of the type
+    pub id: Option<String>,
+    /// The id of the organization owning the resource
+    pub org_id: Option<String>
+}
+
+impl Permission {
+    /// Permission to read or write one bucket
+    pub fn bucket(action: Action, org_id: &str, bucket_id: &str) -> Permission {
+        Permission {
+            action: action,
+            resource: String::from_str("buckets"),
+            id: Some(String::from_str(bucket_id)),
+            org_id: Some(String::from_str(org_id))
+        }
+    }
+
+    /// Permission to read or write every bucket of an organization
+    pub fn all_buckets(action: Action, org_id: &str) -> Permission {
+        Permission {
+            action: action,
+            resource: String::from_str("buckets"),
+            id: None,
+            org_id: Some(String::from_str(org_id))
+        }
+    }
+
+    fn to_json(&self) -> Json {
+        let mut resource = BTreeMap::new();
+        resource.insert(String::from_str("type"), Json::String(self.resource.clone()));
+        if let Some(ref id) = self.id {
+            resource.insert(String::from_str("id"), Json::String(id.clone()));
+        }
+        if let Some(ref org_id) = self.org_id {
+            resource.insert(String::from_str("orgID"), Json::String(org_id.clone()));
+        }
+        let mut permission = BTreeMap::new();
+        permission.insert(String::from_str("action"),
+                          Json::String(String::from_str(self.action.as_str())));
+        permission.insert(String::from_str("resource"), Json::Object(resource));
+        Json::Object(permission)
+    }
+
+    fn from_json(json: &Json) -> Result<Permission, String> {
+        let resource = try!(json.find("resource")
+                            .ok_or(String::from_str("Permission has no resource")));
+        Ok(Permission {
+            action: match try!(string(json, "action")).as_slice() {
+                "read" => Action::Read,
+                "write" => Action::Write,
+                other => return Err(format!("Invalid permission action: {}", other))
+            },
+            resource: try!(string(resource, "type")),
+            id: string(resource, "id").ok(),
+            org_id: string(resource, "orgID").ok()
+        })
+    }
+}
+
+/// A token and its permissions
+#[derive(Show, Clone, PartialEq)]
+pub struct Authorization {
+    /// Assigned by the server
+    pub id: String,
+    /// The token itself
+    pub token: String,
+    /// The id of the organization the token belongs to
+    pub org_id: String,
+    /// Whether the token can be used - `active` or `inactive`
+    pub status: String,
+    pub description: Option<String>,
+    pub permissions: Vec<Permission>
+}
+
+impl Authorization {
+    fn from_json(json: &Json) -> Result<Authorization, String> {
+        let mut permissions = vec!();
+        for permission in array(json, "permissions").iter() {
+            permissions.push(try!(Permission::from_json(permission)));
+        }
+        Ok(Authorization {
+            id: try!(string(json, "id")),
+            token: try!(string(json, "token")),
+            org_id: try!(string(json, "orgID")),
+            status: try!(string(json, "status")),
+            description: string(json, "description").ok(),
+            permissions: permissions
+        })
+    }
+}
+
+/// The authorizations of a 2.x server
+#[derive(Clone)]
+pub struct Authorizations<'a> {
+    influx: &'a Influx
+}
+
+impl<'a> Authorizations<'a> {
+    pub fn new(influx: &'a Influx) -> Authorizations<'a> {
+        Authorizations { influx: influx }
+    }
+
+    /// List the authorizations, of every organization the token can read or
+    /// only of the organization named `org`
+    pub fn list(&self, org: Option<&str>) -> InfluxFuture<Vec<Authorization>> {
+        let mut query = vec!();
+        if let Some(org) = org {
+            query.push((String::from_str("org"), String::from_str(org)));
+        }
+        self.influx.request_v2(Method::Get, &["authorizations"], query, None, |body| {
+            let json = try!(parse(body.as_slice()));
+            array(&json, "authorizations").iter().map(Authorization::from_json).collect()
+        })
+    }
+
+    /// Create a token in the organization with id `org_id`, allowed only
+    /// what `permissions` allow
+    pub fn create(&self, org_id: &str, description: Option<&str>,
+                  permissions: Vec<Permission>) -> InfluxFuture<Authorization> {
+        let mut authorization = BTreeMap::new();
+        authorization.insert(String::from_str("orgID"), Json::String(String::from_str(org_id)));
+        if let Some(description) = description {
+            authorization.insert(String::from_str("description"),
+                                 Json::String(String::from_str(description)));
+        }
+        authorization.insert(String::from_str("permissions"),
+                             Json::Array(permissions.iter().map(|p| p.to_json()).collect()));
+        self.influx.request_v2(Method::Post, &["authorizations"], vec!(),
+                               Some(Json::Object(authorization)),
+                               |body| Authorization::from_json(&try!(parse(body.as_slice()))))
+    }
+
+    /// Revoke a token, deleting its authorization
+    pub fn revoke(&self, id: &str) -> InfluxFuture<()> {
+        self.influx.request_v2(Method::Delete, &["authorizations", id], vec!(), None, |_| Ok(()))
+    }
+}
+
+#[test]
+fn sends_authorization_requests() {
+    use futures::Future;
+    use client::Auth;
+    use transport::{Canned, Request, reply, stub_client};
+    // answers with one authorization
+    let transport = Canned::new(|sent| {
+        let authorization = concat!(r#"{"id":"0f1","token":"secret","orgID":"0a1","#,
+                                    r#""status":"active","permissions":[{"action":"write","#,
+                                    r#""resource":{"type":"buckets","id":"0b1","#,
+                                    r#""orgID":"0a1"}}]}"#);
+        match sent.request.method {
+            Method::Get => reply(200, format!(r#"{{"authorizations":[{}]}}"#,
+                                              authorization).as_slice()),
+            Method::Post => reply(200, authorization),
+            _ => reply(200, "")
+        }
+    });
+    let sent = transport.sent();
+    let influx = stub_client(Auth::Token(String::from_str("operator")), transport);
+    let authorizations = Authorizations::new(&influx);
+    let write = Permission::bucket(Action::Write, "0a1", "0b1");
+    let authorization = Authorization {
+        id: String::from_str("0f1"),
+        token: String::from_str("secret"),
+        org_id: String::from_str("0a1"),
+        status: String::from_str("active"),
+        description: None,
+        permissions: vec!(write.clone())
+    };
+    assert_eq!(authorizations.list(Some("acme")).wait(), Ok(vec!(authorization.clone())));
+    assert_eq!(authorizations.create("0a1", Some("ci"), vec!(write)).wait(), Ok(authorization));
+    assert_eq!(authorizations.revoke("0f1").wait(), Ok(()));
+
+    let sent: Vec<Request> = sent.lock().unwrap().iter().map(|sent| sent.request.clone())
+                                                        .collect();
+    let shape: Vec<(Method, String, Vec<(String, String)>)> = sent.iter().map(|request| {
+        (request.method.clone(), request.path.connect("/"), request.query.clone())
+    }).collect();
+    assert_eq!(shape, vec!(
+        (Method::Get, String::from_str("api/v2/authorizations"),
+         vec!((String::from_str("org"), String::from_str("acme")))),
+        (Method::Post, String::from_str("api/v2/authorizations"), vec!()),
+        (Method::Delete, String::from_str("api/v2/authorizations/0f1"), vec!())));
+    let body = sent[1].body.as_ref().map(|body| parse(body.as_slice()).unwrap());
+    assert_eq!(body, Some(parse(concat!(r#"{"orgID":"0a1","description":"ci","permissions":"#,
+                                        r#"[{"action":"write","resource":{"type":"buckets","#,
+                                        r#""id":"0b1","orgID":"0a1"}}]}"#)).unwrap()));
+    assert!(sent[0].body.is_none() && sent[2].body.is_none());
+}
@@ -0,0 +1,225 @@
+//! Buckets, the 2.x equivalent of a database and its retention policy
+
+use std::collections::BTreeMap;
+use std::time::duration::Duration;
+use hyper::method::Method;
+use rustc_serialize::json::Json;
+use time;
+use client::{Influx, InfluxFuture};
+use influxql::TimeRange;
+use timestamp;
+use super::{parse, string, array, pages, PAGE_SIZE};
+
+/// When a bucket's data expires
+#[derive(Show, Clone, Copy, PartialEq)]
+pub struct RetentionRule {
+    /// How long data is kept
+    pub every: Duration,
+    /// The time range each shard group covers - None lets the server choose
+    /// from `every`
+    pub shard_group_duration: Option<Duration>
+}
+
+impl RetentionRule {
+    /// Keep data for `every`
+    pub fn expire(every: Duration) -> RetentionRule {
+        RetentionRule { every: every, shard_group_duration: None }
+    }
+
+    fn to_json(&self) -> Json {
+        let mut rule = BTreeMap::new();
+        rule.insert(String::from_str("type"), Json::String(String::from_str("expire")));
+        rule.insert(String::from_str("everySeconds"), Json::I64(self.every.num_seconds()));
+        if let Some(duration) = self.shard_group_duration {
+            rule.insert(String::from_str("shardGroupDurationSeconds"),
+                        Json::I64(duration.num_seconds()));
+        }
+        Json::Object(rule)
+    }
+
+    fn from_json(json: &Json) -> Result<RetentionRule, String> {
+        let seconds = |key: &str| json.find(key).and_then(|value| value.as_i64());
+        Ok(RetentionRule {
+            every: Duration::seconds(try!(seconds("everySeconds")
+                                          .ok_or(String::from_str("Rule has no everySeconds")))),
+            // 0 when the server chose
+            shard_group_duration: seconds("shardGroupDurationSeconds")
+                .and_then(|s| if s > 0 { Some(Duration::seconds(s)) } else { None })
+        })
+    }
+}
+
+/// A bucket
+#[derive(Show, Clone, PartialEq)]
+pub struct Bucket {
+    /// Assigned by the server
+    pub id: String,
+    pub name: String,
+    /// The id of the organization owning the bucket
+    pub org_id: String,
+    pub description: Option<String>,
+    /// When data expires - empty keeps it forever
+    pub retention_rules: Vec<RetentionRule>
+}
+
+impl Bucket {
+    /// The members of the bucket that can be set on creating or updating it
+    fn to_json(&self) -> Json {
+        let mut bucket = BTreeMap::new();
+        bucket.insert(String::from_str("name"), Json::String(self.name.clone()));
+        bucket.insert(String::from_str("orgID"), Json::String(self.org_id.clone()));
+        if let Some(ref description) = self.description {
+            bucket.insert(String::from_str("description"), Json::String(description.clone()));
+        }
+        bucket.insert(String::from_str("retentionRules"),
+                      Json::Array(self.retention_rules.iter().map(|r| r.to_json()).collect()));
+        Json::Object(bucket)
+    }
+
+    fn from_json(json: &Json) -> Result<Bucket, String> {
+        let mut rules = vec!();
+        for rule in array(json, "retentionRules").iter() {
+            rules.push(try!(RetentionRule::from_json(rule)));
+        }
+        Ok(Bucket {
+            id: try!(string(json, "id")),
+            name: try!(string(json, "name")),
+            org_id: try!(string(json, "orgID")),
+            description: string(json, "description").ok(),
+            retention_rules: rules
+        })
+    }
+}
+
+/// The buckets of a 2.x server
+#[derive(Clone)]
+pub struct Buckets<'a> {
+    influx: &'a Influx
+}
+
+impl<'a> Buckets<'a> {
+    pub fn new(influx: &'a Influx) -> Buckets<'a> {
+        Buckets { influx: influx }
+    }
+
+    /// List the buckets, of every organization the token can read or only
+    /// of the organization named `org`
+    ///
+    /// The buckets are fetched a page at a time.
+    pub fn list(&self, org: Option<&str>) -> InfluxFuture<Vec<Bucket>> {
+        let influx = self.influx.clone();
+        let org = org.map(|org| String::from_str(org));
+        pages(move |buckets: &[Bucket]| {
+            let mut query = vec!((String::from_str("limit"), PAGE_SIZE.to_string()),
+                                 (String::from_str("offset"), buckets.len().to_string()));
+            if let Some(ref org) = org {
+                query.push((String::from_str("org"), org.clone()));
+            }
+            influx.request_v2(Method::Get, &["buckets"], query, None, |body| {
+                let json = try!(parse(body.as_slice()));
+                array(&json, "buckets").iter().map(Bucket::from_json).collect()
+            })
+        }, vec!())
+    }
+
+    /// Create a bucket in the organization with id `org_id`
+    pub fn create(&self, org_id: &str, name: &str, description: Option<&str>,
+                  retention_rules: Vec<RetentionRule>) -> InfluxFuture<Bucket> {
+        let bucket = Bucket {
+            id: String::new(),
+            name: String::from_str(name),
+            org_id: String::from_str(org_id),
+            description: description.map(|d| String::from_str(d)),
+            retention_rules: retention_rules
+        };
+        self.influx.request_v2(Method::Post, &["buckets"], vec!(), Some(bucket.to_json()),
+                               |body| Bucket::from_json(&try!(parse(body.as_slice()))))
+    }
+
+    /// Change the name, description and retention rules of the bucket with
+    /// `bucket`'s id to match it
+    pub fn update(&self, bucket: &Bucket) -> InfluxFuture<Bucket> {
+        self.influx.request_v2(Method::Patch, &["buckets", bucket.id.as_slice()], vec!(),
+                               Some(bucket.to_json()),
+                               |body| Bucket::from_json(&try!(parse(body.as_slice()))))
+    }
+
+    /// Delete a bucket, and all its data
+    pub fn delete(&self, id: &str) -> InfluxFuture<()> {
+        self.influx.request_v2(Method::Delete, &["buckets", id], vec!(), None, |_| Ok(()))
+    }
+
+    /// Delete the points of `bucket` in `range` matching `predicate`, e.g.
+    /// `_measurement="cpu" AND host="a"` - an empty predicate matches every
+    /// point
+    ///
+    /// 2.x has no `DROP SERIES` or `DELETE` statements, this is their
+    /// replacement
+    pub fn delete_where(&self, bucket: &Bucket, range: TimeRange,
+                        predicate: &str) -> InfluxFuture<()> {
+        let (start, stop) = range.bounds(time::get_time());
+        let mut delete = BTreeMap::new();
+        delete.insert(String::from_str("start"),
+                      Json::String(timestamp::format_rfc3339(&start)));
+        delete.insert(String::from_str("stop"), Json::String(timestamp::format_rfc3339(&stop)));
+        if !predicate.is_empty() {
+            delete.insert(String::from_str("predicate"), Json::String(String::from_str(predicate)));
+        }
+        let query = vec!((String::from_str("orgID"), bucket.org_id.clone()),
+                         (String::from_str("bucketID"), bucket.id.clone()));
+        self.influx.request_v2(Method::Post, &["delete"], query, Some(Json::Object(delete)),
+                               |_| Ok(()))
+    }
+}
+
+#[test]
+fn reads_buckets() {
+    let json = parse(r#"{"buckets":[{"id":"0b1","orgID":"0a1","name":"metrics",
+        "retentionRules":[{"type":"expire","everySeconds":604800,
+                           "shardGroupDurationSeconds":0}]}]}"#).unwrap();
+    let buckets: Result<Vec<Bucket>, String> =
+        array(&json, "buckets").iter().map(Bucket::from_json).collect();
+    let bucket = Bucket {
+        id: String::from_str("0b1"),
+        name: String::from_str("metrics"),
+        org_id: String::from_str("0a1"),
+        description: None,
+        retention_rules: vec!(RetentionRule::expire(Duration::weeks(1)))
+    };
+    assert_eq!(buckets, Ok(vec!(bucket.clone())));
+    let rule = RetentionRule {
+        every: Duration::days(30),
+        shard_group_duration: Some(Duration::days(1))
+    };
+    assert_eq!(RetentionRule::from_json(&rule.to_json()), Ok(rule));
+}
+
+#[test]
+fn lists_buckets_a_page_at_a_time() {
+    use std::cmp;
+    use futures::Future;
+    use client::Auth;
+    use transport::{Canned, reply, stub_client};
+    // has 150 buckets, answering with those asked for
+    let transport = Canned::new(|sent| {
+        let param = |name: &str| sent.request.query.iter()
+            .find(|&&(ref param, _)| param.as_slice() == name)
+            .and_then(|&(_, ref value)| value.parse::<usize>().ok()).unwrap();
+        let (offset, limit) = (param("offset"), param("limit"));
+        let buckets: Vec<String> = (offset..cmp::min(offset + limit, 150)).map(|i| {
+            format!(r#"{{"id":"{}","orgID":"0a1","name":"b{}"}}"#, i, i)
+        }).collect();
+        reply(200, format!(r#"{{"buckets":[{}]}}"#, buckets.connect(",")).as_slice())
+    });
+    let sent = transport.sent();
+    let influx = stub_client(Auth::Token(String::from_str("token")), transport);
+    let buckets = Buckets::new(&influx).list(Some("acme")).wait().unwrap();
+    assert_eq!(buckets.len(), 150);
+    assert_eq!(buckets[149].name.as_slice(), "b149");
+    let param = |name: &str, value: &str| (String::from_str(name), String::from_str(value));
+    let queries: Vec<Vec<(String, String)>> = sent.lock().unwrap().iter()
+                                                  .map(|sent| sent.request.query.clone()).collect();
+    assert_eq!(queries,
+               vec!(vec!(param("limit", "100"), param("offset", "0"), param("org", "acme")),
+                    vec!(param("limit", "100"), param("offset", "100"), param("org", "acme"))));
+}
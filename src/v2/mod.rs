@@ -0,0 +1,53 @@
+//! Clients for the management APIs of InfluxDB 2.x, under `/api/v2`
+//!
+//! Each borrows an `Influx`, and authenticates with its token (or its
+//! username and password, sent as one).
+
+use futures::{future, Future};
+use rustc_serialize::json::Json;
+use client::InfluxFuture;
+
+pub use self::authorizations::{Authorizations, Authorization, Permission, Action};
+pub use self::buckets::{Buckets, Bucket, RetentionRule};
+pub use self::orgs::{Orgs, Org, Member};
+pub use self::tasks::{Tasks, Task, TaskStatus, Run, LogEvent};
+
+pub mod authorizations;
+pub mod buckets;
+pub mod orgs;
+pub mod tasks;
+
+/// Parse a response body as JSON
+fn parse(body: &str) -> Result<Json, String> {
+    Json::from_str(body).map_err(|e| format!("Invalid JSON response: {:?}", e))
+}
+
+/// The string member `key` of `json`
+fn string(json: &Json, key: &str) -> Result<String, String> {
+    json.find(key).and_then(|value| value.as_string())
+        .map(|value| String::from_str(value))
+        .ok_or(format!("Response has no {}", key))
+}
+
+/// The array member `key` of `json`, empty if there is none
+fn array<'a>(json: &'a Json, key: &str) -> &'a [Json] {
+    json.find(key).and_then(|value| value.as_array()).map_or(&[], |values| values.as_slice())
+}
+
+/// The number of items a listing asks for at a time
+static PAGE_SIZE: usize = 100;
+
+/// Fetch a listing a page at a time, `page` fetching the page after `items`
+/// (those fetched so far), until one comes back short
+fn pages<T, F>(page: F, items: Vec<T>) -> InfluxFuture<Vec<T>>
+    where T: Send + 'static,
+          F: Fn(&[T]) -> InfluxFuture<Vec<T>> + Send + 'static
+{
+    let next = page(items.as_slice());
+    Box::new(next.and_then(move |more| -> InfluxFuture<Vec<T>> {
+        let last = more.len() < PAGE_SIZE;
+        let mut items = items;
+        items.extend(more.into_iter());
+        if last { Box::new(future::ok(items)) } else { pages(page, items) }
+    }))
+}
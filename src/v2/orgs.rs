@@ -0,0 +1,181 @@
+//! Organizations, the 2.x unit of tenancy owning buckets, tasks and tokens
+
+use std::collections::BTreeMap;
+use hyper::method::Method;
+use rustc_serialize::json::Json;
+use client::{Influx, InfluxFuture};
+use super::{parse, string, array, pages, PAGE_SIZE};
+
+/// An organization
+#[derive(Show, Clone, PartialEq)]
+pub struct Org {
+    /// Assigned by the server
+    pub id: String,
+    pub name: String,
+    pub description: Option<String>
+}
+
+impl Org {
+    fn from_json(json: &Json) -> Result<Org, String> {
+        Ok(Org {
+            id: try!(string(json, "id")),
+            name: try!(string(json, "name")),
+            description: string(json, "description").ok()
+        })
+    }
+}
+
+/// A user who is a member or owner of an organization
+#[derive(Show, Clone, PartialEq)]
+pub struct Member {
+    /// The user's id
+    pub id: String,
+    pub name: String
+}
+
+impl Member {
+    fn from_json(json: &Json) -> Result<Member, String> {
+        Ok(Member {
+            id: try!(string(json, "id")),
+            name: try!(string(json, "name"))
+        })
+    }
+}
+
+/// The organizations of a 2.x server
+#[derive(Clone)]
+pub struct Orgs<'a> {
+    influx: &'a Influx
+}
+
+impl<'a> Orgs<'a> {
+    pub fn new(influx: &'a Influx) -> Orgs<'a> {
+        Orgs { influx: influx }
+    }
+
+    /// List the organizations the token can read
+    ///
+    /// The organizations are fetched a page at a time.
+    pub fn list(&self) -> InfluxFuture<Vec<Org>> {
+        let influx = self.influx.clone();
+        pages(move |orgs: &[Org]| {
+            let query = vec!((String::from_str("limit"), PAGE_SIZE.to_string()),
+                             (String::from_str("offset"), orgs.len().to_string()));
+            influx.request_v2(Method::Get, &["orgs"], query, None, |body| {
+                let json = try!(parse(body.as_slice()));
+                array(&json, "orgs").iter().map(Org::from_json).collect()
+            })
+        }, vec!())
+    }
+
+    /// Create an organization
+    pub fn create(&self, name: &str, description: Option<&str>) -> InfluxFuture<Org> {
+        let mut org = BTreeMap::new();
+        org.insert(String::from_str("name"), Json::String(String::from_str(name)));
+        if let Some(description) = description {
+            org.insert(String::from_str("description"),
+                       Json::String(String::from_str(description)));
+        }
+        self.influx.request_v2(Method::Post, &["orgs"], vec!(), Some(Json::Object(org)),
+                               |body| Org::from_json(&try!(parse(body.as_slice()))))
+    }
+
+    /// Delete an organization, and everything it owns
+    pub fn delete(&self, id: &str) -> InfluxFuture<()> {
+        self.influx.request_v2(Method::Delete, &["orgs", id], vec!(), None, |_| Ok(()))
+    }
+
+    /// List the members of an organization
+    pub fn members(&self, org_id: &str) -> InfluxFuture<Vec<Member>> {
+        self.users(org_id, "members")
+    }
+
+    /// Make a user a member of an organization
+    pub fn add_member(&self, org_id: &str, user_id: &str) -> InfluxFuture<()> {
+        self.add_user(org_id, "members", user_id)
+    }
+
+    /// Remove a user from the members of an organization
+    pub fn remove_member(&self, org_id: &str, user_id: &str) -> InfluxFuture<()> {
+        self.influx.request_v2(Method::Delete, &["orgs", org_id, "members", user_id], vec!(),
+                               None, |_| Ok(()))
+    }
+
+    /// List the owners of an organization
+    pub fn owners(&self, org_id: &str) -> InfluxFuture<Vec<Member>> {
+        self.users(org_id, "owners")
+    }
+
+    /// Make a user an owner of an organization
+    pub fn add_owner(&self, org_id: &str, user_id: &str) -> InfluxFuture<()> {
+        self.add_user(org_id, "owners", user_id)
+    }
+
+    /// Remove a user from the owners of an organization
+    pub fn remove_owner(&self, org_id: &str, user_id: &str) -> InfluxFuture<()> {
+        self.influx.request_v2(Method::Delete, &["orgs", org_id, "owners", user_id], vec!(),
+                               None, |_| Ok(()))
+    }
+
+    /// List the `members` or `owners` of an organization
+    fn users(&self, org_id: &str, role: &str) -> InfluxFuture<Vec<Member>> {
+        self.influx.request_v2(Method::Get, &["orgs", org_id, role], vec!(), None, |body| {
+            let json = try!(parse(body.as_slice()));
+            array(&json, "users").iter().map(Member::from_json).collect()
+        })
+    }
+
+    /// Add a user to the `members` or `owners` of an organization
+    fn add_user(&self, org_id: &str, role: &str, user_id: &str) -> InfluxFuture<()> {
+        let mut user = BTreeMap::new();
+        user.insert(String::from_str("id"), Json::String(String::from_str(user_id)));
+        self.influx.request_v2(Method::Post, &["orgs", org_id, role], vec!(),
+                               Some(Json::Object(user)), |_| Ok(()))
+    }
+}
+
+#[test]
+fn reads_orgs_and_members() {
+    let json = parse(r#"{"orgs":[{"id":"0a1","name":"acme","description":"Acme Inc"}]}"#);
+    let orgs: Result<Vec<Org>, String> =
+        array(&json.unwrap(), "orgs").iter().map(Org::from_json).collect();
+    assert_eq!(orgs, Ok(vec!(Org {
+        id: String::from_str("0a1"),
+        name: String::from_str("acme"),
+        description: Some(String::from_str("Acme Inc"))
+    })));
+    let json = parse(r#"{"users":[{"id":"0c1","name":"ops","role":"owner"}]}"#);
+    let members: Result<Vec<Member>, String> =
+        array(&json.unwrap(), "users").iter().map(Member::from_json).collect();
+    assert_eq!(members, Ok(vec!(Member {
+        id: String::from_str("0c1"),
+        name: String::from_str("ops")
+    })));
+}
+
+#[test]
+fn lists_orgs_a_page_at_a_time() {
+    use futures::Future;
+    use client::Auth;
+    use transport::{Canned, Request, reply, stub_client};
+    /// The offset a request asks for the organizations from
+    fn offset(request: &Request) -> String {
+        request.query.iter().find(|&&(ref param, _)| param.as_slice() == "offset")
+                            .map(|&(_, ref value)| value.clone()).unwrap()
+    }
+    // has 100 organizations, answering with those asked for
+    let transport = Canned::new(|sent| {
+        let orgs: Vec<String> = if offset(&sent.request).as_slice() == "0" {
+            (0..100).map(|i| format!(r#"{{"id":"{}","name":"org{}"}}"#, i, i)).collect()
+        } else {
+            vec!()
+        };
+        reply(200, format!(r#"{{"orgs":[{}]}}"#, orgs.connect(",")).as_slice())
+    });
+    let sent = transport.sent();
+    let influx = stub_client(Auth::Token(String::from_str("token")), transport);
+    assert_eq!(Orgs::new(&influx).list().wait().map(|orgs| orgs.len()), Ok(100));
+    let offsets: Vec<String> = sent.lock().unwrap().iter().map(|sent| offset(&sent.request))
+                                                       .collect();
+    assert_eq!(offsets, vec!(String::from_str("0"), String::from_str("100")));
+}
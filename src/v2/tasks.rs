@@ -0,0 +1,275 @@
+//! Flux tasks, the 2.x replacement for continuous queries
+//!
+//! A task's schedule and name are part of its script, as
+//! `option task = {name: "downsample", every: 1h}`.
+
+use std::collections::BTreeMap;
+use std::time::duration::Duration;
+use time::Timespec;
+use hyper::method::Method;
+use rustc_serialize::json::Json;
+use client::{Influx, InfluxFuture};
+use influxql;
+use timestamp;
+use super::{parse, string, array, pages, PAGE_SIZE};
+
+/// Whether a task is scheduled
+#[derive(Show, Clone, Copy, PartialEq)]
+pub enum TaskStatus {
+    Active,
+    Inactive
+}
+
+impl TaskStatus {
+    fn as_str(&self) -> &'static str {
+        match *self {
+            TaskStatus::Active => "active",
+            TaskStatus::Inactive => "inactive"
+        }
+    }
+}
+
+/// A task
+#[derive(Show, Clone, PartialEq)]
+pub struct Task {
+    /// Assigned by the server
+    pub id: String,
+    /// The id of the organization owning the task
+    pub org_id: String,
+    /// From the script's `task` option
+    pub name: String,
+    pub status: TaskStatus,
+    /// The Flux script run
+    pub flux: String,
+    /// How often the task runs, from the script's `task` option - None if it
+    /// runs on a `cron` schedule instead
+    pub every: Option<Duration>,
+    /// The cron schedule the task runs on, from the script's `task` option
+    pub cron: Option<String>,
+    pub description: Option<String>
+}
+
+impl Task {
+    fn from_json(json: &Json) -> Result<Task, String> {
+        let every = match string(json, "every").ok() {
+            Some(every) => Some(try!(influxql::parse_duration_literal(every.as_slice())
+                                     .ok_or(format!("Invalid task interval: {}", every)))),
+            None => None
+        };
+        Ok(Task {
+            id: try!(string(json, "id")),
+            org_id: try!(string(json, "orgID")),
+            name: try!(string(json, "name")),
+            status: match try!(string(json, "status")).as_slice() {
+                "active" => TaskStatus::Active,
+                "inactive" => TaskStatus::Inactive,
+                other => return Err(format!("Invalid task status: {}", other))
+            },
+            flux: try!(string(json, "flux")),
+            every: every,
+            cron: string(json, "cron").ok(),
+            description: string(json, "description").ok()
+        })
+    }
+}
+
+/// A run of a task
+#[derive(Show, Clone, PartialEq)]
+pub struct Run {
+    pub id: String,
+    pub task_id: String,
+    /// `scheduled`, `started`, `success`, `failed` or `canceled`
+    pub status: String,
+    /// The time the run covers up to
+    pub scheduled_for: Option<Timespec>,
+    pub started_at: Option<Timespec>,
+    pub finished_at: Option<Timespec>
+}
+
+impl Run {
+    fn from_json(json: &Json) -> Result<Run, String> {
+        Ok(Run {
+            id: try!(string(json, "id")),
+            task_id: try!(string(json, "taskID")),
+            status: try!(string(json, "status")),
+            scheduled_for: try!(time(json, "scheduledFor")),
+            started_at: try!(time(json, "startedAt")),
+            finished_at: try!(time(json, "finishedAt"))
+        })
+    }
+}
+
+/// A line of a task's log
+#[derive(Show, Clone, PartialEq)]
+pub struct LogEvent {
+    /// The run that logged the line
+    pub run_id: Option<String>,
+    pub time: Timespec,
+    pub message: String
+}
+
+impl LogEvent {
+    fn from_json(json: &Json) -> Result<LogEvent, String> {
+        Ok(LogEvent {
+            run_id: string(json, "runID").ok(),
+            time: try!(try!(time(json, "time")).ok_or(String::from_str("Log has no time"))),
+            message: try!(string(json, "message"))
+        })
+    }
+}
+
+/// The RFC3339 time member `key` of `json`, if it has one
+fn time(json: &Json, key: &str) -> Result<Option<Timespec>, String> {
+    match string(json, key).ok() {
+        Some(time) => timestamp::parse_rfc3339(time.as_slice()).map(Some),
+        None => Ok(None)
+    }
+}
+
+/// The tasks of a 2.x server
+#[derive(Clone)]
+pub struct Tasks<'a> {
+    influx: &'a Influx
+}
+
+impl<'a> Tasks<'a> {
+    pub fn new(influx: &'a Influx) -> Tasks<'a> {
+        Tasks { influx: influx }
+    }
+
+    /// List the tasks, of every organization the token can read or only of
+    /// the organization named `org`
+    ///
+    /// The tasks are fetched a page at a time, each after the last task of
+    /// the one before.
+    pub fn list(&self, org: Option<&str>) -> InfluxFuture<Vec<Task>> {
+        let influx = self.influx.clone();
+        let org = org.map(|org| String::from_str(org));
+        pages(move |tasks: &[Task]| {
+            let mut query = vec!((String::from_str("limit"), PAGE_SIZE.to_string()));
+            if let Some(ref org) = org {
+                query.push((String::from_str("org"), org.clone()));
+            }
+            if let Some(last) = tasks.last() {
+                query.push((String::from_str("after"), last.id.clone()));
+            }
+            influx.request_v2(Method::Get, &["tasks"], query, None, |body| {
+                let json = try!(parse(body.as_slice()));
+                array(&json, "tasks").iter().map(Task::from_json).collect()
+            })
+        }, vec!())
+    }
+
+    /// Create an active task in the organization with id `org_id`, running
+    /// `flux` on the schedule given by its `task` option
+    pub fn create(&self, org_id: &str, flux: &str,
+                  description: Option<&str>) -> InfluxFuture<Task> {
+        let mut task = BTreeMap::new();
+        task.insert(String::from_str("orgID"), Json::String(String::from_str(org_id)));
+        task.insert(String::from_str("flux"), Json::String(String::from_str(flux)));
+        task.insert(String::from_str("status"),
+                    Json::String(String::from_str(TaskStatus::Active.as_str())));
+        if let Some(description) = description {
+            task.insert(String::from_str("description"),
+                        Json::String(String::from_str(description)));
+        }
+        self.influx.request_v2(Method::Post, &["tasks"], vec!(), Some(Json::Object(task)),
+                               |body| Task::from_json(&try!(parse(body.as_slice()))))
+    }
+
+    /// Change the script, status and description of the task with `task`'s
+    /// id to match it
+    ///
+    /// The name and schedule follow the script's `task` option
+    pub fn update(&self, task: &Task) -> InfluxFuture<Task> {
+        let mut update = BTreeMap::new();
+        update.insert(String::from_str("flux"), Json::String(task.flux.clone()));
+        update.insert(String::from_str("status"),
+                      Json::String(String::from_str(task.status.as_str())));
+        if let Some(ref description) = task.description {
+            update.insert(String::from_str("description"), Json::String(description.clone()));
+        }
+        self.influx.request_v2(Method::Patch, &["tasks", task.id.as_slice()], vec!(),
+                               Some(Json::Object(update)),
+                               |body| Task::from_json(&try!(parse(body.as_slice()))))
+    }
+
+    /// Delete a task, and its runs and logs
+    pub fn delete(&self, id: &str) -> InfluxFuture<()> {
+        self.influx.request_v2(Method::Delete, &["tasks", id], vec!(), None, |_| Ok(()))
+    }
+
+    /// Run a task now, outside its schedule
+    pub fn run(&self, id: &str) -> InfluxFuture<Run> {
+        self.influx.request_v2(Method::Post, &["tasks", id, "runs"], vec!(),
+                               Some(Json::Object(BTreeMap::new())),
+                               |body| Run::from_json(&try!(parse(body.as_slice()))))
+    }
+
+    /// List the recent runs of a task
+    pub fn runs(&self, id: &str) -> InfluxFuture<Vec<Run>> {
+        self.influx.request_v2(Method::Get, &["tasks", id, "runs"], vec!(), None, |body| {
+            let json = try!(parse(body.as_slice()));
+            array(&json, "runs").iter().map(Run::from_json).collect()
+        })
+    }
+
+    /// The log of a task, over all its runs
+    pub fn logs(&self, id: &str) -> InfluxFuture<Vec<LogEvent>> {
+        self.influx.request_v2(Method::Get, &["tasks", id, "logs"], vec!(), None, |body| {
+            let json = try!(parse(body.as_slice()));
+            array(&json, "events").iter().map(LogEvent::from_json).collect()
+        })
+    }
+}
+
+#[test]
+fn reads_tasks_and_logs() {
+    let json = parse(r#"{"id":"0d1","orgID":"0a1","name":"downsample","status":"active",
+        "flux":"option task = {name: \"downsample\", every: 1h}\nfrom(bucket: \"metrics\")",
+        "every":"1h"}"#);
+    let task = Task::from_json(&json.unwrap()).unwrap();
+    assert_eq!(task.status, TaskStatus::Active);
+    assert_eq!(task.every, Some(Duration::hours(1)));
+    assert_eq!(task.cron, None);
+
+    let json = parse(r#"{"events":[{"runID":"0e1","time":"2020-01-01T00:00:00Z",
+        "message":"Completed(success)"}]}"#);
+    let logs: Result<Vec<LogEvent>, String> =
+        array(&json.unwrap(), "events").iter().map(LogEvent::from_json).collect();
+    assert_eq!(logs, Ok(vec!(LogEvent {
+        run_id: Some(String::from_str("0e1")),
+        time: Timespec::new(1577836800, 0),
+        message: String::from_str("Completed(success)")
+    })));
+}
+
+#[test]
+fn lists_tasks_after_the_last_of_each_page() {
+    use std::cmp;
+    use futures::Future;
+    use client::Auth;
+    use transport::{Canned, Request, reply, stub_client};
+    /// The task id a request asks for the tasks after
+    fn after(request: &Request) -> Option<String> {
+        request.query.iter().find(|&&(ref param, _)| param.as_slice() == "after")
+                            .map(|&(_, ref value)| value.clone())
+    }
+    // has 120 tasks, answering with those after the one asked for
+    let transport = Canned::new(|sent| {
+        let first = after(&sent.request).map_or(0, |id| id.parse::<usize>().unwrap() + 1);
+        let tasks: Vec<String> = (first..cmp::min(first + 100, 120)).map(|i| {
+            format!(concat!(r#"{{"id":"{}","orgID":"0a1","name":"t{}","status":"active","#,
+                            r#""flux":"from(bucket: \"b\")","every":"1h"}}"#), i, i)
+        }).collect();
+        reply(200, format!(r#"{{"tasks":[{}]}}"#, tasks.connect(",")).as_slice())
+    });
+    let sent = transport.sent();
+    let influx = stub_client(Auth::Token(String::from_str("token")), transport);
+    let tasks = Tasks::new(&influx).list(None).wait().unwrap();
+    assert_eq!(tasks.len(), 120);
+    assert_eq!(tasks[119].id.as_slice(), "119");
+    let cursors: Vec<Option<String>> = sent.lock().unwrap().iter()
+                                           .map(|sent| after(&sent.request)).collect();
+    assert_eq!(cursors, vec!(None, Some(String::from_str("99"))));
+}
@@ -0,0 +1,73 @@
+//! Blocking on several outstanding requests at once
+
+use std::io;
+use std::thread::Thread;
+use std::time::duration::Duration;
+use futures::{future, Future};
+use futures::future::Either;
+use futures::sync::oneshot;
+use client::RequestFuture;
+
+/// Wait for every request to complete, returning their outcomes in order
+///
+/// Unlike `futures::future::join_all`, a failed request doesn't stop the
+/// others being waited for.
+pub fn wait_all<T, E>(requests: Vec<RequestFuture<T, E>>) -> Vec<Result<T, E>>
+    where T: Send + 'static, E: Send + 'static {
+    let outcomes = requests.into_iter().map(|request| request.then(|r| Ok::<_, ()>(r)));
+    future::join_all(outcomes).wait().unwrap_or(vec!())
+}
+
+/// Wait for the first of the requests to complete, returning its index, its
+/// outcome and the requests still outstanding - None if there are no
+/// requests
+pub fn wait_any<T, E>(requests: Vec<RequestFuture<T, E>>)
+                      -> Option<(usize, Result<T, E>, Vec<RequestFuture<T, E>>)>
+    where T: Send + 'static, E: Send + 'static {
+    if requests.is_empty() {
+        return None;
+    }
+    Some(match future::select_all(requests).wait() {
+        Ok((value, index, rest)) => (index, Ok(value), rest),
+        Err((e, index, rest)) => (index, Err(e), rest)
+    })
+}
+
+/// Wait at most `timeout` for a request to complete - Ok(None) if it
+/// hasn't by then
+///
+/// The request isn't cancelled by timing out, only its outcome is
+/// discarded.
+pub fn wait_timeout<T, E>(request: RequestFuture<T, E>, timeout: Duration) -> Result<Option<T>, E>
+    where T: Send + 'static, E: Send + 'static {
+    let (expire, timer) = oneshot::channel();
+    Thread::spawn(move || {
+        io::timer::sleep(timeout);
+        let _ = expire.send(());
+    });
+    match request.select2(timer).wait() {
+        Ok(Either::A((value, _))) => Ok(Some(value)),
+        Err(Either::A((e, _))) => Err(e),
+        // the timer fired, or its thread died
+        Ok(Either::B(_)) | Err(Either::B(_)) => Ok(None)
+    }
+}
+
+#[test]
+fn waits_for_requests() {
+    let requests: Vec<RequestFuture<u32, String>> = vec!(
+        Box::new(future::ok(1)),
+        Box::new(future::err(String::from_str("failed"))),
+        Box::new(future::ok(3)));
+    assert_eq!(wait_all(requests),
+               vec!(Ok(1), Err(String::from_str("failed")), Ok(3)));
+
+    let (_pending, never) = oneshot::channel::<u32>();
+    let requests: Vec<RequestFuture<u32, String>> = vec!(
+        Box::new(never.map_err(|_| String::from_str("canceled"))),
+        Box::new(future::ok(2)));
+    let (index, result, rest) = wait_any(requests).unwrap();
+    assert_eq!((index, result, rest.len()), (1, Ok(2), 1));
+    assert_eq!(wait_timeout(rest.into_iter().next().unwrap(), Duration::milliseconds(10)),
+               Ok(None));
+}
@@ -0,0 +1,264 @@
+//! A fixed set of threads performing requests taken from a bounded queue,
+//! and a timer thread starting delayed work
+
+use std::cell::Cell;
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::{self, Thread};
+use std::thunk::Thunk;
+use std::time::duration::Duration;
+use time;
+use queue::{BoundedQueue, OverflowPolicy};
+
+thread_local!(static ON_WORKER: Cell<bool> = Cell::new(false));
+
+/// Whether the current thread is one of a pool's, so waiting for the pool
+/// to go idle would wait for itself
+pub fn on_worker_thread() -> bool {
+    ON_WORKER.with(|on_worker| on_worker.get())
+}
+
+/// The queue of a pool, and the count of jobs submitted but not finished
+struct Jobs {
+    queue: BoundedQueue<Thunk<'static>>,
+    outstanding: Mutex<usize>,
+    /// Signalled whenever the outstanding jobs drop to none
+    idle: Condvar
+}
+
+impl Jobs {
+    fn finished(&self) {
+        let mut outstanding = self.outstanding.lock().unwrap();
+        *outstanding -= 1;
+        if *outstanding == 0 {
+            self.idle.notify_all();
+        }
+    }
+}
+
+/// Threads taking jobs from a queue, so any number of outstanding requests
+/// share a fixed number of threads
+///
+/// Submitting to a full queue blocks until there is room. The threads stop
+/// once the pool is dropped and the queue drained.
+pub struct WorkerPool {
+    jobs: Arc<Jobs>
+}
+
+impl WorkerPool {
+    /// Start `threads` threads, with room for `queue_size` jobs waiting for
+    /// one
+    pub fn new(threads: usize, queue_size: usize) -> WorkerPool {
+        let jobs = Arc::new(Jobs {
+            queue: BoundedQueue::new(queue_size, OverflowPolicy::Block),
+            outstanding: Mutex::new(0),
+            idle: Condvar::new()
+        });
+        for _ in 0..threads {
+            Worker::start(jobs.clone());
+        }
+        WorkerPool { jobs: jobs }
+    }
+
+    /// Run `job` on one of the threads once it is free
+    pub fn execute<F>(&self, job: F) where F: FnOnce() + Send + 'static {
+        *self.jobs.outstanding.lock().unwrap() += 1;
+        if let Err(job) = self.jobs.queue.push(Thunk::new(job)) {
+            // Only a closed queue refuses jobs, and that only happens on drop
+            job.invoke(());
+            self.jobs.finished();
+        }
+    }
+
+    /// Number of jobs waiting for a thread
+    pub fn queued(&self) -> usize {
+        self.jobs.queue.len()
+    }
+
+    /// Wait up to `timeout` for every job submitted so far to finish -
+    /// true if they did
+    pub fn wait_idle(&self, timeout: Duration) -> bool {
+        let deadline = time::precise_time_ns() + timeout.num_nanoseconds().unwrap_or(0) as u64;
+        let mut outstanding = self.jobs.outstanding.lock().unwrap();
+        while *outstanding > 0 {
+            let now = time::precise_time_ns();
+            if now >= deadline {
+                return false;
+            }
+            let wait = Duration::nanoseconds((deadline - now) as i64);
+            outstanding = self.jobs.idle.wait_timeout(outstanding, wait).unwrap().0;
+        }
+        true
+    }
+}
+
+impl Drop for WorkerPool {
+    fn drop(&mut self) {
+        self.jobs.queue.close();
+    }
+}
+
+/// A thread of a pool, replaced by a new one if a job panics
+struct Worker {
+    jobs: Arc<Jobs>
+}
+
+impl Worker {
+    fn start(jobs: Arc<Jobs>) {
+        Thread::spawn(move || {
+            ON_WORKER.with(|on_worker| on_worker.set(true));
+            let worker = Worker { jobs: jobs };
+            while let Some(job) = worker.jobs.queue.pop() {
+                job.invoke(());
+                worker.jobs.finished();
+            }
+        });
+    }
+}
+
+impl Drop for Worker {
+    fn drop(&mut self) {
+        if thread::panicking() {
+            self.jobs.finished();
+            Worker::start(self.jobs.clone());
+        }
+    }
+}
+
+/// The jobs of a timer not yet due, latest first, and whether its thread
+/// is running or should stop
+struct Schedule {
+    due: Vec<(u64, Thunk<'static>)>,
+    started: bool,
+    stopped: bool
+}
+
+/// One thread running jobs once they are due, shared by everything waiting
+/// on a delay, instead of a thread each
+///
+/// Jobs run on the timer's thread, so should only hand work on, e.g. to a
+/// `WorkerPool`. The thread starts with the first job, and stops once the
+/// timer is dropped, dropping the jobs not yet due.
+pub struct Timer {
+    schedule: Arc<(Mutex<Schedule>, Condvar)>
+}
+
+impl Timer {
+    pub fn new() -> Timer {
+        Timer {
+            schedule: Arc::new((Mutex::new(Schedule {
+                due: vec!(),
+                started: false,
+                stopped: false
+            }), Condvar::new()))
+        }
+    }
+
+    /// Run `job` once `delay` has passed
+    pub fn schedule<F>(&self, delay: Duration, job: F) where F: FnOnce() + Send + 'static {
+        let at = time::precise_time_ns() + delay.num_nanoseconds().unwrap_or(0).max(0) as u64;
+        let &(ref lock, ref changed) = &*self.schedule;
+        let mut schedule = lock.lock().unwrap();
+        if !schedule.started {
+            schedule.started = true;
+            TimerThread::start(self.schedule.clone());
+        }
+        // after the jobs due later, and before those due at the same time
+        let pos = schedule.due.iter().position(|&(due, _)| due <= at)
+                                     .unwrap_or(schedule.due.len());
+        schedule.due.insert(pos, (at, Thunk::new(job)));
+        changed.notify_one();
+    }
+}
+
+impl Drop for Timer {
+    fn drop(&mut self) {
+        let &(ref lock, ref changed) = &*self.schedule;
+        let mut schedule = lock.lock().unwrap();
+        schedule.stopped = true;
+        schedule.due.clear();
+        changed.notify_one();
+    }
+}
+
+/// The thread of a timer, replaced by a new one if a job panics
+struct TimerThread {
+    shared: Arc<(Mutex<Schedule>, Condvar)>
+}
+
+impl TimerThread {
+    fn start(shared: Arc<(Mutex<Schedule>, Condvar)>) {
+        Thread::spawn(move || {
+            let thread = TimerThread { shared: shared };
+            thread.run();
+        });
+    }
+
+    fn run(&self) {
+        let &(ref lock, ref changed) = &*self.shared;
+        let mut schedule = lock.lock().unwrap();
+        while !schedule.stopped {
+            let now = time::precise_time_ns();
+            schedule = match schedule.due.last().map(|&(at, _)| at) {
+                Some(at) if at <= now => {
+                    let (_, job) = schedule.due.pop().unwrap();
+                    drop(schedule);
+                    job.invoke(());
+                    lock.lock().unwrap()
+                },
+                Some(at) => {
+                    let wait = Duration::nanoseconds((at - now) as i64);
+                    changed.wait_timeout(schedule, wait).unwrap().0
+                },
+                None => changed.wait(schedule).unwrap()
+            };
+        }
+    }
+}
+
+impl Drop for TimerThread {
+    fn drop(&mut self) {
+        if thread::panicking() {
+            TimerThread::start(self.shared.clone());
+        }
+    }
+}
+
+#[test]
+fn runs_jobs_on_a_fixed_set_of_threads() {
+    use std::sync::mpsc;
+    let pool = WorkerPool::new(2, 10);
+    let (sender, receiver) = mpsc::channel();
+    for i in 0..10u32 {
+        let sender = sender.clone();
+        pool.execute(move || { let _ = sender.send(i); });
+    }
+    let mut done: Vec<u32> = (0..10).map(|_| receiver.recv().unwrap()).collect();
+    done.sort();
+    assert_eq!(done, (0..10).collect::<Vec<u32>>());
+    assert!(pool.wait_idle(Duration::seconds(1)));
+}
+
+#[test]
+fn runs_jobs_once_due_in_order() {
+    use std::sync::mpsc;
+    let timer = Timer::new();
+    let (sender, receiver) = mpsc::channel();
+    for &(delay, i) in [(60i64, 3u32), (20, 1), (40, 2), (20, 4)].iter() {
+        let sender = sender.clone();
+        timer.schedule(Duration::milliseconds(delay), move || { let _ = sender.send(i); });
+    }
+    let start = time::precise_time_ns();
+    let fired: Vec<u32> = (0..4).map(|_| receiver.recv().unwrap()).collect();
+    assert_eq!(fired, vec!(1, 4, 2, 3));
+    assert!(time::precise_time_ns() - start >= 40_000_000);
+}
+
+#[test]
+fn keeps_running_jobs_after_one_panics() {
+    use std::sync::mpsc;
+    let timer = Timer::new();
+    let (sender, receiver) = mpsc::channel();
+    timer.schedule(Duration::milliseconds(0), || panic!("job failed"));
+    timer.schedule(Duration::milliseconds(20), move || { let _ = sender.send(()); });
+    assert_eq!(receiver.recv(), Ok(()));
+}